@@ -0,0 +1,119 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::cli::ArtCommand;
+use crate::config::ArtConfig;
+use crate::Result;
+use arcanio_lib::art::{embed_cover, front_cover, read_artwork, shrink, write_cover, Artwork};
+use arcanio_lib::files::{detect_filetype, glob_expand};
+use arcanio_lib::sidecar::find_directory_art;
+
+pub async fn handle_art(command: ArtCommand, config: &ArtConfig) -> Result<()> {
+    match command {
+        ArtCommand::Extract { paths, force } => handle_extract(paths, force, config).await,
+        ArtCommand::Embed { paths, image } => handle_embed(paths, image, config).await,
+    }
+}
+
+async fn handle_extract(paths: Vec<String>, force: bool, config: &ArtConfig) -> Result<()> {
+    let files = glob_expand(paths)?;
+
+    // Every track of an album usually carries the same cover, so one per directory
+    let mut extracted = HashSet::new();
+    for file in files {
+        let dir = directory(&file);
+        if extracted.contains(&dir) {
+            continue;
+        }
+
+        let filetype = match detect_filetype(&file) {
+            Ok(filetype) => filetype,
+            Err(e) => {
+                println!("{} ({})", file.display(), e);
+                continue;
+            }
+        };
+
+        let artwork = read_artwork(&file, &filetype)?;
+        let Some(cover) = front_cover(&artwork) else {
+            println!("{} (no embedded art)", file.display());
+            continue;
+        };
+
+        let cover = match fit(cover.clone(), config).await {
+            Ok(cover) => cover,
+            Err(e) => {
+                println!("{} ({})", file.display(), e);
+                continue;
+            }
+        };
+
+        match write_cover(&file, &cover, force)? {
+            Some(path) => println!("{} -> {}", file.display(), path.display()),
+            None => println!("{} (cover already exists, use --force to replace it)", file.display()),
+        }
+        extracted.insert(dir);
+    }
+
+    Ok(())
+}
+
+async fn handle_embed(paths: Vec<String>, image: Option<String>, config: &ArtConfig) -> Result<()> {
+    let files = glob_expand(paths)?;
+
+    // Load and shrink each image once, however many tracks it goes into
+    let mut covers: HashMap<PathBuf, Option<(PathBuf, Artwork)>> = HashMap::new();
+    for file in files {
+        let dir = directory(&file);
+        if !covers.contains_key(&dir) {
+            let image = match &image {
+                Some(image) => Some(PathBuf::from(image)),
+                None => find_directory_art(&dir)?,
+            };
+            let cover = match image {
+                Some(image) => match load(&image, config).await {
+                    Ok(cover) => Some((image, cover)),
+                    Err(e) => {
+                        println!("{} ({})", image.display(), e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            covers.insert(dir.clone(), cover);
+        }
+
+        let Some((image, cover)) = &covers[&dir] else {
+            println!("{} (no cover image found)", file.display());
+            continue;
+        };
+
+        let result = detect_filetype(&file).and_then(|filetype| embed_cover(&file, &filetype, cover));
+        match result {
+            Ok(()) => println!("{} (embedded {})", file.display(), image.display()),
+            Err(e) => println!("{} ({})", file.display(), e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Read an image and shrink it to the configured limits
+async fn load(image: &Path, config: &ArtConfig) -> Result<Artwork> {
+    fit(Artwork::from_file(image)?, config).await
+}
+
+/// Recompress art that is over the configured limits, leaving the rest untouched
+async fn fit(artwork: Artwork, config: &ArtConfig) -> Result<Artwork> {
+    if !artwork.exceeds(config.max_dimension, config.max_bytes()) {
+        return Ok(artwork);
+    }
+    Ok(shrink(Path::new(&config.ffmpeg_path), &artwork, config.max_dimension).await?)
+}
+
+fn directory(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
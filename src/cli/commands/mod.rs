@@ -1,3 +1,6 @@
 pub mod normalize;
+pub mod split;
+pub mod tag;
+pub mod art;
 pub mod config;
 pub mod temp;
@@ -1,56 +1,201 @@
+use std::path::Path;
+
+use crate::config::AppConfig;
 use crate::Result;
-use arcanio_lib::{files::{glob_expand, SupportedFiletype}};
-
-pub async fn handle_normalize(paths: Vec<String>) -> Result<()> {
-    // let files = glob_expand(paths)?;
-    // 
-    // for file in files {
-    //     match file.extension().and_then(|e| e.to_str()) {
-    //         Some("flac") => {
-    //             let mut cmd = SupportedFiletype::Flac.validation_command(&file)?;
-    //             match cmd.output().await {
-    //                 Ok(output) => {
-    //                     if output.status.success() {
-    //                         let codec = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    //                         if codec == "flac" {
-    //                             println!("{} (valid flac)", file.display());
-    //                         } else {
-    //                             println!("{} (invalid flac - actually {})", file.display(), codec);
-    //                         }
-    //                     } else {
-    //                         println!("{} (invalid flac)", file.display());
-    //                     }
-    //                 }
-    //                 Err(e) => {
-    //                     println!("{} (validation failed: {})", file.display(), e);
-    //                 }
-    //             }
-    //         }
-    //         Some("m4a") => {
-    //             let mut cmd = SupportedFiletype::M4a.validation_command(&file)?;
-    //             match cmd.output().await {
-    //                 Ok(output) => {
-    //                     if output.status.success() {
-    //                         let codec = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    //                         if codec == "aac" {
-    //                             println!("{} (valid m4a)", file.display());
-    //                         } else {
-    //                             println!("{} (invalid m4a - actually {})", file.display(), codec);
-    //                         }
-    //                     } else {
-    //                         println!("{} (invalid m4a)", file.display());
-    //                     }
-    //                 }
-    //                 Err(e) => {
-    //                     println!("{} (validation failed: {})", file.display(), e);
-    //                 }
-    //             }
-    //         }
-    //         _ => {
-    //             println!("{}", file.display());
-    //         }
-    //     }
-    // }
-    // 
+use arcanio_lib::audiobook::read_chapters;
+use arcanio_lib::metadata::ffprobe::{Ffprobe, Probe};
+use arcanio_lib::metadata::tags::NARRATOR;
+use arcanio_lib::metadata::{flac, mp4};
+use arcanio_lib::video::read_video_info;
+use arcanio_lib::files::{detect_codec, detect_filetype, detect_mediatype, glob_expand, AudioCodec, File, SupportedFiletype, SupportedMediaType};
+
+pub async fn handle_normalize(paths: Vec<String>, validate: bool, config: &AppConfig) -> Result<()> {
+    let files = glob_expand(paths)?;
+    let options = config.normalize_options();
+    let ffprobe = Ffprobe::new(&config.metadata.ffprobe_path);
+
+    for file in files {
+        let filetype = match detect_filetype(&file) {
+            Ok(filetype) => filetype,
+            Err(e) => {
+                println!("{} ({})", file.display(), e);
+                continue;
+            }
+        };
+
+        let codec = match detect_codec(&file, &filetype) {
+            Ok(codec) => codec,
+            Err(e) => {
+                println!("{} ({})", file.display(), e);
+                continue;
+            }
+        };
+        let quality = match codec {
+            Some(codec) if codec.is_lossless() => "lossless",
+            Some(_) => "lossy",
+            None => "unknown codec",
+        };
+
+        let mediatype = match detect_mediatype(&file, &filetype) {
+            Ok(mediatype) => mediatype,
+            Err(e) => {
+                println!("{} ({})", file.display(), e);
+                continue;
+            }
+        };
+        match codec {
+            Some(codec) => println!("{} ({:?} {:?}, {}, {:?})", file.display(), filetype, codec, quality, mediatype),
+            None => println!("{} ({:?}, {}, {:?})", file.display(), filetype, quality, mediatype),
+        }
+
+        if mediatype == SupportedMediaType::Audiobook {
+            let chapters = match read_chapters(&file, &filetype) {
+                Ok(chapters) => chapters,
+                Err(e) => {
+                    println!("{} ({})", file.display(), e);
+                    continue;
+                }
+            };
+            for chapter in chapters {
+                println!("    {:>8.1}s {}", chapter.start.as_secs_f64(), chapter.title);
+            }
+        }
+
+        if matches!(mediatype, SupportedMediaType::TVShow | SupportedMediaType::Movie) {
+            let info = match read_video_info(&file, &filetype) {
+                Ok(info) => info,
+                Err(e) => {
+                    println!("{} ({})", file.display(), e);
+                    continue;
+                }
+            };
+            if let Some(info) = info {
+                for stream in &info.video {
+                    println!("    {}x{} {:?}", stream.width, stream.height, stream.codec);
+                }
+                if let Some(duration) = info.duration {
+                    println!("    {:.1}s", duration.as_secs_f64());
+                }
+            }
+        }
+
+        if filetype == SupportedFiletype::Flac {
+            let metadata = match flac::read_metadata(&file) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    println!("{} ({})", file.display(), e);
+                    continue;
+                }
+            };
+            if let Some(metadata) = metadata {
+                let info = metadata.stream_info;
+                println!("    {} Hz, {} bit, {} ch", info.sample_rate, info.bits_per_sample, info.channels);
+            }
+        }
+
+        if matches!(filetype, SupportedFiletype::M4a | SupportedFiletype::M4b) {
+            let info = match mp4::read_audio_info(&file) {
+                Ok(info) => info,
+                Err(e) => {
+                    println!("{} ({})", file.display(), e);
+                    continue;
+                }
+            };
+            if let Some(info) = info {
+                println!("    {} Hz, {} bit, {} ch", info.sample_rate, info.bits_per_sample, info.channels);
+            }
+        }
+
+        let normalized = match File::try_new_with(file.clone(), &options) {
+            Ok(normalized) => normalized,
+            Err(e) => {
+                println!("{} ({})", file.display(), e);
+                continue;
+            }
+        };
+        if let Some(inference) = normalized.inference() {
+            println!("    inferred from {} ({:.0}% confident)", inference.pattern, inference.confidence * 100.0);
+        }
+        if let Some(narrator) = normalized.tags().custom(NARRATOR).filter(|_| mediatype == SupportedMediaType::Audiobook) {
+            println!("    narrated by {}", narrator);
+        }
+        println!("    -> {}", normalized.normalized_path().display());
+        for sidecar in normalized.sidecars() {
+            println!("       {} -> {}", sidecar.path().display(), sidecar.normalized_path().display());
+        }
+
+        // ffprobe can't validate ebooks
+        if mediatype == SupportedMediaType::Ebook {
+            continue;
+        }
+
+        if validate {
+            validate_codec(&ffprobe, &file, codec).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare the natively detected codec against what ffprobe reports
+async fn validate_codec(ffprobe: &Ffprobe, file: &Path, codec: Option<AudioCodec>) -> Result<()> {
+    let mut cmd = ffprobe.command(file)?;
+    match cmd.output().await {
+        Ok(output) => {
+            if output.status.success() {
+                let probe = Probe::parse(&output.stdout)?;
+                let actual = probe
+                    .streams_of("audio")
+                    .next()
+                    .and_then(|s| s.codec_name.clone())
+                    .unwrap_or_else(|| "no audio stream".to_string());
+                match codec {
+                    Some(codec) if codec.matches_ffprobe_name(&actual) => {
+                        println!("    valid {:?}", codec);
+                    }
+                    Some(codec) => println!("    invalid {:?} - actually {}", codec, actual),
+                    None => println!("    unknown codec - ffprobe reports {}", actual),
+                }
+            } else {
+                println!("    invalid (ffprobe could not read the file)");
+            }
+        }
+        Err(e) => {
+            println!("    validation failed: {}", e);
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A FLAC file with only a STREAMINFO block
+    fn flac(sample_rate: u32) -> Vec<u8> {
+        let mut streaminfo = vec![0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0];
+        // 20 bits of sample rate, 3 of channels - 1, 5 of bits per sample - 1
+        let packed = ((sample_rate as u64) << 44) | (1 << 41) | (15 << 36);
+        streaminfo.extend_from_slice(&packed.to_be_bytes());
+        streaminfo.extend_from_slice(&[0; 16]);
+
+        let mut bytes = b"fLaC".to_vec();
+        bytes.extend_from_slice(&[0x80, 0, 0, streaminfo.len() as u8]);
+        bytes.extend_from_slice(&streaminfo);
+        bytes
+    }
+
+    #[tokio::test]
+    async fn test_normalize_skips_corrupt_files() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("01 - Money.flac"), flac(44_100)).unwrap();
+        // A Vorbis comment block that claims far more bytes than the file has
+        fs::write(temp_dir.path().join("02 - Time.flac"), b"fLaC\x04\x00\x10\x00\x01").unwrap();
+        fs::write(temp_dir.path().join("03 - Us and Them.flac"), flac(48_000)).unwrap();
+
+        let glob = temp_dir.path().join("*.flac").display().to_string();
+        handle_normalize(vec![glob], false, &AppConfig::default()).await.unwrap();
+    }
+}
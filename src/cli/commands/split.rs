@@ -0,0 +1,50 @@
+use std::path::{Path, PathBuf};
+
+use crate::cli::normalize::handle_normalize;
+use crate::config::{AppConfig, Validate};
+use crate::{Error, Result};
+use arcanio_lib::files::detect_filetype;
+use arcanio_lib::metadata::cue::read_cue_sheet;
+use arcanio_lib::music::{cue_audio_file, find_cue_sheet, split_commands};
+
+pub async fn handle_split(path: String, output: Option<String>, config: &AppConfig) -> Result<()> {
+    let path = PathBuf::from(path);
+
+    // Either a cue sheet pointing at its image, or an image with a sidecar or embedded sheet
+    let is_cue = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("cue"));
+    let (audio, sheet) = if is_cue {
+        let sheet = read_cue_sheet(&path)?;
+        (cue_audio_file(&path, &sheet)?, sheet)
+    } else {
+        let filetype = detect_filetype(&path)?;
+        match find_cue_sheet(&path, &filetype)? {
+            Some(sheet) => (path, sheet),
+            None => {
+                println!("{} (no cue sheet found)", path.display());
+                return Ok(());
+            }
+        }
+    };
+
+    // Checked here rather than when the config loads, since only splitting needs ffmpeg
+    config.split.validate().map_err(|errors| Error::ConfigValidationError(errors.to_string()))?;
+
+    let filetype = detect_filetype(&audio)?;
+    let output_dir = match output {
+        Some(output) => PathBuf::from(output),
+        None => audio.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    std::fs::create_dir_all(&output_dir)?;
+
+    let mut tracks = Vec::new();
+    for (track, mut cmd) in split_commands(Path::new(&config.split.ffmpeg_path), &audio, &filetype, &sheet, &output_dir)? {
+        match cmd.status().await {
+            Ok(status) if status.success() => tracks.push(track.to_string_lossy().to_string()),
+            Ok(_) => println!("{} (ffmpeg failed)", track.display()),
+            Err(e) => println!("{} (split failed: {})", track.display(), e),
+        }
+    }
+
+    // Feed the new tracks straight into normalization
+    handle_normalize(tracks, false, config).await
+}
@@ -0,0 +1,52 @@
+use crate::cli::TagEdits;
+use crate::Result;
+use arcanio_lib::files::{detect_filetype, glob_expand};
+use arcanio_lib::metadata::tags::{MetadataReader, MetadataWriter};
+
+pub async fn handle_tag(paths: Vec<String>, edits: TagEdits) -> Result<()> {
+    let files = glob_expand(paths)?;
+
+    for file in files {
+        let filetype = match detect_filetype(&file) {
+            Ok(filetype) => filetype,
+            Err(e) => {
+                println!("{} ({})", file.display(), e);
+                continue;
+            }
+        };
+
+        let mut tags = match filetype.read_tags(&file) {
+            Ok(tags) => tags,
+            Err(e) => {
+                eprintln!("{} ({})", file.display(), e);
+                continue;
+            }
+        };
+        let value = |edit: &Option<String>| edit.as_ref().map(|v| Some(v.trim().to_string()).filter(|v| !v.is_empty()));
+        if let Some(title) = value(&edits.title) {
+            tags.title = title;
+        }
+        if let Some(artist) = value(&edits.artist) {
+            tags.artists = artist.into_iter().collect();
+        }
+        if let Some(album_artist) = value(&edits.album_artist) {
+            tags.album_artist = album_artist;
+        }
+        if let Some(album) = value(&edits.album) {
+            tags.album = album;
+        }
+        if let Some(date) = value(&edits.date) {
+            tags.date = date;
+        }
+        if let Some(genre) = value(&edits.genre) {
+            tags.genre = genre;
+        }
+
+        match filetype.write_tags(&file, &tags) {
+            Ok(()) => println!("{} (tagged)", file.display()),
+            Err(e) => println!("{} ({})", file.display(), e),
+        }
+    }
+
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use clap::Parser as _;
 
-use crate::{cli::{config::handle_config, normalize::handle_normalize, setup_logging, temp::handle_temp, Cli, Command}, config, Result};
+use crate::{cli::{art::handle_art, config::handle_config, normalize::handle_normalize, setup_logging, split::handle_split, tag::handle_tag, temp::handle_temp, Cli, Command}, config, Result};
 
 pub async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -10,7 +10,10 @@ pub async fn main() -> Result<()> {
     setup_logging(&config.logging)?;
 
     match cli.command {
-        Command::Normalize { paths } => { handle_normalize(paths).await? },
+        Command::Normalize { paths, validate } => { handle_normalize(paths, validate, &config).await? },
+        Command::Split { path, output } => { handle_split(path, output, &config).await? },
+        Command::Tag { paths, edits } => { handle_tag(paths, edits).await? },
+        Command::Art { command } => { handle_art(command, &config.art).await? },
         Command::Config { command } => { handle_config(command, &config).await? },
         Command::Temp => { handle_temp().await? },
     }
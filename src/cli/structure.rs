@@ -1,4 +1,5 @@
-use clap::{arg, command, Parser, Subcommand, ArgAction};
+#[allow(unused_imports)]
+use clap::{arg, command, Args, Parser, Subcommand, ArgAction};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -36,6 +37,37 @@ pub enum Command {
     Normalize {
         /// Files or directories to normalize. Supports wildcards.
         paths: Vec<String>,
+
+        /// Confirm the detected codec of each file with ffprobe
+        #[arg(long)]
+        validate: bool,
+    },
+
+    /// Split a single file album image into tracks using its cue sheet
+    #[command(arg_required_else_help=true)]
+    Split {
+        /// A cue sheet, or an image with a matching or embedded cue sheet
+        path: String,
+
+        /// Directory to write the tracks to, defaults to the image's directory
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Correct the tags of FLAC and MP4 files, then re-run normalize to move them
+    #[command(arg_required_else_help=true)]
+    Tag {
+        /// Files to tag. Supports wildcards.
+        paths: Vec<String>,
+
+        #[command(flatten)]
+        edits: TagEdits,
+    },
+
+    /// Extract, embed and shrink cover art
+    Art {
+        #[command(subcommand)]
+        command: ArtCommand,
     },
 
     /// Configuration management commands
@@ -49,6 +81,59 @@ pub enum Command {
     Temp,
 }
 
+/// Tag fields to change, an empty value removes the field
+#[derive(Args, Debug)]
+pub struct TagEdits {
+    /// Track title
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// Track artist, replacing every existing artist
+    #[arg(long)]
+    pub artist: Option<String>,
+
+    /// Album artist
+    #[arg(long)]
+    pub album_artist: Option<String>,
+
+    /// Album title
+    #[arg(long)]
+    pub album: Option<String>,
+
+    /// Release date, usually `YYYY` or `YYYY-MM-DD`
+    #[arg(long)]
+    pub date: Option<String>,
+
+    /// Genre
+    #[arg(long)]
+    pub genre: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ArtCommand {
+    /// Write the embedded front cover of each directory's tracks to `cover.jpg`
+    #[command(arg_required_else_help=true)]
+    Extract {
+        /// Files to extract art from. Supports wildcards.
+        paths: Vec<String>,
+
+        /// Replace an existing cover image
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Embed a directory image as the front cover of every FLAC and MP4 track
+    #[command(arg_required_else_help=true)]
+    Embed {
+        /// Files to embed art into. Supports wildcards.
+        paths: Vec<String>,
+
+        /// Image to embed, defaults to the `cover`, `folder` or `front` image next to each file
+        #[arg(long)]
+        image: Option<String>,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum ConfigCommand {
     /// Print the default configuration to stdout
@@ -39,7 +39,26 @@ impl ConfigBuilder {
                     .set_default("logging.file.enabled", defaults.logging.file.enabled).unwrap_or_else(|_| Config::builder())
                     .set_default("logging.file.level", defaults.logging.file.level).unwrap_or_else(|_| Config::builder())
                     .set_default("logging.file.path", defaults.logging.file.path).unwrap_or_else(|_| Config::builder())
-                    .set_default("logging.file.rotation", defaults.logging.file.rotation).unwrap_or_else(|_| Config::builder());
+                    .set_default("logging.file.rotation", defaults.logging.file.rotation).unwrap_or_else(|_| Config::builder())
+                    .set_default("metadata.backends", defaults.metadata.backends).unwrap_or_else(|_| Config::builder())
+                    .set_default("metadata.ffprobe_path", defaults.metadata.ffprobe_path).unwrap_or_else(|_| Config::builder())
+                    .set_default("metadata.path_patterns", defaults.metadata.path_patterns).unwrap_or_else(|_| Config::builder())
+                    .set_default("metadata.min_confidence", defaults.metadata.min_confidence as f64).unwrap_or_else(|_| Config::builder())
+                    .set_default("art.max_dimension", defaults.art.max_dimension).unwrap_or_else(|_| Config::builder())
+                    .set_default("art.max_size_kb", defaults.art.max_size_kb).unwrap_or_else(|_| Config::builder())
+                    .set_default("art.ffmpeg_path", defaults.art.ffmpeg_path).unwrap_or_else(|_| Config::builder())
+                    .set_default("templates.music", defaults.templates.music).unwrap_or_else(|_| Config::builder())
+                    .set_default("templates.audiobook", defaults.templates.audiobook).unwrap_or_else(|_| Config::builder())
+                    .set_default("templates.ebook", defaults.templates.ebook).unwrap_or_else(|_| Config::builder())
+                    .set_default("templates.tvshow", defaults.templates.tvshow).unwrap_or_else(|_| Config::builder())
+                    .set_default("templates.movie", defaults.templates.movie).unwrap_or_else(|_| Config::builder())
+                    .set_default("templates.disc_layout", defaults.templates.disc_layout).unwrap_or_else(|_| Config::builder())
+                    .set_default("sanitize.profile", defaults.sanitize.profile).unwrap_or_else(|_| Config::builder())
+                    .set_default("sanitize.unicode_form", defaults.sanitize.unicode_form).unwrap_or_else(|_| Config::builder())
+                    .set_default("sanitize.max_component_bytes", defaults.sanitize.max_component_bytes as u64).unwrap_or_else(|_| Config::builder())
+                    .set_default("compilations.various_artists", defaults.compilations.various_artists).unwrap_or_else(|_| Config::builder())
+                    .set_default("compilations.folder", defaults.compilations.folder).unwrap_or_else(|_| Config::builder())
+                    .set_default("split.ffmpeg_path", defaults.split.ffmpeg_path).unwrap_or_else(|_| Config::builder());
                 
                 self.config = builder.build().unwrap_or_default();
             }
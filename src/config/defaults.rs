@@ -1,8 +1,24 @@
+use arcanio_lib::files::{AlbumCache, NormalizeOptions};
+use arcanio_lib::metadata::ffprobe::Ffprobe;
+use arcanio_lib::metadata::tags::MetadataBackend;
+use arcanio_lib::music::{Compilations, DEFAULT_COMPILATION_FOLDER, DEFAULT_VARIOUS_ARTISTS};
+use arcanio_lib::pattern::PathPattern;
+use arcanio_lib::sanitize::{Profile, Sanitizer, UnicodeForm, DEFAULT_MAX_COMPONENT_BYTES};
+use arcanio_lib::template::{
+    DiscLayout, Template, Templates, DEFAULT_AUDIOBOOK_TEMPLATE, DEFAULT_EBOOK_TEMPLATE, DEFAULT_MOVIE_TEMPLATE,
+    DEFAULT_MUSIC_TEMPLATE, DEFAULT_TVSHOW_TEMPLATE,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub logging: LoggingConfig,
+    pub metadata: MetadataConfig,
+    pub art: ArtConfig,
+    pub templates: TemplatesConfig,
+    pub sanitize: SanitizeConfig,
+    pub compilations: CompilationsConfig,
+    pub split: SplitConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,14 +46,235 @@ pub struct FileLoggingConfig {
     pub rotation: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetadataConfig {
+    /// Backends to read tags with, in order: `native`, `ffprobe`
+    pub backends: Vec<String>,
+    /// The ffprobe to run, a program name looked up on PATH or a path to one
+    pub ffprobe_path: String,
+    /// Layouts to infer missing music tags from, e.g. `{artist} - {album}/{track} - {title}`
+    pub path_patterns: Vec<String>,
+    /// Inferences scoring below this, from 0 to 1, are ignored
+    pub min_confidence: f32,
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        // ffprobe is opt in, not every machine has ffmpeg installed
+        Self {
+            backends: vec!["native".to_string()],
+            ffprobe_path: "ffprobe".to_string(),
+            path_patterns: [
+                "{artist} - {album}/{track} - {title}",
+                "{artist}/{year} - {album}/{track}. {title}",
+                "{artist}/{year} - {album}/{track} - {title}",
+                "{artist}/{album}/{track} - {title}",
+                "{artist}/{album}/{track}. {title}",
+            ]
+            .map(String::from)
+            .to_vec(),
+            min_confidence: 0.5,
+        }
+    }
+}
+
+impl MetadataConfig {
+    /// The configured backends, in order, skipping any unknown names
+    pub fn metadata_backends(&self) -> Vec<MetadataBackend> {
+        self.backends
+            .iter()
+            .filter_map(|backend| match backend.as_str() {
+                "native" => Some(MetadataBackend::Native),
+                "ffprobe" => Some(MetadataBackend::Ffprobe(Ffprobe::new(&self.ffprobe_path))),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplatesConfig {
+    /// Path templates per media type, see the `template` module of the library for the syntax
+    pub music: String,
+    pub audiobook: String,
+    pub ebook: String,
+    pub tvshow: String,
+    pub movie: String,
+    /// How multi-disc releases are laid out: `prefix` for `2-05_Title`, `folder` for `Disc 2/05_Title`
+    pub disc_layout: String,
+}
+
+impl Default for TemplatesConfig {
+    fn default() -> Self {
+        Self {
+            music: DEFAULT_MUSIC_TEMPLATE.to_string(),
+            audiobook: DEFAULT_AUDIOBOOK_TEMPLATE.to_string(),
+            ebook: DEFAULT_EBOOK_TEMPLATE.to_string(),
+            tvshow: DEFAULT_TVSHOW_TEMPLATE.to_string(),
+            movie: DEFAULT_MOVIE_TEMPLATE.to_string(),
+            disc_layout: "prefix".to_string(),
+        }
+    }
+}
+
+impl TemplatesConfig {
+    /// The parsed templates, falling back to the default for any that don't parse
+    pub fn templates(&self) -> Templates {
+        let defaults = Templates::default();
+        let parse = |template: &str, default: Template| Template::parse(template).unwrap_or(default);
+        Templates {
+            music: parse(&self.music, defaults.music),
+            audiobook: parse(&self.audiobook, defaults.audiobook),
+            ebook: parse(&self.ebook, defaults.ebook),
+            tvshow: parse(&self.tvshow, defaults.tvshow),
+            movie: parse(&self.movie, defaults.movie),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SanitizeConfig {
+    /// Filesystems names must be valid on: `posix`, `windows-safe` or `ascii-only`
+    pub profile: String,
+    /// Unicode normalization of names: `nfc` or `nfd`
+    pub unicode_form: String,
+    /// Longest a single directory or file name may be, in bytes
+    pub max_component_bytes: usize,
+}
+
+impl Default for SanitizeConfig {
+    fn default() -> Self {
+        // SD cards and Samba shares are common targets, so be safe for them by default
+        Self {
+            profile: "windows-safe".to_string(),
+            unicode_form: "nfc".to_string(),
+            max_component_bytes: DEFAULT_MAX_COMPONENT_BYTES,
+        }
+    }
+}
+
+impl SanitizeConfig {
+    /// The configured sanitizer, with defaults for any unknown names
+    pub fn sanitizer(&self) -> Sanitizer {
+        let defaults = Sanitizer::default();
+        Sanitizer {
+            profile: Profile::from_name(&self.profile).unwrap_or(defaults.profile),
+            unicode_form: UnicodeForm::from_name(&self.unicode_form).unwrap_or(defaults.unicode_form),
+            max_component_bytes: self.max_component_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompilationsConfig {
+    /// Album artists that mark an album as a compilation, compared ignoring case
+    pub various_artists: Vec<String>,
+    /// The folder compilations are filed under instead of an artist folder
+    pub folder: String,
+}
+
+impl Default for CompilationsConfig {
+    fn default() -> Self {
+        Self {
+            various_artists: DEFAULT_VARIOUS_ARTISTS.iter().map(|name| name.to_string()).collect(),
+            folder: DEFAULT_COMPILATION_FOLDER.to_string(),
+        }
+    }
+}
+
+impl CompilationsConfig {
+    pub fn compilations(&self) -> Compilations {
+        Compilations {
+            various_artists: self.various_artists.clone(),
+            folder: self.folder.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SplitConfig {
+    /// The ffmpeg that cuts an album image into tracks
+    pub ffmpeg_path: String,
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        Self {
+            ffmpeg_path: "ffmpeg".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArtConfig {
+    /// Longest side in pixels cover art may have, 0 for no limit
+    pub max_dimension: u32,
+    /// Largest cover art file in KiB, 0 for no limit
+    pub max_size_kb: u64,
+    /// The ffmpeg that recompresses art over either limit
+    pub ffmpeg_path: String,
+}
+
+impl Default for ArtConfig {
+    fn default() -> Self {
+        Self {
+            max_dimension: 0,
+            max_size_kb: 0,
+            ffmpeg_path: "ffmpeg".to_string(),
+        }
+    }
+}
+
+impl ArtConfig {
+    /// Whether art is recompressed at all, which is when ffmpeg is needed
+    pub fn is_limited(&self) -> bool {
+        self.max_dimension > 0 || self.max_size_kb > 0
+    }
+
+    /// The size limit in bytes, 0 for no limit
+    pub fn max_bytes(&self) -> u64 {
+        self.max_size_kb * 1024
+    }
+}
+
+impl AppConfig {
+    /// How files are read and laid out, skipping any pattern that doesn't parse
+    pub fn normalize_options(&self) -> NormalizeOptions {
+        NormalizeOptions {
+            backends: self.metadata.metadata_backends(),
+            patterns: self.metadata.path_patterns.iter().filter_map(|p| PathPattern::parse(p).ok()).collect(),
+            min_confidence: self.metadata.min_confidence,
+            templates: self.templates.templates(),
+            disc_layout: DiscLayout::from_name(&self.templates.disc_layout).unwrap_or(DiscLayout::Prefix),
+            sanitizer: self.sanitize.sanitizer(),
+            compilations: self.compilations.compilations(),
+            albums: AlbumCache::default(),
+        }
+    }
+}
+
+#[allow(clippy::derivable_impls)]
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             logging: LoggingConfig::default(),
+            metadata: MetadataConfig::default(),
+            art: ArtConfig::default(),
+            templates: TemplatesConfig::default(),
+            sanitize: SanitizeConfig::default(),
+            compilations: CompilationsConfig::default(),
+            split: SplitConfig::default(),
         }
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for LoggingConfig {
     fn default() -> Self {
         Self {
@@ -81,9 +318,11 @@ mod tests {
     use crate::config::AppConfig;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_default_config() {
         let config = AppConfig::default();
         assert_eq!(config.logging.console.level, "off");
         assert_eq!(config.logging.file.enabled, false);
+        assert_eq!(config.metadata.backends, vec!["native"]);
     }
 }
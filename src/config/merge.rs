@@ -49,7 +49,7 @@
 //! ```
 
 use crate::cli::Cli;
-use crate::config::defaults::{AppConfig, LoggingConfig, ConsoleLoggingConfig, FileLoggingConfig};
+use crate::config::defaults::{AppConfig, LoggingConfig, ConsoleLoggingConfig, FileLoggingConfig, MetadataConfig, ArtConfig, TemplatesConfig, SanitizeConfig, CompilationsConfig, SplitConfig};
 
 /// A trait for merging configuration values from different sources
 pub trait Merge<T> {
@@ -102,11 +102,18 @@ macro_rules! impl_merge {
     };
 }
 
+#[allow(unused_imports)]
 pub(crate) use impl_merge;
 
 impl Merge<AppConfig> for AppConfig {
     fn merge_with(&mut self, other: AppConfig, defaults: &Self) {
         self.logging.merge_with(other.logging, &defaults.logging);
+        self.metadata.merge_with(other.metadata, &defaults.metadata);
+        self.art.merge_with(other.art, &defaults.art);
+        self.templates.merge_with(other.templates, &defaults.templates);
+        self.sanitize.merge_with(other.sanitize, &defaults.sanitize);
+        self.compilations.merge_with(other.compilations, &defaults.compilations);
+        self.split.merge_with(other.split, &defaults.split);
     }
 }
 
@@ -125,6 +132,30 @@ impl_merge!(FileLoggingConfig,
     enabled, level, path, rotation
 );
 
+impl_merge!(MetadataConfig,
+    backends, ffprobe_path, path_patterns, min_confidence
+);
+
+impl_merge!(TemplatesConfig,
+    music, audiobook, ebook, tvshow, movie, disc_layout
+);
+
+impl_merge!(SanitizeConfig,
+    profile, unicode_form, max_component_bytes
+);
+
+impl_merge!(CompilationsConfig,
+    various_artists, folder
+);
+
+impl_merge!(ArtConfig,
+    max_dimension, max_size_kb, ffmpeg_path
+);
+
+impl_merge!(SplitConfig,
+    ffmpeg_path
+);
+
 /// Convert CLI verbosity levels to log level strings
 fn verbosity_to_log_level(verbose: u8) -> String {
     match verbose {
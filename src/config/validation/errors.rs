@@ -204,6 +204,28 @@ mod tests {
         assert!(display.contains("Try using 'info' instead"));
     }
 
+    #[test]
+    fn test_validation_error_from_context() {
+        let context = ValidationContext::new("logging.file.rotation", "weekly")
+            .with_context("Rotation is applied by the file appender");
+        let error = ValidationError::from_context(context, "Invalid file rotation setting");
+
+        assert_eq!(error.field_path, "logging.file.rotation");
+        assert_eq!(error.current_value, "weekly");
+        assert_eq!(error.context.len(), 1);
+    }
+
+    #[test]
+    fn test_validation_errors_merge_and_summary() {
+        let mut errors = ValidationErrors::single(ValidationError::new("field1", "value1", "error1"));
+        errors.merge(ValidationErrors::single(ValidationError::new("field2", "value2", "error2")));
+
+        assert_eq!(errors.len(), 2);
+        let summary = errors.summary();
+        assert!(summary.contains("Found 2 validation error(s)"));
+        assert!(summary.contains("field2: error2"));
+    }
+
     #[test]
     fn test_errors_for_field() {
         let error1 = ValidationError::new("logging.level", "invalid", "error1");
@@ -1,7 +1,9 @@
-use crate::config::defaults::{AppConfig, LoggingConfig, ConsoleLoggingConfig, FileLoggingConfig};
+use crate::config::defaults::{AppConfig, LoggingConfig, ConsoleLoggingConfig, FileLoggingConfig, MetadataConfig, ArtConfig, TemplatesConfig, SanitizeConfig, CompilationsConfig, SplitConfig};
 use crate::config::validation::{
     Validate, ValidateField, ValidationResult, ValidationError, ValidationErrors,
     LogLevelValidator, LogFormatValidator, FileRotationValidator, FilePathValidator,
+    MetadataBackendsValidator, ExecutableValidator, PathPatternsValidator, ConfidenceValidator, TemplateValidator,
+    SanitizeProfileValidator, UnicodeFormValidator, ComponentLengthValidator, DiscLayoutValidator, FolderNameValidator,
     collect_validation_errors,
 };
 
@@ -9,7 +11,12 @@ impl Validate for AppConfig {
     fn validate(&self) -> ValidationResult<()> {
         // Validate nested config structs
         collect_validation_errors!(
-            self.logging.validate()
+            self.logging.validate(),
+            self.metadata.validate(),
+            self.art.validate(),
+            self.templates.validate(),
+            self.sanitize.validate(),
+            self.compilations.validate()
         )
     }
 }
@@ -50,6 +57,70 @@ impl Validate for FileLoggingConfig {
     }
 }
 
+impl Validate for MetadataConfig {
+    fn validate(&self) -> ValidationResult<()> {
+        // ffprobe only has to exist if a backend will run it
+        let ffprobe = if self.backends.iter().any(|backend| backend == "ffprobe") {
+            ExecutableValidator::validate_field(&self.ffprobe_path, "metadata.ffprobe_path")
+        } else {
+            Ok(())
+        };
+        collect_validation_errors!(
+            MetadataBackendsValidator::validate_field(&self.backends, "metadata.backends"),
+            ffprobe,
+            PathPatternsValidator::validate_field(&self.path_patterns, "metadata.path_patterns"),
+            ConfidenceValidator::validate_field(&self.min_confidence, "metadata.min_confidence")
+        )
+    }
+}
+
+impl Validate for TemplatesConfig {
+    fn validate(&self) -> ValidationResult<()> {
+        collect_validation_errors!(
+            TemplateValidator::validate_field(&self.music, "templates.music"),
+            TemplateValidator::validate_field(&self.audiobook, "templates.audiobook"),
+            TemplateValidator::validate_field(&self.ebook, "templates.ebook"),
+            TemplateValidator::validate_field(&self.tvshow, "templates.tvshow"),
+            TemplateValidator::validate_field(&self.movie, "templates.movie"),
+            DiscLayoutValidator::validate_field(&self.disc_layout, "templates.disc_layout")
+        )
+    }
+}
+
+impl Validate for SanitizeConfig {
+    fn validate(&self) -> ValidationResult<()> {
+        collect_validation_errors!(
+            SanitizeProfileValidator::validate_field(&self.profile, "sanitize.profile"),
+            UnicodeFormValidator::validate_field(&self.unicode_form, "sanitize.unicode_form"),
+            ComponentLengthValidator::validate_field(&self.max_component_bytes, "sanitize.max_component_bytes")
+        )
+    }
+}
+
+impl Validate for CompilationsConfig {
+    fn validate(&self) -> ValidationResult<()> {
+        FolderNameValidator::validate_field(&self.folder, "compilations.folder")
+    }
+}
+
+impl Validate for ArtConfig {
+    fn validate(&self) -> ValidationResult<()> {
+        // ffmpeg only runs when art has a limit to be recompressed to
+        if self.is_limited() {
+            ExecutableValidator::validate_field(&self.ffmpeg_path, "art.ffmpeg_path")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Not part of `AppConfig`'s validation, ffmpeg only has to exist once something is split
+impl Validate for SplitConfig {
+    fn validate(&self) -> ValidationResult<()> {
+        ExecutableValidator::validate_field(&self.ffmpeg_path, "split.ffmpeg_path")
+    }
+}
+
 /// Validate cross-field consistency rules for logging config
 fn validate_logging_consistency(config: &LoggingConfig) -> ValidationResult<()> {
     let mut errors = Vec::new();
@@ -172,6 +243,45 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_missing_ffprobe() {
+        let dir = tempdir().unwrap();
+        let mut config = AppConfig::default();
+        config.metadata.ffprobe_path = dir.path().join("ffprobe").to_string_lossy().to_string();
+
+        // Not checked while no backend uses it
+        assert!(config.validate().is_ok());
+
+        config.metadata.backends = vec!["native".to_string(), "ffprobe".to_string()];
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(!errors.errors_for_field("metadata.ffprobe_path").is_empty());
+    }
+
+    #[test]
+    fn test_missing_ffmpeg() {
+        let dir = tempdir().unwrap();
+        let mut config = AppConfig::default();
+        config.art.ffmpeg_path = dir.path().join("ffmpeg").to_string_lossy().to_string();
+        assert!(config.validate().is_ok());
+
+        config.art.max_dimension = 1000;
+        let errors = config.validate().unwrap_err();
+        assert!(!errors.errors_for_field("art.ffmpeg_path").is_empty());
+    }
+
+    #[test]
+    fn test_missing_split_ffmpeg() {
+        let dir = tempdir().unwrap();
+        let mut config = AppConfig::default();
+        config.split.ffmpeg_path = dir.path().join("ffmpeg").to_string_lossy().to_string();
+
+        // Only checked when something is split
+        assert!(config.validate().is_ok());
+        let errors = config.split.validate().unwrap_err();
+        assert!(!errors.errors_for_field("split.ffmpeg_path").is_empty());
+    }
+
     #[test]
     fn test_log_level_priority() {
         assert_eq!(log_level_priority("off"), 0);
@@ -1,5 +1,9 @@
-use std::path::Path;
+use std::env;
+use std::path::{Path, PathBuf};
 use std::fs;
+use arcanio_lib::pattern::{self, PathPattern};
+use arcanio_lib::sanitize::{Profile, UnicodeForm, DEFAULT_MAX_COMPONENT_BYTES};
+use arcanio_lib::template::{DiscLayout, Template};
 use crate::config::validation::{ValidationError, ValidationErrors, ValidationResult, ValidateField};
 
 /// Validator for log levels
@@ -101,9 +105,243 @@ impl ValidateField<String> for FilePathValidator {
     }
 }
 
+/// Validator for the ordered list of metadata backends
+pub struct MetadataBackendsValidator;
+
+impl ValidateField<Vec<String>> for MetadataBackendsValidator {
+    fn validate_field(value: &Vec<String>, field_name: &str) -> ValidationResult<()> {
+        const VALID_BACKENDS: &[&str] = &["native", "ffprobe"];
+
+        if value.is_empty() {
+            let error = ValidationError::new(field_name, "[]", "At least one metadata backend is required")
+                .with_context(format!("Valid backends are: {}", VALID_BACKENDS.join(", ")))
+                .with_suggestion("Try using [\"native\"] or [\"native\", \"ffprobe\"]");
+            return Err(ValidationErrors::single(error));
+        }
+
+        let mut errors = Vec::new();
+        for (i, backend) in value.iter().enumerate() {
+            if !VALID_BACKENDS.contains(&backend.as_str()) {
+                errors.push(
+                    ValidationError::new(field_name, backend, "Invalid metadata backend")
+                        .with_context(format!("Valid backends are: {}", VALID_BACKENDS.join(", ")))
+                        .with_suggestion(format!("Try using '{}' instead", suggest_closest_match(backend, VALID_BACKENDS)))
+                );
+            } else if value[..i].contains(backend) {
+                errors.push(
+                    ValidationError::new(field_name, backend, "Metadata backend listed more than once")
+                        .with_suggestion("Remove the repeated backend")
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors::new(errors))
+        }
+    }
+}
+
+/// Validator for external programs, which must exist or be found on PATH
+pub struct ExecutableValidator;
+
+impl ValidateField<String> for ExecutableValidator {
+    fn validate_field(value: &String, field_name: &str) -> ValidationResult<()> {
+        if value.is_empty() {
+            let error = ValidationError::new(field_name, value, "Program cannot be empty")
+                .with_suggestion("Provide a program name or a path to the program");
+            return Err(ValidationErrors::single(error));
+        }
+
+        if find_executable(value).is_some() {
+            return Ok(());
+        }
+
+        let error = if Path::new(value).is_file() {
+            ValidationError::new(field_name, value, "Program is not executable")
+                .with_suggestion("Make the file executable, e.g. with 'chmod +x'")
+        } else if Path::new(value).components().count() > 1 {
+            ValidationError::new(field_name, value, "Program does not exist")
+                .with_suggestion("Check the path, or use the program name to look it up on PATH")
+        } else {
+            ValidationError::new(field_name, value, "Program not found on PATH")
+                .with_context(format!("Searched PATH for '{}'", value))
+                .with_suggestion("Install the program, set the full path to it, or stop using the feature that needs it")
+        };
+        Err(ValidationErrors::single(error))
+    }
+}
+
+/// Validator for the path patterns tags are inferred from
+pub struct PathPatternsValidator;
+
+impl ValidateField<Vec<String>> for PathPatternsValidator {
+    fn validate_field(value: &Vec<String>, field_name: &str) -> ValidationResult<()> {
+        let errors: Vec<_> = value
+            .iter()
+            .filter_map(|pattern| PathPattern::parse(pattern).err().map(|e| (pattern, e)))
+            .map(|(pattern, e)| {
+                ValidationError::new(field_name, pattern, "Invalid path pattern")
+                    .with_context(e.to_string())
+                    .with_suggestion(format!("Use '/' between folders and placeholders from: {}", pattern::FIELDS.join(", ")))
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors::new(errors))
+        }
+    }
+}
+
+/// Validator for path templates
+pub struct TemplateValidator;
+
+impl ValidateField<String> for TemplateValidator {
+    fn validate_field(value: &String, field_name: &str) -> ValidationResult<()> {
+        match Template::parse(value) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let error = ValidationError::new(field_name, value, "Invalid path template")
+                    .with_context(e.to_string())
+                    .with_suggestion("Check the field names and that every '{' and '[' is closed");
+                Err(ValidationErrors::single(error))
+            }
+        }
+    }
+}
+
+/// Validator for the layout of multi-disc releases
+pub struct DiscLayoutValidator;
+
+impl ValidateField<String> for DiscLayoutValidator {
+    fn validate_field(value: &String, field_name: &str) -> ValidationResult<()> {
+        if DiscLayout::from_name(value).is_some() {
+            return Ok(());
+        }
+        let error = ValidationError::new(field_name, value, "Invalid disc layout")
+            .with_context(format!("Valid layouts are: {}", DiscLayout::NAMES.join(", ")))
+            .with_suggestion(format!("Try using '{}' instead", suggest_closest_match(value, DiscLayout::NAMES)));
+        Err(ValidationErrors::single(error))
+    }
+}
+
+/// Validator for a single folder name, which must not be blank or a path
+pub struct FolderNameValidator;
+
+impl ValidateField<String> for FolderNameValidator {
+    fn validate_field(value: &String, field_name: &str) -> ValidationResult<()> {
+        let error = if value.trim().is_empty() {
+            ValidationError::new(field_name, value, "Folder name is empty")
+                .with_suggestion("Try using 'Various Artists' or 'Compilations'")
+        } else if value.contains(['/', '\\']) {
+            ValidationError::new(field_name, value, "Folder name contains a path separator")
+                .with_context("This names one directory, nested directories aren't supported")
+                .with_suggestion(format!("Try using '{}' instead", value.replace(['/', '\\'], " ")))
+        } else {
+            return Ok(());
+        };
+        Err(ValidationErrors::single(error))
+    }
+}
+
+/// Validator for the sanitization profile
+pub struct SanitizeProfileValidator;
+
+impl ValidateField<String> for SanitizeProfileValidator {
+    fn validate_field(value: &String, field_name: &str) -> ValidationResult<()> {
+        if Profile::from_name(value).is_some() {
+            return Ok(());
+        }
+        let error = ValidationError::new(field_name, value, "Invalid sanitization profile")
+            .with_context(format!("Valid profiles are: {}", Profile::NAMES.join(", ")))
+            .with_suggestion(format!("Try using '{}' instead", suggest_closest_match(value, Profile::NAMES)));
+        Err(ValidationErrors::single(error))
+    }
+}
+
+/// Validator for Unicode normalization forms
+pub struct UnicodeFormValidator;
+
+impl ValidateField<String> for UnicodeFormValidator {
+    fn validate_field(value: &String, field_name: &str) -> ValidationResult<()> {
+        if UnicodeForm::from_name(value).is_some() {
+            return Ok(());
+        }
+        let error = ValidationError::new(field_name, value, "Invalid Unicode normalization form")
+            .with_context(format!("Valid forms are: {}", UnicodeForm::NAMES.join(", ")))
+            .with_suggestion(format!("Try using '{}' instead", suggest_closest_match(value, UnicodeForm::NAMES)));
+        Err(ValidationErrors::single(error))
+    }
+}
+
+/// Validator for the byte limit of a path component
+pub struct ComponentLengthValidator;
+
+impl ValidateField<usize> for ComponentLengthValidator {
+    fn validate_field(value: &usize, field_name: &str) -> ValidationResult<()> {
+        // Short enough limits leave no room for a name next to its extension
+        const MIN_BYTES: usize = 32;
+
+        if (MIN_BYTES..=DEFAULT_MAX_COMPONENT_BYTES).contains(value) {
+            return Ok(());
+        }
+        let error = ValidationError::new(field_name, value.to_string(), "Component length limit out of range")
+            .with_context(format!("Limits run from {} to {} bytes", MIN_BYTES, DEFAULT_MAX_COMPONENT_BYTES))
+            .with_suggestion(format!("Try using {}, the limit of most filesystems", DEFAULT_MAX_COMPONENT_BYTES));
+        Err(ValidationErrors::single(error))
+    }
+}
+
+/// Validator for confidence thresholds, which run from 0 to 1
+pub struct ConfidenceValidator;
+
+impl ValidateField<f32> for ConfidenceValidator {
+    fn validate_field(value: &f32, field_name: &str) -> ValidationResult<()> {
+        if (0.0..=1.0).contains(value) {
+            Ok(())
+        } else {
+            let error = ValidationError::new(field_name, value.to_string(), "Confidence must be between 0 and 1")
+                .with_context("0 accepts any match, 1 only unambiguous matches of anchored patterns")
+                .with_suggestion("Try using 0.5");
+            Err(ValidationErrors::single(error))
+        }
+    }
+}
+
+/// Resolve a program the way a shell would, paths are used as is and names are searched on PATH
+fn find_executable(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return is_executable(path).then(|| path.to_path_buf());
+    }
+
+    let extensions: &[&str] = if cfg!(windows) { &["", ".exe"] } else { &[""] };
+    env::split_paths(&env::var_os("PATH")?)
+        .flat_map(|dir| extensions.iter().map(move |ext| dir.join(format!("{}{}", program, ext))))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Whether a path is a file that can be run, which on unix means one of its execute bits is set
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
 /// Validator for directory paths
+#[cfg(test)]
 pub struct DirectoryPathValidator;
 
+#[cfg(test)]
 impl ValidateField<String> for DirectoryPathValidator {
     fn validate_field(value: &String, field_name: &str) -> ValidationResult<()> {
         if value.is_empty() {
@@ -155,6 +393,7 @@ fn suggest_closest_match<'a>(input: &str, valid_options: &'a [&str]) -> &'a str
 }
 
 /// Simple Levenshtein distance implementation for string similarity
+#[allow(clippy::needless_range_loop)]
 fn levenshtein_distance(s1: &str, s2: &str) -> usize {
     let s1_chars: Vec<char> = s1.chars().collect();
     let s2_chars: Vec<char> = s2.chars().collect();
@@ -253,6 +492,100 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_metadata_backends_validator() {
+        let backends = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert!(MetadataBackendsValidator::validate_field(&backends(&["native", "ffprobe"]), "metadata.backends").is_ok());
+        assert!(MetadataBackendsValidator::validate_field(&backends(&[]), "metadata.backends").is_err());
+        assert_eq!(MetadataBackendsValidator::validate_field(&backends(&["native", "native"]), "metadata.backends").unwrap_err().len(), 1);
+
+        let errors = MetadataBackendsValidator::validate_field(&backends(&["ffprob"]), "metadata.backends").unwrap_err();
+        assert!(errors.errors[0].message.contains("Invalid metadata backend"));
+    }
+
+    #[test]
+    fn test_executable_validator() {
+        let dir = tempdir().unwrap();
+        let program = dir.path().join("ffprobe");
+        fs::write(&program, "").unwrap();
+        let program = program.to_string_lossy().to_string();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let errors = ExecutableValidator::validate_field(&program, "metadata.ffprobe_path").unwrap_err();
+            assert!(errors.errors[0].message.contains("not executable"));
+            fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        assert!(ExecutableValidator::validate_field(&program, "metadata.ffprobe_path").is_ok());
+        assert!(ExecutableValidator::validate_field(&"".to_string(), "metadata.ffprobe_path").is_err());
+
+        let missing = dir.path().join("missing").to_string_lossy().to_string();
+        let errors = ExecutableValidator::validate_field(&missing, "metadata.ffprobe_path").unwrap_err();
+        assert!(errors.errors[0].message.contains("does not exist"));
+
+        let errors = ExecutableValidator::validate_field(&"arcanio-no-such-program".to_string(), "metadata.ffprobe_path").unwrap_err();
+        assert!(errors.errors[0].message.contains("not found on PATH"));
+    }
+
+    #[test]
+    fn test_path_patterns_validator() {
+        let patterns = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        assert!(PathPatternsValidator::validate_field(&patterns(&["{artist} - {album}/{track} - {title}"]), "metadata.path_patterns").is_ok());
+        assert!(PathPatternsValidator::validate_field(&patterns(&[]), "metadata.path_patterns").is_ok());
+
+        let errors = PathPatternsValidator::validate_field(&patterns(&["{artist}/{titel}", "{artist}//{title}"]), "metadata.path_patterns").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.errors[0].context[0].contains("unknown field '{titel}'"));
+    }
+
+    #[test]
+    fn test_template_validator() {
+        let template = |t: &str| t.to_string();
+        assert!(TemplateValidator::validate_field(&template("{albumartist}/{year} - {album}/{disc}-{track:02} {title}"), "templates.music").is_ok());
+        assert!(TemplateValidator::validate_field(&template("{artist}/[{year} - {album}"), "templates.music").is_err());
+
+        let errors = TemplateValidator::validate_field(&template("{artist}/{titel}"), "templates.music").unwrap_err();
+        assert!(errors.errors[0].context[0].contains("unknown field 'titel'"));
+    }
+
+    #[test]
+    fn test_disc_layout_validator() {
+        assert!(DiscLayoutValidator::validate_field(&"folder".to_string(), "templates.disc_layout").is_ok());
+        let errors = DiscLayoutValidator::validate_field(&"folders".to_string(), "templates.disc_layout").unwrap_err();
+        assert!(errors.errors[0].suggestions[0].contains("'folder'"));
+    }
+
+    #[test]
+    fn test_sanitize_validators() {
+        assert!(SanitizeProfileValidator::validate_field(&"windows-safe".to_string(), "sanitize.profile").is_ok());
+        let errors = SanitizeProfileValidator::validate_field(&"windows".to_string(), "sanitize.profile").unwrap_err();
+        assert!(errors.errors[0].suggestions[0].contains("windows-safe"));
+
+        assert!(UnicodeFormValidator::validate_field(&"nfd".to_string(), "sanitize.unicode_form").is_ok());
+        assert!(UnicodeFormValidator::validate_field(&"NFC".to_string(), "sanitize.unicode_form").is_err());
+
+        assert!(ComponentLengthValidator::validate_field(&255, "sanitize.max_component_bytes").is_ok());
+        assert!(ComponentLengthValidator::validate_field(&8, "sanitize.max_component_bytes").is_err());
+        assert!(ComponentLengthValidator::validate_field(&1024, "sanitize.max_component_bytes").is_err());
+    }
+
+    #[test]
+    fn test_folder_name_validator() {
+        assert!(FolderNameValidator::validate_field(&"Compilations".to_string(), "compilations.folder").is_ok());
+        assert!(FolderNameValidator::validate_field(&" ".to_string(), "compilations.folder").is_err());
+        assert!(FolderNameValidator::validate_field(&"Music/VA".to_string(), "compilations.folder").is_err());
+    }
+
+    #[test]
+    fn test_confidence_validator() {
+        assert!(ConfidenceValidator::validate_field(&0.5, "metadata.min_confidence").is_ok());
+        assert!(ConfidenceValidator::validate_field(&1.0, "metadata.min_confidence").is_ok());
+        assert!(ConfidenceValidator::validate_field(&1.5, "metadata.min_confidence").is_err());
+        assert!(ConfidenceValidator::validate_field(&-0.1, "metadata.min_confidence").is_err());
+    }
+
     #[test]
     fn test_levenshtein_distance() {
         assert_eq!(levenshtein_distance("info", "info"), 0);
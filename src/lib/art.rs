@@ -0,0 +1,368 @@
+//! Cover art embedded in audio files and kept next to them
+//!
+//! FLAC stores art in PICTURE blocks (or base64 `METADATA_BLOCK_PICTURE`
+//! comments in Ogg), MP4 in `covr` items and ID3 in `APIC` frames. Art can
+//! be written out as a directory image like `cover.jpg`, or a directory image
+//! embedded into every track. Oversized art is scaled down and recompressed
+//! to JPEG with ffmpeg, since many players choke on huge covers.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use base64::Engine as _;
+use tokio::io::AsyncWriteExt;
+
+use crate::files::SupportedFiletype;
+use crate::metadata::flac::{self, MetadataBlock, Picture};
+use crate::metadata::id3::{self, FrameContent, Id3v2Tag};
+use crate::metadata::mp4::{self, IlstValue};
+use crate::metadata::{ogg, riff};
+use crate::{Error, Result};
+
+/// The ID3 and FLAC picture type of a front cover
+pub const FRONT_COVER: u32 = 3;
+
+/// Name an extracted cover is written as, with the extension of its format
+pub const COVER_NAME: &str = "cover";
+
+/// A single embedded or directory image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artwork {
+    pub mime_type: String,
+    /// ID3/FLAC picture type, `FRONT_COVER` when the format doesn't record one
+    pub picture_type: u32,
+    pub data: Vec<u8>,
+}
+
+impl Artwork {
+    /// Load an image file as a front cover, its type decided by its contents
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let data = fs::read(path)?;
+        let mime_type = image_mime_type(&data).ok_or(Error::UnsupportedFiletype)?;
+        Ok(Self { mime_type: mime_type.to_string(), picture_type: FRONT_COVER, data })
+    }
+
+    /// File extension for the image, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self.mime_type.as_str() {
+            "image/png" => "png",
+            "image/webp" => "webp",
+            _ => "jpg",
+        }
+    }
+
+    /// Width and height in pixels, read from the JPEG or PNG header
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        image_dimensions(&self.data)
+    }
+
+    /// Check whether the image is larger than the given limits, 0 meaning no limit
+    ///
+    /// Images whose dimensions can't be read are only judged by size.
+    pub fn exceeds(&self, max_dimension: u32, max_bytes: u64) -> bool {
+        let too_wide = max_dimension > 0 && self.dimensions().is_some_and(|(w, h)| w.max(h) > max_dimension);
+        let too_big = max_bytes > 0 && self.data.len() as u64 > max_bytes;
+        too_wide || too_big
+    }
+
+    /// Convert an MP4 `covr` value, sniffing images stored with the generic data type
+    fn from_ilst(value: &IlstValue) -> Option<Self> {
+        let (mime_type, data) = match value {
+            IlstValue::Jpeg(data) => ("image/jpeg", data),
+            IlstValue::Png(data) => ("image/png", data),
+            IlstValue::Binary(data) => (image_mime_type(data)?, data),
+            _ => return None,
+        };
+        Some(Self { mime_type: mime_type.to_string(), picture_type: FRONT_COVER, data: data.clone() })
+    }
+}
+
+/// Read every picture embedded in a file, in stored order
+pub fn read_artwork(path: &Path, filetype: &SupportedFiletype) -> Result<Vec<Artwork>> {
+    let artwork = match filetype {
+        SupportedFiletype::Flac => flac::read_metadata(path)?
+            .map(|metadata| metadata.pictures.into_iter().map(Artwork::from).collect())
+            .unwrap_or_default(),
+        SupportedFiletype::Ogg | SupportedFiletype::Opus | SupportedFiletype::OggFlac => {
+            let Some((_, comments)) = ogg::read_comments(path)? else {
+                return Ok(Vec::new());
+            };
+            comments
+                .get_all("METADATA_BLOCK_PICTURE")
+                .into_iter()
+                .filter_map(|value| base64::engine::general_purpose::STANDARD.decode(value.trim()).ok())
+                .filter_map(|bytes| Picture::parse(&bytes).ok())
+                .map(Artwork::from)
+                .collect()
+        }
+        SupportedFiletype::M4a | SupportedFiletype::M4b | SupportedFiletype::Mp4 => mp4::read_tags(path)?
+            .map(|tags| tags.covers().into_iter().filter_map(Artwork::from_ilst).collect())
+            .unwrap_or_default(),
+        SupportedFiletype::Mp3 => id3::read_id3v2(path)?.map(|tag| id3_artwork(&tag)).unwrap_or_default(),
+        SupportedFiletype::Wav | SupportedFiletype::Aiff => riff::read_chunks(path)?
+            .and_then(|metadata| metadata.id3)
+            .map(|tag| id3_artwork(&tag))
+            .unwrap_or_default(),
+        SupportedFiletype::Epub | SupportedFiletype::Mkv | SupportedFiletype::Webm => Vec::new(),
+    };
+    Ok(artwork)
+}
+
+/// The front cover, or the first picture if none is marked as one
+pub fn front_cover(artwork: &[Artwork]) -> Option<&Artwork> {
+    artwork.iter().find(|art| art.picture_type == FRONT_COVER).or_else(|| artwork.first())
+}
+
+/// Path an extracted cover is written to, next to the media file
+pub fn cover_path(media: &Path, artwork: &Artwork) -> PathBuf {
+    media.with_file_name(format!("{}.{}", COVER_NAME, artwork.extension()))
+}
+
+/// Write a cover next to a media file, returning the written path
+///
+/// An existing image is only replaced when `force` is set, otherwise `None`
+/// is returned and nothing is written.
+pub fn write_cover(media: &Path, artwork: &Artwork, force: bool) -> Result<Option<PathBuf>> {
+    let target = cover_path(media, artwork);
+    if target.exists() && !force {
+        return Ok(None);
+    }
+    fs::write(&target, &artwork.data)?;
+    Ok(Some(target))
+}
+
+/// Embed an image as the front cover of a file, replacing any existing front cover
+///
+/// Other pictures, like a back cover or booklet scans, are kept. Only FLAC
+/// and MP4 can be written.
+pub fn embed_cover(path: &Path, filetype: &SupportedFiletype, artwork: &Artwork) -> Result<()> {
+    match filetype {
+        SupportedFiletype::Flac => {
+            let (width, height) = artwork.dimensions().unwrap_or_default();
+            let picture = Picture {
+                picture_type: FRONT_COVER,
+                mime_type: artwork.mime_type.clone(),
+                description: String::new(),
+                width,
+                height,
+                data: artwork.data.clone(),
+            };
+            flac::write_blocks(path, |blocks| {
+                blocks.retain(|b| {
+                    b.block_type != flac::PICTURE
+                        || !matches!(Picture::parse(&b.data), Ok(p) if p.picture_type == FRONT_COVER)
+                });
+                blocks.push(MetadataBlock { block_type: flac::PICTURE, data: picture.to_bytes() });
+            })
+        }
+        SupportedFiletype::M4a | SupportedFiletype::M4b | SupportedFiletype::Mp4 => {
+            // ilst has no picture types, so the cover replaces every covr image
+            let value = match artwork.mime_type.as_str() {
+                "image/jpeg" => IlstValue::Jpeg(artwork.data.clone()),
+                "image/png" => IlstValue::Png(artwork.data.clone()),
+                _ => return Err(Error::UnsupportedFiletype),
+            };
+            mp4::write_tags(path, &[("covr".to_string(), value)], &["covr"])
+        }
+        _ => Err(Error::UnsupportedFiletype),
+    }
+}
+
+/// Build the ffmpeg command that shrinks an image read from stdin, writing JPEG to stdout
+///
+/// The image is scaled to fit a `max_dimension` square keeping its aspect
+/// ratio, 0 keeps its size and only recompresses it. Smaller images are
+/// never scaled up.
+pub fn shrink_command(ffmpeg: &Path, max_dimension: u32) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new(ffmpeg);
+    cmd.args(["-v", "error", "-f", "image2pipe", "-i", "pipe:0", "-frames:v", "1"]);
+    if max_dimension > 0 {
+        cmd.arg("-vf").arg(format!(
+            "scale='min(iw,{0})':'min(ih,{0})':force_original_aspect_ratio=decrease",
+            max_dimension
+        ));
+    }
+    cmd.args(["-c:v", "mjpeg", "-q:v", "3", "-f", "image2pipe", "pipe:1"]);
+    cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    cmd
+}
+
+/// Recompress an image to JPEG with ffmpeg, see `shrink_command`
+pub async fn shrink(ffmpeg: &Path, artwork: &Artwork, max_dimension: u32) -> Result<Artwork> {
+    let mut child = shrink_command(ffmpeg, max_dimension).spawn()?;
+
+    // Feed stdin from its own task so a full stdout pipe can't stall the write
+    // ffmpeg may stop reading once it has a frame, a broken pipe then isn't an error
+    if let Some(mut stdin) = child.stdin.take() {
+        let data = artwork.data.clone();
+        tokio::spawn(async move { stdin.write_all(&data).await });
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() || output.stdout.is_empty() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(Error::InvalidMetadata(format!("ffmpeg could not recompress the image: {}", message)));
+    }
+    Ok(Artwork { mime_type: "image/jpeg".to_string(), picture_type: artwork.picture_type, data: output.stdout })
+}
+
+/// Identify JPEG, PNG and WebP images by their magic numbers
+pub fn image_mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// Width and height of a JPEG or PNG image
+///
+/// PNG keeps them in the `IHDR` chunk right after the signature, JPEG in the
+/// first start of frame marker, which can follow any number of other segments.
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    match image_mime_type(data)? {
+        "image/png" => {
+            let ihdr = data.get(12..24).filter(|chunk| &chunk[..4] == b"IHDR")?;
+            let width = u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]);
+            let height = u32::from_be_bytes([ihdr[8], ihdr[9], ihdr[10], ihdr[11]]);
+            Some((width, height))
+        }
+        "image/jpeg" => {
+            let mut pos = 2;
+            while pos + 4 <= data.len() {
+                if data[pos] != 0xFF {
+                    return None;
+                }
+                let marker = data[pos + 1];
+                // Fill bytes before a marker
+                if marker == 0xFF {
+                    pos += 1;
+                    continue;
+                }
+                let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+                // SOF0 to SOF15, except DHT, JPG and DAC which share the range
+                if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                    let frame = data.get(pos + 4..pos + 9)?;
+                    let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                    let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                    return Some((width, height));
+                }
+                pos += 2 + len;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+impl From<Picture> for Artwork {
+    fn from(picture: Picture) -> Self {
+        Self { mime_type: picture.mime_type, picture_type: picture.picture_type, data: picture.data }
+    }
+}
+
+/// The `APIC` frames of an ID3v2 tag
+fn id3_artwork(tag: &Id3v2Tag) -> Vec<Artwork> {
+    tag.frames
+        .iter()
+        .filter_map(|frame| match &frame.content {
+            FrameContent::Picture { mime_type, picture_type, data, .. } => {
+                // ID3v2.2 stores a three letter format instead of a MIME type
+                let mime_type = image_mime_type(data).map(str::to_string).unwrap_or_else(|| mime_type.clone());
+                Some(Artwork { mime_type, picture_type: *picture_type as u32, data: data.clone() })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A PNG signature and IHDR chunk, enough for `image_dimensions`
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0]);
+        data
+    }
+
+    /// A JPEG with an APP0 segment before its start of frame
+    fn jpeg(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x4A, 0x46, 0xFF, 0xC2, 0x00, 0x11, 0x08];
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&[3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1, 0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        assert_eq!(image_dimensions(&png(3000, 2000)), Some((3000, 2000)));
+        assert_eq!(image_dimensions(&jpeg(600, 500)), Some((600, 500)));
+        assert_eq!(image_dimensions(b"GIF89a"), None);
+
+        let art = Artwork { mime_type: "image/png".to_string(), picture_type: FRONT_COVER, data: png(3000, 2000) };
+        assert!(art.exceeds(1000, 0));
+        assert!(!art.exceeds(3000, 0));
+        assert!(art.exceeds(0, 10));
+        assert!(!art.exceeds(0, 0));
+    }
+
+    #[test]
+    fn test_front_cover() {
+        let art = |picture_type| Artwork { mime_type: "image/jpeg".to_string(), picture_type, data: Vec::new() };
+        assert_eq!(front_cover(&[art(4), art(FRONT_COVER)]).unwrap().picture_type, FRONT_COVER);
+        assert_eq!(front_cover(&[art(4), art(0)]).unwrap().picture_type, 4);
+        assert_eq!(front_cover(&[]), None);
+    }
+
+    #[test]
+    fn test_embed_and_write_flac_cover() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("01.flac");
+        let back = flac::picture(4, "image/jpeg", &jpeg(10, 10));
+        let front = flac::picture(FRONT_COVER, "image/jpeg", &jpeg(3000, 3000));
+        fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::PICTURE, back), (flac::PICTURE, front)])).unwrap();
+
+        let folder = temp_dir.path().join("folder.png");
+        fs::write(&folder, png(500, 500)).unwrap();
+        let cover = Artwork::from_file(&folder).unwrap();
+        embed_cover(&path, &SupportedFiletype::Flac, &cover).unwrap();
+
+        let artwork = read_artwork(&path, &SupportedFiletype::Flac).unwrap();
+        assert_eq!(artwork.len(), 2);
+        assert_eq!(artwork[0].picture_type, 4);
+        assert_eq!(front_cover(&artwork), Some(&cover));
+
+        let extracted = write_cover(&path, front_cover(&artwork).unwrap(), false).unwrap().unwrap();
+        assert_eq!(extracted, temp_dir.path().join("cover.png"));
+        assert_eq!(fs::read(&extracted).unwrap(), png(500, 500));
+        assert_eq!(write_cover(&path, &artwork[0], false).unwrap(), Some(temp_dir.path().join("cover.jpg")));
+        assert_eq!(write_cover(&path, &cover, false).unwrap(), None);
+        assert!(write_cover(&path, &cover, true).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_embed_unsupported_filetype() {
+        let art = Artwork { mime_type: "image/jpeg".to_string(), picture_type: FRONT_COVER, data: jpeg(1, 1) };
+        let result = embed_cover(Path::new("song.mp3"), &SupportedFiletype::Mp3, &art);
+        assert!(matches!(result, Err(Error::UnsupportedFiletype)));
+    }
+
+    #[test]
+    fn test_shrink_command() {
+        let cmd = shrink_command(Path::new("ffmpeg"), 1000);
+        let args: Vec<_> = cmd.as_std().get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert!(args.contains(&"scale='min(iw,1000)':'min(ih,1000)':force_original_aspect_ratio=decrease".to_string()));
+        assert_eq!(args.last().map(String::as_str), Some("pipe:1"));
+    }
+}
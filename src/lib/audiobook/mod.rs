@@ -0,0 +1,303 @@
+//! Audiobook detection and chapter handling
+//!
+//! Audiobooks come either as a single chaptered file (usually M4B) or as a
+//! folder of numbered parts. The parts on their own look exactly like music
+//! tracks, so they're recognized by their shared naming or an audiobook genre.
+//!
+//! Series, narrator and the like are often missing from the tags, so they
+//! are also taken from the book's folder name and the `desc.txt` and
+//! `reader.txt` files Audiobookshelf keeps next to a book.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::files::SupportedFiletype;
+use crate::metadata::tags::{Tags, DESCRIPTION, NARRATOR, SERIES_INDEX};
+use crate::metadata::{id3, mp4, Chapter};
+use crate::Result;
+
+/// The file next to a book holding its description
+pub const DESCRIPTION_FILE: &str = "desc.txt";
+/// The file next to a book holding its narrators, one per line
+pub const NARRATOR_FILE: &str = "reader.txt";
+
+/// Custom fields taggers keep the position in a series in, besides `tags::SERIES_INDEX`
+const SERIES_INDEX_ALIASES: &[&str] = &["SERIES-PART", "SERIESPART", "SERIES_PART"];
+
+/// Words a book folder may put before its position in the series, longest first
+const BOOK_NUMBER_PREFIXES: &[&str] = &["book", "volume", "vol.", "vol", "#"];
+
+/// Genres taggers use for spoken word books
+const AUDIOBOOK_GENRES: &[&str] = &["audiobook", "audiobooks", "audio book", "spoken word", "hörbuch", "speech"];
+
+/// Part names that are just as common for music rips, so they don't mark a book on their own
+const GENERIC_PART_NAMES: &[&str] = &["", "track", "audiotrack", "cd", "disc", "disk", "side"];
+
+/// Check whether a genre tag marks an audiobook
+pub fn is_audiobook_genre(genre: &str) -> bool {
+    AUDIOBOOK_GENRES.contains(&genre.trim().to_lowercase().as_str())
+}
+
+/// Read the chapters embedded in a file
+pub fn read_chapters(path: &Path, filetype: &SupportedFiletype) -> Result<Vec<Chapter>> {
+    match filetype {
+        SupportedFiletype::M4a | SupportedFiletype::M4b => mp4::read_chapters(path),
+        SupportedFiletype::Mp3 => Ok(id3::read_id3v2(path)?.map(|tag| tag.chapters()).unwrap_or_default()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Check whether a file is one part of a book
+///
+/// A file counts as a part if it carries an audiobook genre, or if it and
+/// its siblings form a numbered sequence that shares a common name.
+pub fn is_book_part(path: &Path) -> Result<bool> {
+    if let Some(genre) = id3::read_id3v2(path).ok().flatten().and_then(|tag| tag.genre()) {
+        if is_audiobook_genre(&genre) {
+            return Ok(true);
+        }
+    }
+
+    let Some(parent) = path.parent() else {
+        return Ok(false);
+    };
+    let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+
+    let mut siblings = Vec::new();
+    for entry in fs::read_dir(parent)? {
+        let sibling = entry?.path();
+        if sibling.is_file() && is_part_filetype(&sibling) {
+            siblings.push(sibling);
+        }
+    }
+
+    Ok(detect_book_parts(&siblings).is_some_and(|parts| parts.iter().any(|p| p.file_name() == path.file_name())))
+}
+
+/// Order a set of files as the parts of a single book, if they look like one
+///
+/// Every file must share the same name once its part number is removed, that
+/// name can't be a generic one like `Track`, and the numbers must run without gaps.
+pub fn detect_book_parts(files: &[PathBuf]) -> Option<Vec<PathBuf>> {
+    if files.len() < 2 {
+        return None;
+    }
+
+    let mut numbered: Vec<(u32, String, &PathBuf)> = files
+        .iter()
+        .map(|f| split_part_number(f.file_stem()?.to_str()?).map(|(base, n)| (n, base, f)))
+        .collect::<Option<_>>()?;
+    numbered.sort_by_key(|(n, _, _)| *n);
+
+    let base = &numbered[0].1;
+    if GENERIC_PART_NAMES.contains(&base.as_str()) || numbered.iter().any(|(_, b, _)| b != base) {
+        return None;
+    }
+
+    let first = numbered[0].0;
+    let contiguous = first <= 1 && numbered.iter().enumerate().all(|(i, (n, _, _))| *n == first + i as u32);
+    contiguous.then(|| numbered.into_iter().map(|(_, _, f)| f.clone()).collect())
+}
+
+/// Fill in what the tags of an audiobook leave out from wherever else book details are kept
+///
+/// Values already in the tags always win. The series position may also be
+/// under another custom field or in the folder name, like `Book 03 - Title`,
+/// which gives the book title too. The narrator comes from `reader.txt`, or
+/// else the composer where rippers of Audible books put it, and the
+/// description from `desc.txt`.
+pub fn fill_book_tags(path: &Path, tags: &mut Tags) -> Result<()> {
+    let alias = SERIES_INDEX_ALIASES.iter().find_map(|key| tags.custom(key)).map(str::to_string);
+    set_missing(tags, SERIES_INDEX, alias);
+
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let folder = dir.canonicalize()?;
+    if let Some((index, title)) = folder.file_name().and_then(|name| name.to_str()).and_then(parse_book_folder) {
+        set_missing(tags, SERIES_INDEX, Some(index));
+        if tags.album.is_none() {
+            tags.album = Some(title);
+        }
+    }
+
+    let narrators = read_text(&dir.join(NARRATOR_FILE))?
+        .map(|text| text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(", "));
+    set_missing(tags, NARRATOR, narrators);
+    let composer = tags.composer.clone();
+    set_missing(tags, NARRATOR, composer);
+    set_missing(tags, DESCRIPTION, read_text(&dir.join(DESCRIPTION_FILE))?);
+    Ok(())
+}
+
+/// Set a custom field unless it already has a value
+fn set_missing(tags: &mut Tags, key: &str, value: Option<String>) {
+    if let Some(value) = value.filter(|_| tags.custom(key).is_none()) {
+        tags.custom.push((key.to_string(), value));
+    }
+}
+
+/// Read a text file, trimmed, if it exists and isn't blank
+fn read_text(path: &Path) -> Result<Option<String>> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            let text = String::from_utf8_lossy(&bytes).trim().to_string();
+            Ok((!text.is_empty()).then_some(text))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Split a book folder name like `Book 03 - Title`, `Vol. 2.5 - Title` or
+/// `03_Title` into the position in the series and the title
+///
+/// Without a prefix word the number has to be followed by a `-`, `_` or
+/// `.`, so titles like `2001 A Space Odyssey` aren't taken for one.
+fn parse_book_folder(name: &str) -> Option<(String, String)> {
+    let lower = name.to_lowercase();
+    let prefix = BOOK_NUMBER_PREFIXES.iter().find(|prefix| lower.starts_with(*prefix)).map_or(0, |prefix| prefix.len());
+    let rest = name[prefix..].trim_start();
+
+    let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+    let number = rest[..number_len].trim_end_matches('.');
+    if !number.starts_with(|c: char| c.is_ascii_digit()) || number.parse::<f32>().is_err() {
+        return None;
+    }
+
+    let separator_len = rest[number.len()..].find(|c: char| !" -_.".contains(c)).unwrap_or(rest.len() - number.len());
+    let separator = &rest[number.len()..number.len() + separator_len];
+    let title = rest[number.len() + separator_len..].trim();
+    if title.is_empty() || (prefix == 0 && !separator.contains(['-', '_', '.'])) || (prefix > 0 && separator.is_empty()) {
+        return None;
+    }
+    Some((number.to_string(), title.to_string()))
+}
+
+/// Split a file stem into its normalized base name and its last number
+fn split_part_number(stem: &str) -> Option<(String, u32)> {
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end].rfind(|c: char| !c.is_ascii_digit()).map(|i| i + 1).unwrap_or(0);
+    let number = stem[start..end].parse().ok()?;
+
+    let base = format!("{}{}", &stem[..start], &stem[end..])
+        .to_lowercase()
+        .trim_matches(|c: char| c.is_whitespace() || "-_.#()[]".contains(c))
+        .to_string();
+    Some((base, number))
+}
+
+fn is_part_filetype(path: &Path) -> bool {
+    matches!(
+        SupportedFiletype::from_path(path),
+        Some(SupportedFiletype::Mp3 | SupportedFiletype::M4a | SupportedFiletype::M4b)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_split_part_number() {
+        assert_eq!(split_part_number("The Hobbit - Part 03"), Some(("the hobbit - part".to_string(), 3)));
+        assert_eq!(split_part_number("Chapter 12 (Final)"), Some(("chapter  (final".to_string(), 12)));
+        assert_eq!(split_part_number("Intro"), None);
+    }
+
+    #[test]
+    fn test_detect_book_parts_orders_parts() {
+        let parts = detect_book_parts(&paths(&["Dune 02.mp3", "Dune 01.mp3", "Dune 03.mp3"])).unwrap();
+        assert_eq!(parts, paths(&["Dune 01.mp3", "Dune 02.mp3", "Dune 03.mp3"]));
+    }
+
+    #[test]
+    fn test_music_tracks_are_not_book_parts() {
+        // Different titles per track
+        assert!(detect_book_parts(&paths(&["01 - Intro.mp3", "02 - Outro.mp3"])).is_none());
+        // Generic names from untagged CD rips
+        assert!(detect_book_parts(&paths(&["Track 01.mp3", "Track 02.mp3"])).is_none());
+        // Gaps in the numbering
+        assert!(detect_book_parts(&paths(&["Part 1.mp3", "Part 3.mp3"])).is_none());
+        assert!(detect_book_parts(&paths(&["Part 1.mp3"])).is_none());
+    }
+
+    #[test]
+    fn test_is_book_part_from_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["Part 1.mp3", "Part 2.mp3", "cover.jpg"] {
+            fs::write(temp_dir.path().join(name), "").unwrap();
+        }
+
+        assert!(is_book_part(&temp_dir.path().join("Part 1.mp3")).unwrap());
+    }
+
+    #[test]
+    fn test_is_book_part_from_genre() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("01 - Opening.mp3");
+        fs::write(&path, b"ID3\x04\x00\x00\x00\x00\x00\x14TCON\x00\x00\x00\x0A\x00\x00\x03Audiobook").unwrap();
+        fs::write(temp_dir.path().join("02 - Closing.mp3"), "").unwrap();
+
+        assert!(is_book_part(&path).unwrap());
+        assert!(!is_book_part(&temp_dir.path().join("02 - Closing.mp3")).unwrap());
+    }
+
+    #[test]
+    fn test_parse_book_folder() {
+        let parse = |name: &str| parse_book_folder(name).map(|(index, title)| format!("{}|{}", index, title));
+        assert_eq!(parse("Book 03 - Oathbringer").as_deref(), Some("03|Oathbringer"));
+        assert_eq!(parse("Vol. 2.5 - Edgedancer").as_deref(), Some("2.5|Edgedancer"));
+        assert_eq!(parse("#1 The Way of Kings").as_deref(), Some("1|The Way of Kings"));
+        assert_eq!(parse("04. Rhythm of War").as_deref(), Some("04|Rhythm of War"));
+        assert_eq!(parse("2001 A Space Odyssey"), None);
+        assert_eq!(parse("1984"), None);
+        assert_eq!(parse("Bookworm 3 - Title"), None);
+    }
+
+    #[test]
+    fn test_fill_book_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("Book 02 - Words of Radiance");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join(NARRATOR_FILE), "Michael Kramer\nKate Reading\n").unwrap();
+        fs::write(dir.join(DESCRIPTION_FILE), "  Six years ago...  ").unwrap();
+        let path = dir.join("book.m4b");
+
+        let mut tags = Tags {
+            composer: Some("Ripper".to_string()),
+            custom: vec![("SERIES".to_string(), "The Stormlight Archive".to_string())],
+            ..Default::default()
+        };
+        fill_book_tags(&path, &mut tags).unwrap();
+        assert_eq!(tags.custom(SERIES_INDEX), Some("02"));
+        assert_eq!(tags.album.as_deref(), Some("Words of Radiance"));
+        assert_eq!(tags.custom(NARRATOR), Some("Michael Kramer, Kate Reading"));
+        assert_eq!(tags.custom(DESCRIPTION), Some("Six years ago..."));
+
+        // Tags win over the folder, and the composer stands in for a missing reader.txt
+        fs::remove_file(dir.join(NARRATOR_FILE)).unwrap();
+        let mut tags = Tags {
+            composer: Some("Michael Kramer".to_string()),
+            custom: vec![("series-part".to_string(), "2.5".to_string())],
+            ..Default::default()
+        };
+        fill_book_tags(&path, &mut tags).unwrap();
+        assert_eq!(tags.custom(SERIES_INDEX), Some("2.5"));
+        assert_eq!(tags.custom(NARRATOR), Some("Michael Kramer"));
+    }
+
+    #[test]
+    fn test_audiobook_genres() {
+        assert!(is_audiobook_genre("Audiobook"));
+        assert!(is_audiobook_genre(" Spoken Word "));
+        assert!(!is_audiobook_genre("Rock"));
+    }
+}
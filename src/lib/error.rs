@@ -10,10 +10,34 @@ pub enum Error {
 
     #[error("invalid file path")]
     InvalidFilePath,
+
+    #[error("unsupported filetype")]
+    UnsupportedFiletype,
+
+    #[error("invalid metadata: {0}")]
+    InvalidMetadata(String),
+
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
     
     #[error("glob pattern error: {0}")]
     GlobPattern(#[from] glob::PatternError),
     
     #[error("glob error: {0}")]
     Glob(#[from] glob::GlobError),
+
+    #[error("zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("xml error: {0}")]
+    Xml(#[from] quick_xml::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("ffprobe error: {0}")]
+    Ffprobe(String),
 }
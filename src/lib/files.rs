@@ -1,85 +1,346 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
 use glob::glob;
 use strum::IntoEnumIterator;
-use crate::{Error, Result};
+use crate::metadata::ffprobe::Ffprobe;
+use crate::metadata::mp4;
+use crate::metadata::tags::{self, MetadataBackend, MetadataReader, Tags};
+use crate::music::{self, Compilations};
+use crate::pattern::{self, Inference, PathPattern};
+use crate::sanitize::Sanitizer;
+use crate::sidecar::{self, Sidecar};
+use crate::template::{DiscLayout, Templates};
+use crate::{audiobook, sniff, video, Error, Result};
 
 pub struct File {
     filetype: SupportedFiletype,
     mediatype: SupportedMediaType,
+    codec: Option<AudioCodec>,
+    tags: Tags,
+    inference: Option<Inference>,
     normalized_path: PathBuf,
+    sidecars: Vec<Sidecar>,
+}
+
+/// How a `File` reads its tags
+#[derive(Debug, Clone)]
+pub struct NormalizeOptions {
+    /// Backends to read tags with, in order
+    pub backends: Vec<MetadataBackend>,
+    /// Patterns to infer missing music and audiobook tags from the path with
+    pub patterns: Vec<PathPattern>,
+    /// Inferences less confident than this are ignored
+    pub min_confidence: f32,
+    /// How the normalized path of each media type is laid out
+    pub templates: Templates,
+    /// Whether discs of a multi-disc release get a track prefix or their own folder
+    pub disc_layout: DiscLayout,
+    /// How tag text is made safe to use in the normalized path
+    pub sanitizer: Sanitizer,
+    /// How compilations are recognised and where they are filed
+    pub compilations: Compilations,
+    /// Tags of the tracks already read for each album directory
+    pub albums: AlbumCache,
+}
+
+/// Tags of the music files in each album directory, shared by every clone
+///
+/// Deciding whether an album is a compilation needs the tags of all of its
+/// tracks, so they're read once for the first track and reused for the rest.
+#[derive(Debug, Clone, Default)]
+pub struct AlbumCache(Arc<Mutex<HashMap<PathBuf, Vec<AlbumTrack>>>>);
+
+/// A track and its tags, as read for `AlbumCache`
+type AlbumTrack = (PathBuf, Tags);
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        Self {
+            backends: vec![MetadataBackend::Native],
+            patterns: Vec::new(),
+            min_confidence: 0.5,
+            templates: Templates::default(),
+            disc_layout: DiscLayout::Prefix,
+            sanitizer: Sanitizer::default(),
+            compilations: Compilations::default(),
+            albums: AlbumCache::default(),
+        }
+    }
 }
 
 impl File {
     pub fn try_new(path: PathBuf) -> Result<Self> {
+        Self::try_new_with(path, &NormalizeOptions::default())
+    }
+
+    /// Like `try_new`, reading and inferring tags as `options` says
+    ///
+    /// Tags always win over the path, an inference only fills the fields
+    /// the file has no tags for.
+    pub fn try_new_with(path: PathBuf, options: &NormalizeOptions) -> Result<Self> {
         let ft = detect_filetype(&path)?;
+        let mediatype = detect_mediatype(&path, &ft)?;
+        let mut tags = options.backends.read_tags(&path)?;
+
+        // Patterns and album siblings need the folders above the file, which a relative path may not have
+        let absolute = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let inference = match mediatype {
+            SupportedMediaType::Music | SupportedMediaType::Audiobook => infer(&absolute, options),
+            _ => None,
+        };
+        if let Some(inference) = &inference {
+            tags.fill_missing(&inference.tags);
+        }
+        if mediatype == SupportedMediaType::Audiobook {
+            audiobook::fill_book_tags(&absolute, &mut tags)?;
+        }
+
+        let release_artist = match mediatype {
+            SupportedMediaType::Music => release_artist(&absolute, &tags, options)?,
+            _ => tags.primary_artist().map(str::to_string),
+        };
+        let normalized_path = normalize_path(&path, &ft, &mediatype, &tags, release_artist.as_deref(), options)?;
         Ok(Self { 
             filetype: ft.clone(),
-            mediatype: SupportedMediaType::from(ft), 
-            normalized_path: normalize_path(&path)?,
+            mediatype,
+            codec: detect_codec(&path, &ft)?,
+            tags,
+            inference,
+            sidecars: sidecar::find_sidecars(&path, &normalized_path)?,
+            normalized_path,
         })
     }
+
+    pub fn filetype(&self) -> &SupportedFiletype {
+        &self.filetype
+    }
+
+    pub fn codec(&self) -> Option<&AudioCodec> {
+        self.codec.as_ref()
+    }
+
+    pub fn mediatype(&self) -> &SupportedMediaType {
+        &self.mediatype
+    }
+
+    /// Tags read from the file, in the same shape whatever the format
+    pub fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    /// Tags inferred from the path, if a pattern matched confidently enough
+    pub fn inference(&self) -> Option<&Inference> {
+        self.inference.as_ref()
+    }
+
+    pub fn normalized_path(&self) -> &Path {
+        &self.normalized_path
+    }
+
+    /// Subtitles, art and other files that move along with this one
+    pub fn sidecars(&self) -> &[Sidecar] {
+        &self.sidecars
+    }
 }
 
-fn normalize_path(path: &Path) -> Result<PathBuf>{
-    let mut normalized_path = PathBuf::new();
+/// Infer tags from the path of a file, if a pattern matches confidently enough
+fn infer(path: &Path, options: &NormalizeOptions) -> Option<Inference> {
+    pattern::infer_tags(path, &options.patterns).filter(|i| i.confidence >= options.min_confidence)
+}
 
-    if let Some(super_group) = detect_super_group(path) {
-        normalized_path.push(super_group);
+/// The artist the album of a music file is filed under, see `Compilations::release_artist`
+fn release_artist(path: &Path, tags: &Tags, options: &NormalizeOptions) -> Result<Option<String>> {
+    let mut album = vec![tags.clone()];
+    if tags.album.is_some() {
+        let dir = path.parent().ok_or(Error::InvalidFilePath)?;
+        let mut cache = options.albums.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if !cache.contains_key(dir) {
+            cache.insert(dir.to_path_buf(), album_tracks(path, options)?);
+        }
+        let others = cache[dir].iter().filter(|(track, other)| track != path && other.album == tags.album);
+        album.extend(others.map(|(_, other)| other.clone()));
     }
+    Ok(options.compilations.release_artist(&album))
+}
 
-    if let Some(sub_group) = detect_sub_group(path) {
-        normalized_path.push(sub_group);
+/// The tags of every track that may be on the same album as `path`
+///
+/// They're read with the native backend only, running ffprobe over the
+/// whole album would take far too long. Tracks that can't be read are left
+/// out.
+fn album_tracks(path: &Path, options: &NormalizeOptions) -> Result<Vec<AlbumTrack>> {
+    let mut tracks = Vec::new();
+    for candidate in music::album_candidates(path)? {
+        let Ok(mut tags) = MetadataBackend::Native.read_tags(&candidate) else {
+            continue;
+        };
+        if let Some(inference) = infer(&candidate, options) {
+            tags.fill_missing(&inference.tags);
+        }
+        tracks.push((candidate, tags));
     }
+    Ok(tracks)
+}
 
-    let mut filename = String::new();
-    filename.push_str(detect_name_prefix(path).unwrap_or(""));
-    filename.push_str("_");
-    filename.push_str(detect_name(path)?);
-    filename.push_str(&detect_extension(path)?);
-    normalized_path.push(filename);
+/// Lay out the normalized path of a file with the template for its media type
+///
+/// Every component is sanitized on its own, so separators in tag text can't
+/// add directories.
+fn normalize_path(
+    path: &Path,
+    filetype: &SupportedFiletype,
+    mediatype: &SupportedMediaType,
+    tags: &Tags,
+    release_artist: Option<&str>,
+    options: &NormalizeOptions,
+) -> Result<PathBuf> {
+    let mut components = options
+        .templates
+        .get(mediatype)
+        .render(|name| match name {
+            "release_artist" => release_artist.map(str::to_string),
+            _ => template_field(name, path, mediatype, tags, options),
+        });
+    let stem = components.pop().ok_or(Error::InvalidFilePath)?;
 
+    let mut normalized_path: PathBuf = components.iter().map(|c| options.sanitizer.component(c)).collect();
+    normalized_path.push(options.sanitizer.file_name(&stem, &filetype.clone().get_extension()));
     Ok(normalized_path)
 }
 
-fn detect_extension(path: &Path) -> Result<String> {
-    Ok(detect_filetype(path)?.get_extension())
-}
+pub fn detect_filetype(path: &Path) -> Result<SupportedFiletype> {
+    let header = sniff::Header::read(path)?;
 
-fn detect_filetype(path: &Path) -> Result<SupportedFiletype> {
     // if path has an extension, use it as a starting place to validate
+    if let Some(ft) = SupportedFiletype::from_path(path) {
+        if sniff::matches(&ft, &header) {
+            return Ok(ft);
+        }
+    }
+
     // else, iterate through all supported filetypes to find the correct one
-    todo!()
+    SupportedFiletype::iter()
+        .find(|ft| sniff::matches(ft, &header))
+        .ok_or(Error::UnsupportedFiletype)
+}
+
+/// Detect the media type of a file, starting from what its filetype implies
+///
+/// Music files that turn out to be one part of a multi-file book are
+/// reclassified as audiobooks, and videos named like an episode are TV shows.
+pub fn detect_mediatype(path: &Path, filetype: &SupportedFiletype) -> Result<SupportedMediaType> {
+    let mediatype = SupportedMediaType::from(filetype.clone());
+    if mediatype == SupportedMediaType::Music
+        && matches!(filetype, SupportedFiletype::Mp3 | SupportedFiletype::M4a)
+        && audiobook::is_book_part(path)?
+    {
+        return Ok(SupportedMediaType::Audiobook);
+    }
+    if mediatype == SupportedMediaType::Movie && video::episode(path).is_some() {
+        return Ok(SupportedMediaType::TVShow);
+    }
+    Ok(mediatype)
 }
 
-fn detect_name_prefix(path: &Path) -> Option<&str> {
-    // track position if music
-    // episode number if tv
-    // series number if movie
-    // book number if audiobook/ebook and in series
-    todo!()
+/// Detect the audio codec carried by a file of the given filetype
+///
+/// Most filetypes only ever carry one codec, MP4 containers are inspected
+/// to tell AAC, ALAC and (E-)AC-3 apart.
+pub fn detect_codec(path: &Path, filetype: &SupportedFiletype) -> Result<Option<AudioCodec>> {
+    match filetype {
+        SupportedFiletype::Flac => Ok(Some(AudioCodec::Flac)),
+        SupportedFiletype::M4a | SupportedFiletype::M4b => mp4::read_audio_codec(path),
+        SupportedFiletype::Mp3 => Ok(Some(AudioCodec::Mp3)),
+        SupportedFiletype::Ogg => Ok(Some(AudioCodec::Vorbis)),
+        SupportedFiletype::Opus => Ok(Some(AudioCodec::Opus)),
+        SupportedFiletype::OggFlac => Ok(Some(AudioCodec::Flac)),
+        SupportedFiletype::Wav => Ok(Some(AudioCodec::Pcm)),
+        // AIFC may be compressed, but uncompressed PCM is by far the common case
+        SupportedFiletype::Aiff => Ok(Some(AudioCodec::Pcm)),
+        SupportedFiletype::Epub => Ok(None),
+        // Video containers report their first audio track
+        SupportedFiletype::Mkv | SupportedFiletype::Webm | SupportedFiletype::Mp4 => {
+            Ok(video::read_video_info(path, filetype)?.and_then(|info| info.audio.into_iter().flatten().next()))
+        }
+    }
 }
 
-fn detect_name(path: &Path) -> Result<&str> {
-    // track title if music
-    // episode name if tv
-    // title if movie
-    todo!()
+/// Look up a template field of a file, see `template::TEMPLATE_FIELDS`
+fn template_field(name: &str, path: &Path, mediatype: &SupportedMediaType, tags: &Tags, options: &NormalizeOptions) -> Option<String> {
+    let episode = || video::episode(path).filter(|_| *mediatype == SupportedMediaType::TVShow);
+    let movie = || video::movie(path, tags.title.as_deref()).filter(|_| *mediatype == SupportedMediaType::Movie);
+    let number = |n: Option<u32>| n.map(|n| n.to_string());
+    let multi_disc = |layout: DiscLayout| number(tags.disc_number.filter(|_| is_multi_disc(tags) && options.disc_layout == layout));
+    match name {
+        "artist" => tags.artists.first().cloned(),
+        "albumartist" => tags.album_artist.clone(),
+        "author" => tags.primary_artist().map(str::to_string),
+        "album" => tags.album.clone(),
+        "title" => tags.title.clone(),
+        "subtitle" => tags.subtitle.clone(),
+        // A book's parts share the album, a single file book may only have a title
+        "book" => tags.album.clone().or_else(|| tags.title.clone()),
+        "narrator" => tags.custom(tags::NARRATOR).map(str::to_string),
+        "track" => number(tags.track_number),
+        "tracktotal" => number(tags.track_total),
+        "disc" => number(tags.disc_number),
+        "disctotal" => number(tags.disc_total),
+        "disc_prefix" => multi_disc(DiscLayout::Prefix),
+        "disc_folder" => multi_disc(DiscLayout::Folder),
+        "year" => number(tags.year().or_else(|| movie()?.year)),
+        "date" => tags.date.clone(),
+        "genre" => tags.genre.clone(),
+        "series" => tags.custom(tags::SERIES).map(str::to_string),
+        // A book number means nothing outside its series
+        "series_index" => {
+            tags.custom(tags::SERIES)?;
+            tags.custom(tags::SERIES_INDEX)?.parse().ok().map(format_series_index)
+        }
+        "show" => episode().map(|e| e.series).filter(|series| !series.is_empty()),
+        "season" => number(episode().and_then(|e| e.season())),
+        "episode" => number(episode().and_then(|e| e.episode())),
+        "episode_end" => number(episode().and_then(|e| e.last_episode())),
+        "episode_code" => episode().map(|e| e.code()),
+        "air_date" => episode().and_then(|e| e.air_date()),
+        "episode_title" => episode().and_then(|e| e.title),
+        "movie" => movie().map(|m| m.title),
+        "edition" => movie().and_then(|m| m.edition),
+        "part" => number(movie().and_then(|m| m.part)),
+        // The file name, cleaned of release noise for videos
+        "filename" => {
+            let stem = path.file_stem()?.to_str()?;
+            match mediatype {
+                SupportedMediaType::TVShow | SupportedMediaType::Movie => Some(video::strip_noise(&video::clean(stem))),
+                _ => Some(stem.to_string()),
+            }
+        }
+        _ => None,
+    }
 }
 
-fn detect_sub_group(path: &Path) -> Option<&str> {
-    // album name if music
-    // season identifier if tv
-    todo!()
+/// Check whether a track belongs to a release with more than one disc
+///
+/// Without a disc total, any disc past the first gives it away.
+fn is_multi_disc(tags: &Tags) -> bool {
+    match (tags.disc_number, tags.disc_total) {
+        (_, Some(total)) => total > 1,
+        (Some(disc), None) => disc > 1,
+        (None, None) => false,
+    }
 }
 
-fn detect_super_group(path: &Path) -> Option<&str> {
-    // primary artist if music
-    // series name if tv
-    // series name if movie
-    todo!()
+/// Format a series index as a zero padded book number, keeping fractional
+/// indexes used for novellas, e.g. `2.5` becomes `02.5`
+fn format_series_index(index: f32) -> String {
+    if index.fract() == 0.0 {
+        format!("{:02}", index as u32)
+    } else {
+        format!("{:04.1}", index)
+    }
 }
 
-#[derive(strum::EnumIter, Clone)]
+#[derive(strum::EnumIter, Clone, Debug, PartialEq)]
 pub enum SupportedMediaType{
     Music,
     Audiobook,
@@ -93,14 +354,108 @@ impl From<SupportedFiletype> for SupportedMediaType {
         match ft {
             SupportedFiletype::Flac => Self::Music,
             SupportedFiletype::M4a => Self::Music,
+            SupportedFiletype::M4b => Self::Audiobook,
+            SupportedFiletype::Mp3 => Self::Music,
+            SupportedFiletype::Ogg => Self::Music,
+            SupportedFiletype::Opus => Self::Music,
+            SupportedFiletype::OggFlac => Self::Music,
+            SupportedFiletype::Wav => Self::Music,
+            SupportedFiletype::Aiff => Self::Music,
+            SupportedFiletype::Epub => Self::Ebook,
+            // Episodes are recognized by name in `detect_mediatype`
+            SupportedFiletype::Mkv => Self::Movie,
+            SupportedFiletype::Webm => Self::Movie,
+            SupportedFiletype::Mp4 => Self::Movie,
+        }
+    }
+}
+
+/// The audio codec inside a container
+#[derive(strum::EnumIter, Clone, Copy, Debug, PartialEq)]
+pub enum AudioCodec {
+    Aac,
+    Alac,
+    Ac3,
+    Eac3,
+    Flac,
+    Mp3,
+    Vorbis,
+    Opus,
+    Pcm,
+}
+
+impl AudioCodec {
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, AudioCodec::Alac | AudioCodec::Flac | AudioCodec::Pcm)
+    }
+
+    /// Check whether an ffprobe `codec_name` refers to this codec
+    pub fn matches_ffprobe_name(&self, name: &str) -> bool {
+        match self {
+            AudioCodec::Aac => name == "aac",
+            AudioCodec::Alac => name == "alac",
+            AudioCodec::Ac3 => name == "ac3",
+            AudioCodec::Eac3 => name == "eac3",
+            AudioCodec::Flac => name == "flac",
+            AudioCodec::Mp3 => name == "mp3" || name == "mp3float",
+            AudioCodec::Vorbis => name == "vorbis",
+            AudioCodec::Opus => name == "opus",
+            // ffprobe names pcm by sample format, e.g. pcm_s16le
+            AudioCodec::Pcm => name.starts_with("pcm_"),
         }
     }
 }
 
-#[derive(strum::EnumIter, Clone)]
+/// The video codec inside a container
+#[derive(strum::EnumIter, Clone, Copy, Debug, PartialEq)]
+pub enum VideoCodec {
+    H264,
+    Hevc,
+    Av1,
+    Vp8,
+    Vp9,
+    /// MPEG-4 Part 2, e.g. XviD and DivX
+    Mpeg4,
+    Mpeg2,
+}
+
+impl VideoCodec {
+    /// Check whether an ffprobe `codec_name` refers to this codec
+    pub fn matches_ffprobe_name(&self, name: &str) -> bool {
+        match self {
+            VideoCodec::H264 => name == "h264",
+            VideoCodec::Hevc => name == "hevc",
+            VideoCodec::Av1 => name == "av1",
+            VideoCodec::Vp8 => name == "vp8",
+            VideoCodec::Vp9 => name == "vp9",
+            VideoCodec::Mpeg4 => name == "mpeg4",
+            VideoCodec::Mpeg2 => name == "mpeg2video",
+        }
+    }
+}
+
+#[derive(strum::EnumIter, Clone, Debug, PartialEq)]
 pub enum SupportedFiletype {
     Flac,
     M4a,
+    /// Chaptered audiobook in an MP4 container
+    M4b,
+    Mp3,
+    /// Ogg Vorbis
+    Ogg,
+    /// Opus in an Ogg container
+    Opus,
+    /// FLAC in an Ogg container
+    OggFlac,
+    Wav,
+    Aiff,
+    Epub,
+    /// Matroska video
+    Mkv,
+    /// WebM, the Matroska subset used on the web
+    Webm,
+    /// MP4 with a video track
+    Mp4,
 }
 
 impl SupportedFiletype {
@@ -108,40 +463,37 @@ impl SupportedFiletype {
         match self {
             SupportedFiletype::Flac => String::from(".flac"),
             SupportedFiletype::M4a => String::from(".m4a"),
+            SupportedFiletype::M4b => String::from(".m4b"),
+            SupportedFiletype::Mp3 => String::from(".mp3"),
+            SupportedFiletype::Ogg => String::from(".ogg"),
+            SupportedFiletype::Opus => String::from(".opus"),
+            SupportedFiletype::OggFlac => String::from(".oga"),
+            SupportedFiletype::Wav => String::from(".wav"),
+            SupportedFiletype::Aiff => String::from(".aiff"),
+            SupportedFiletype::Epub => String::from(".epub"),
+            SupportedFiletype::Mkv => String::from(".mkv"),
+            SupportedFiletype::Webm => String::from(".webm"),
+            SupportedFiletype::Mp4 => String::from(".mp4"),
         }
     }
 
-    fn validation_command(self, file_path: &Path) -> Result<tokio::process::Command>{
-        let mut ffprobe_validate_args: Vec<String> = vec![
-            "-v".to_string(), 
-            "quiet".to_string(), 
-            "-show_entries".to_string(), 
-            "format_tags=major_brand".to_string(), 
-            "-of".to_string(), 
-            "csv=p=0".to_string()
-        ];
+    /// Look up the filetype matching the extension of `path`, ignoring case
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        Self::iter().find(|ft| ft.clone().get_extension() == format!(".{}", ext))
+    }
+
+    /// The ffprobe command used to confirm what a file actually contains
+    pub fn validation_command(self, file_path: &Path) -> Result<tokio::process::Command>{
         match self {
-            SupportedFiletype::Flac => {
-                let mut cmd = tokio::process::Command::new("ffprobe");
-                ffprobe_validate_args.push(file_path.to_str().ok_or(Error::InvalidFilePath)?.to_string());
-                cmd.args(&ffprobe_validate_args);
-                Ok(cmd)
-            }
-            SupportedFiletype::M4a => {
-                let mut cmd = tokio::process::Command::new("ffprobe");
-                ffprobe_validate_args.push(file_path.to_str().ok_or(Error::InvalidFilePath)?.to_string());
-                cmd.args(&ffprobe_validate_args);
-                Ok(cmd)
-            }
+            // ffprobe has nothing to say about ebooks
+            SupportedFiletype::Epub => Err(Error::UnsupportedFiletype),
+            _ => Ffprobe::default().command(file_path),
         }
     }
 }
 
 pub fn glob_expand(patterns: Vec<String>) -> Result<Vec<PathBuf>> {
-    let supported_extensions: Vec<String> = SupportedFiletype::iter()
-        .map(|ft| ft.get_extension())
-        .collect();
-    
     let mut result = Vec::new();
     
     for pattern in patterns {
@@ -156,13 +508,10 @@ pub fn glob_expand(patterns: Vec<String>) -> Result<Vec<PathBuf>> {
             let path = entry?;
             
             if path.is_file() {
-                if let Some(extension) = path.extension() {
-                    if let Some(ext_str) = extension.to_str() {
-                        let ext_with_dot = format!(".{}", ext_str);
-                        if supported_extensions.contains(&ext_with_dot) {
-                            result.push(path);
-                        }
-                    }
+                // Files with a supported extension are kept as is, anything
+                // else is only kept if its content is recognized
+                if SupportedFiletype::from_path(&path).is_some() || detect_filetype(&path).is_ok() {
+                    result.push(path);
                 }
             }
         }
@@ -174,12 +523,69 @@ pub fn glob_expand(patterns: Vec<String>) -> Result<Vec<PathBuf>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::metadata::{epub, flac, matroska, ogg, riff::{self, ChunkForm}};
+    use crate::sanitize::Profile;
     use std::fs;
     use tempfile::TempDir;
 
     #[test]
     fn test_detect_extension() {
-        todo!()
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let mut m4a = vec![0, 0, 0, 0x18];
+        m4a.extend_from_slice(b"ftypM4A \0\0\0\0M4A isom");
+
+        fs::write(temp_path.join("song.flac"), b"fLaC\0\0\0\x22").unwrap();
+        fs::write(temp_path.join("song.m4a"), &m4a).unwrap();
+        // Generic brands defer to the extension, an M4B brand always wins
+        let mut generic = vec![0, 0, 0, 0x14];
+        generic.extend_from_slice(b"ftypisom\0\0\0\0mp42");
+        fs::write(temp_path.join("song.m4b"), &generic).unwrap();
+        let mut m4b = vec![0, 0, 0, 0x14];
+        m4b.extend_from_slice(b"ftypM4B \0\0\0\0isom");
+        fs::write(temp_path.join("book.m4a"), &m4b).unwrap();
+        fs::write(temp_path.join("really_flac.m4a"), b"fLaC\0\0\0\x22").unwrap();
+        fs::write(temp_path.join("no_extension"), &m4a).unwrap();
+        fs::write(temp_path.join("song.opus"), ogg::page(2, 1, 0, &[b"OpusHead\x01\x02"])).unwrap();
+        fs::write(temp_path.join("vorbis.opus"), ogg::page(2, 1, 0, &[b"\x01vorbis\0\0\0\0"])).unwrap();
+        fs::write(temp_path.join("rip.wav"), riff::container(ChunkForm::Wave, &[])).unwrap();
+        fs::write(temp_path.join("rip.aif"), riff::container(ChunkForm::Aifc, &[])).unwrap();
+        fs::write(temp_path.join("song.mp3"), b"ID3\x04\x00\x00\x00\x00\x00\x00\xFF\xFB\x90\x64").unwrap();
+        fs::write(temp_path.join("fake.flac"), "test flac content").unwrap();
+
+        let extension = |name| detect_filetype(&temp_path.join(name)).map(|ft| ft.get_extension());
+        assert_eq!(extension("song.flac").unwrap(), ".flac");
+        assert_eq!(extension("song.m4a").unwrap(), ".m4a");
+        assert_eq!(extension("song.m4b").unwrap(), ".m4b");
+        assert_eq!(extension("book.m4a").unwrap(), ".m4b");
+        assert_eq!(extension("really_flac.m4a").unwrap(), ".flac");
+        assert_eq!(extension("no_extension").unwrap(), ".m4a");
+        assert_eq!(extension("song.mp3").unwrap(), ".mp3");
+        assert_eq!(extension("rip.wav").unwrap(), ".wav");
+        assert_eq!(extension("rip.aif").unwrap(), ".aiff");
+        assert_eq!(extension("song.opus").unwrap(), ".opus");
+        assert_eq!(extension("vorbis.opus").unwrap(), ".ogg");
+        assert!(matches!(
+            extension("fake.flac"),
+            Err(Error::UnsupportedFiletype)
+        ));
+    }
+
+    #[test]
+    fn test_detect_codec() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("lossless.m4a");
+
+        let mut bytes = mp4::mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        bytes.extend_from_slice(&mp4::mp4_box(b"moov", &mp4::track(b"soun", &mp4::audio_entry(b"alac", &[]))));
+        fs::write(&path, &bytes).unwrap();
+
+        let codec = detect_codec(&path, &SupportedFiletype::M4a).unwrap().unwrap();
+        assert_eq!(codec, AudioCodec::Alac);
+        assert!(codec.is_lossless());
+        assert!(!AudioCodec::Aac.is_lossless());
+        assert!(AudioCodec::Pcm.matches_ffprobe_name("pcm_s24le"));
     }
 
     #[test]
@@ -191,15 +597,15 @@ mod tests {
         // Create test files
         fs::write(temp_path.join("test.flac"), "test flac content").unwrap();
         fs::write(temp_path.join("test.m4a"), "test m4a content").unwrap();
-        fs::write(temp_path.join("test.mp3"), "test mp3 content").unwrap(); // unsupported
+        fs::write(temp_path.join("test.mp3"), "test mp3 content").unwrap();
         fs::write(temp_path.join("test.txt"), "test txt content").unwrap(); // unsupported
         
         // Test glob_expand with the temp directory
         let patterns = vec![temp_path.to_string_lossy().to_string()];
         let result = glob_expand(patterns).unwrap();
         
-        // Should only return flac and m4a files
-        assert_eq!(result.len(), 2);
+        // Should only return flac, m4a and mp3 files
+        assert_eq!(result.len(), 3);
         
         let extensions: Vec<String> = result.iter()
             .filter_map(|path| path.extension())
@@ -209,5 +615,278 @@ mod tests {
         
         assert!(extensions.contains(&"flac".to_string()));
         assert!(extensions.contains(&"m4a".to_string()));
+        assert!(extensions.contains(&"mp3".to_string()));
+    }
+
+    #[test]
+    fn test_glob_expand_sniffs_unknown_extensions() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        fs::write(temp_path.join("download"), b"fLaC\0\0\0\x22").unwrap();
+        fs::write(temp_path.join("download.part"), b"fLaC\0\0\0\x22").unwrap();
+        fs::write(temp_path.join("notes"), "not media").unwrap();
+
+        let patterns = vec![temp_path.to_string_lossy().to_string()];
+        let result = glob_expand(patterns).unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().all(|path| !path.ends_with("notes")));
+    }
+
+    #[test]
+    fn test_normalize_epub() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("download");
+        epub::write_epub(&path, r#"<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Words of Radiance</dc:title>
+    <dc:creator>Brandon Sanderson</dc:creator>
+    <meta name="calibre:series" content="The Stormlight Archive"/>
+    <meta name="calibre:series_index" content="2"/>
+  </metadata>
+</package>"#);
+
+        let file = File::try_new(path).unwrap();
+        assert_eq!(file.filetype(), &SupportedFiletype::Epub);
+        assert_eq!(file.mediatype(), &SupportedMediaType::Ebook);
+        assert_eq!(file.codec(), None);
+        assert_eq!(
+            file.normalized_path(),
+            Path::new("Brandon Sanderson/The Stormlight Archive/02_Words of Radiance.epub")
+        );
+        assert_eq!(format_series_index(2.5), "02.5");
+    }
+
+    #[test]
+    fn test_normalize_flac() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("track.flac");
+        let comments = crate::metadata::vorbis::encode("reference libFLAC", &[
+            ("TITLE", "Money"),
+            ("ARTIST", "Pink Floyd"),
+            ("ALBUM", "The Dark Side of the Moon"),
+            ("TRACKNUMBER", "6/10"),
+        ]);
+        fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+
+        let file = File::try_new(path).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Pink Floyd/The Dark Side of the Moon/06_Money.flac"));
+    }
+
+    #[test]
+    fn test_normalize_multi_disc() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("track.flac");
+        let write = |disc: &str| {
+            let comments = crate::metadata::vorbis::encode("reference libFLAC", &[
+                ("TITLE", "Hey You"),
+                ("ARTIST", "Pink Floyd"),
+                ("ALBUM", "The Wall"),
+                ("TRACKNUMBER", "1"),
+                ("DISCNUMBER", disc),
+            ]);
+            fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+        };
+
+        write("2/2");
+        let file = File::try_new(path.clone()).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Pink Floyd/The Wall/2-01_Hey You.flac"));
+
+        let options = NormalizeOptions { disc_layout: DiscLayout::Folder, ..Default::default() };
+        let file = File::try_new_with(path.clone(), &options).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Pink Floyd/The Wall/Disc 2/01_Hey You.flac"));
+
+        write("1/1");
+        let file = File::try_new_with(path, &options).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Pink Floyd/The Wall/01_Hey You.flac"));
+    }
+
+    #[test]
+    fn test_normalize_compilation() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |name: &str, artist: &str, track: &str| {
+            let comments = crate::metadata::vorbis::encode("reference libFLAC", &[
+                ("TITLE", name),
+                ("ARTIST", artist),
+                ("ALBUM", "Now 32"),
+                ("TRACKNUMBER", track),
+            ]);
+            let path = temp_dir.path().join(format!("{}.flac", name));
+            fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+            path
+        };
+        let blur = write("Country House", "Blur", "1");
+        let oasis = write("Roll With It", "Oasis", "2");
+
+        let file = File::try_new(blur).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Various Artists/Now 32/01_Country House.flac"));
+
+        let options = NormalizeOptions {
+            compilations: Compilations { folder: "Compilations".to_string(), ..Default::default() },
+            ..Default::default()
+        };
+        let file = File::try_new_with(oasis, &options).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Compilations/Now 32/02_Roll With It.flac"));
+    }
+
+    #[test]
+    fn test_normalize_reads_album_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |name: &str, artist: &str| {
+            let comments = crate::metadata::vorbis::encode("reference libFLAC", &[
+                ("TITLE", name),
+                ("ARTIST", artist),
+                ("ALBUM", "The Wall"),
+            ]);
+            let path = temp_dir.path().join(format!("{}.flac", name));
+            fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+            path
+        };
+        let hey_you = write("Hey You", "Pink Floyd");
+        write("Mother", "Pink Floyd");
+
+        let options = NormalizeOptions::default();
+        let file = File::try_new_with(hey_you.clone(), &options).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Pink Floyd/The Wall/Hey You.flac"));
+
+        // The album was read for the first track, so a sibling changing on disk goes unnoticed
+        write("Mother", "Roger Waters");
+        let file = File::try_new_with(hey_you.clone(), &options).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Pink Floyd/The Wall/Hey You.flac"));
+        let file = File::try_new_with(hey_you, &NormalizeOptions::default()).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Various Artists/The Wall/Hey You.flac"));
+    }
+
+    #[test]
+    fn test_normalize_sanitizes_tag_text() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("track.flac");
+        let comments = crate::metadata::vorbis::encode("reference libFLAC", &[
+            ("TITLE", "Who Are You?"),
+            ("ARTIST", "AC/DC"),
+            ("ALBUM", "Live..."),
+        ]);
+        fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+
+        let file = File::try_new(path.clone()).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("AC_DC/Live/Who Are You_.flac"));
+
+        let options = NormalizeOptions { sanitizer: Sanitizer { profile: Profile::Posix, ..Default::default() }, ..Default::default() };
+        let file = File::try_new_with(path, &options).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("AC_DC/Live.../Who Are You?.flac"));
+    }
+
+    #[test]
+    fn test_normalize_infers_missing_tags_from_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let album = temp_dir.path().join("Pink Floyd - Animals");
+        fs::create_dir(&album).unwrap();
+        let path = album.join("02 - dogs.flac");
+        let comments = crate::metadata::vorbis::encode("reference libFLAC", &[("TITLE", "Dogs")]);
+        fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+
+        let options = NormalizeOptions {
+            patterns: vec![PathPattern::parse("{artist} - {album}/{track} - {title}").unwrap()],
+            ..Default::default()
+        };
+        let file = File::try_new_with(path.clone(), &options).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Pink Floyd/Animals/02_Dogs.flac"));
+        assert_eq!(file.inference().unwrap().confidence, 1.0);
+
+        let options = NormalizeOptions { min_confidence: 1.1, ..options };
+        let file = File::try_new_with(path, &options).unwrap();
+        assert!(file.inference().is_none());
+        assert_eq!(file.normalized_path(), Path::new("Dogs.flac"));
+    }
+
+    #[test]
+    fn test_normalize_m4a() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("track.m4a");
+
+        let mut moov = mp4::track(b"soun", &mp4::audio_entry(b"mp4a", &[]));
+        moov.extend_from_slice(&mp4::udta(&[
+            mp4::ilst_item(b"\xA9nam", 1, b"Time"),
+            mp4::ilst_item(b"\xA9ART", 1, b"Pink Floyd"),
+            mp4::ilst_item(b"\xA9alb", 1, b"The Dark Side of the Moon"),
+            mp4::ilst_item(b"trkn", 0, &[0, 0, 0, 4, 0, 10, 0, 0]),
+        ]));
+        let mut bytes = mp4::mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        bytes.extend_from_slice(&mp4::mp4_box(b"moov", &moov));
+        fs::write(&path, &bytes).unwrap();
+
+        let file = File::try_new(path).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Pink Floyd/The Dark Side of the Moon/04_Time.m4a"));
+    }
+
+    #[test]
+    fn test_normalize_audiobook() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("Book 03 - Oathbringer");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("reader.txt"), "Kate Reading\n").unwrap();
+        let path = dir.join("download.m4b");
+
+        let mut series = mp4::mp4_box(b"mean", b"\0\0\0\0com.apple.iTunes");
+        series.extend_from_slice(&mp4::mp4_box(b"name", b"\0\0\0\0SERIES"));
+        series.extend_from_slice(&mp4::mp4_box(b"data", b"\0\0\0\x01\0\0\0\0The Stormlight Archive"));
+        let mut moov = mp4::track(b"soun", &mp4::audio_entry(b"mp4a", &[]));
+        moov.extend_from_slice(&mp4::udta(&[
+            mp4::ilst_item(b"\xA9nam", 1, b"Oathbringer"),
+            mp4::ilst_item(b"\xA9ART", 1, b"Brandon Sanderson"),
+            mp4::ilst_item(b"\xA9wrt", 1, b"Michael Kramer"),
+            mp4::mp4_box(b"----", &series),
+        ]));
+        let mut bytes = mp4::mp4_box(b"ftyp", b"M4B \0\0\0\0M4B isom");
+        bytes.extend_from_slice(&mp4::mp4_box(b"moov", &moov));
+        fs::write(&path, &bytes).unwrap();
+
+        let file = File::try_new(path).unwrap();
+        assert_eq!(file.mediatype(), &SupportedMediaType::Audiobook);
+        assert_eq!(file.normalized_path(), Path::new("Brandon Sanderson/The Stormlight Archive/03_Oathbringer/Oathbringer.m4b"));
+        // reader.txt wins over the composer
+        assert_eq!(file.tags().custom(tags::NARRATOR), Some("Kate Reading"));
+        assert_eq!(file.sidecars()[0].normalized_path(), Path::new("Brandon Sanderson/The Stormlight Archive/03_Oathbringer/reader.txt"));
+    }
+
+    #[test]
+    fn test_normalize_video() {
+        let temp_dir = TempDir::new().unwrap();
+        let episode = temp_dir.path().join("The.Expanse.S02E05.mkv");
+        let movie = temp_dir.path().join("download.mkv");
+        fs::write(temp_dir.path().join("The.Expanse.S02E05.en.srt"), "").unwrap();
+        fs::write(&episode, matroska::matroska("matroska", "Home", &[(1, "V_MPEG4/ISO/AVC"), (2, "A_OPUS")])).unwrap();
+        fs::write(&movie, matroska::matroska("matroska", "Sintel", &[(1, "V_VP9")])).unwrap();
+
+        let file = File::try_new(episode).unwrap();
+        assert_eq!(file.filetype(), &SupportedFiletype::Mkv);
+        assert_eq!(file.mediatype(), &SupportedMediaType::TVShow);
+        assert_eq!(file.codec(), Some(&AudioCodec::Opus));
+        assert_eq!(file.normalized_path(), Path::new("The Expanse/Season 02/S02E05_Home.mkv"));
+        assert_eq!(file.sidecars().len(), 1);
+        assert_eq!(file.sidecars()[0].normalized_path(), Path::new("The Expanse/Season 02/S02E05_Home.en.srt"));
+
+        let daily = temp_dir.path().join("The.Daily.Show.2024.03.15.720p.WEB.mkv");
+        fs::write(&daily, matroska::matroska("matroska", "", &[(1, "V_MPEG4/ISO/AVC")])).unwrap();
+        let file = File::try_new(daily).unwrap();
+        assert_eq!(file.mediatype(), &SupportedMediaType::TVShow);
+        assert_eq!(file.normalized_path(), Path::new("The Daily Show/Season 2024/2024-03-15_The Daily Show.mkv"));
+
+        let anime = temp_dir.path().join("[Group] Frieren - 07 [1080p].mkv");
+        fs::write(&anime, matroska::matroska("matroska", "The Hero's Funeral", &[(1, "V_MPEG4/ISO/AVC")])).unwrap();
+        let file = File::try_new(anime).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Frieren/07_The Hero's Funeral.mkv"));
+
+        let file = File::try_new(movie).unwrap();
+        assert_eq!(file.mediatype(), &SupportedMediaType::Movie);
+        assert_eq!(file.codec(), None);
+        assert_eq!(file.normalized_path(), Path::new("Sintel/Sintel.mkv"));
+        assert!(file.sidecars().is_empty());
+
+        let release = temp_dir.path().join("Blade.Runner.1982.Final.Cut.1080p.BluRay.x264-GROUP.mkv");
+        fs::write(&release, matroska::matroska("matroska", "", &[(1, "V_MPEG4/ISO/AVC")])).unwrap();
+        let file = File::try_new(release).unwrap();
+        assert_eq!(file.normalized_path(), Path::new("Blade Runner (1982)/Blade Runner (1982) {edition-Final Cut}.mkv"));
     }
 }
@@ -0,0 +1,219 @@
+//! CUE sheet parsing
+//!
+//! A CUE sheet is a line based text file describing the tracks of a disc
+//! image. Times are `MM:SS:FF` where a frame is 1/75 of a second, and each
+//! track's `INDEX 01` marks where its audio starts.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::metadata::id3::decode_latin1;
+use crate::{Error, Result};
+
+/// CD audio frames per second
+const FRAMES_PER_SECOND: u64 = 75;
+
+/// A parsed CUE sheet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// `REM` comments such as `GENRE` and `DATE`
+    pub rems: Vec<(String, String)>,
+    pub tracks: Vec<CueTrack>,
+}
+
+/// A single `TRACK` of a CUE sheet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    /// The `FILE` the track's audio lives in
+    pub file: Option<String>,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub isrc: Option<String>,
+    /// Index number and position within the file
+    pub indexes: Vec<(u32, Duration)>,
+}
+
+impl CueSheet {
+    /// The value of a `REM` comment, ignoring case
+    pub fn rem(&self, key: &str) -> Option<&str> {
+        self.rems.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str())
+    }
+
+    /// The distinct files referenced by the sheet, in order
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = Vec::new();
+        for file in self.tracks.iter().filter_map(|t| t.file.as_deref()) {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        files
+    }
+
+    /// Parse the text of a CUE sheet
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut sheet = CueSheet::default();
+        let mut file: Option<String> = None;
+
+        for (line_number, line) in text.lines().enumerate() {
+            let tokens = tokenize(line);
+            let Some((command, args)) = tokens.split_first() else {
+                continue;
+            };
+            let arg = |i: usize| args.get(i).cloned();
+            let track = sheet.tracks.last_mut();
+
+            match (command.to_uppercase().as_str(), track) {
+                ("REM", _) => {
+                    if let (Some(key), Some(_)) = (arg(0), arg(1)) {
+                        sheet.rems.push((key.to_uppercase(), args[1..].join(" ")));
+                    }
+                }
+                ("FILE", _) => file = arg(0),
+                ("TRACK", _) => {
+                    let number = arg(0).and_then(|n| n.parse().ok()).ok_or_else(|| invalid(line_number, "track number"))?;
+                    sheet.tracks.push(CueTrack { number, file: file.clone(), ..Default::default() });
+                }
+                ("TITLE", None) => sheet.title = arg(0),
+                ("TITLE", Some(track)) => track.title = arg(0),
+                ("PERFORMER", None) => sheet.performer = arg(0),
+                ("PERFORMER", Some(track)) => track.performer = arg(0),
+                ("ISRC", Some(track)) => track.isrc = arg(0),
+                ("INDEX", Some(track)) => {
+                    let number = arg(0).and_then(|n| n.parse().ok()).ok_or_else(|| invalid(line_number, "index number"))?;
+                    let time = arg(1).as_deref().and_then(parse_time).ok_or_else(|| invalid(line_number, "index time"))?;
+                    track.indexes.push((number, time));
+                }
+                // CATALOG, FLAGS, PREGAP, SONGWRITER and friends don't affect splitting
+                _ => {}
+            }
+        }
+
+        Ok(sheet)
+    }
+}
+
+impl CueTrack {
+    /// Where the track's audio starts, `INDEX 01` or the first index if it's missing
+    pub fn start(&self) -> Option<Duration> {
+        self.indexes
+            .iter()
+            .find(|(n, _)| *n == 1)
+            .or(self.indexes.first())
+            .map(|(_, time)| *time)
+    }
+}
+
+/// Read and parse a CUE sheet, which may be UTF-8 or a legacy single byte encoding
+pub fn read_cue_sheet(path: &Path) -> Result<CueSheet> {
+    let bytes = fs::read(path)?;
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => CueSheet::parse(text),
+        Err(_) => CueSheet::parse(&decode_latin1(bytes)),
+    }
+}
+
+/// Parse an `MM:SS:FF` time
+pub fn parse_time(time: &str) -> Option<Duration> {
+    let mut parts = time.split(':').map(|p| p.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= FRAMES_PER_SECOND {
+        return None;
+    }
+    let frames = (minutes * 60 + seconds) * FRAMES_PER_SECOND + frames;
+    Some(Duration::from_nanos(frames * 1_000_000_000 / FRAMES_PER_SECOND))
+}
+
+/// Split a line into whitespace separated tokens, honouring double quotes
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(c) = chars.peek().copied() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            tokens.push(chars.by_ref().take_while(|c| *c != '"').collect());
+        } else {
+            let mut token = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+            tokens.push(token);
+        }
+    }
+    tokens
+}
+
+fn invalid(line_number: usize, what: &str) -> Error {
+    Error::InvalidMetadata(format!("invalid cue {} on line {}", what, line_number + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const SHEET: &str = r#"REM GENRE "Progressive Rock"
+REM DATE 1973
+PERFORMER "Pink Floyd"
+TITLE "The Dark Side of the Moon"
+FILE "Pink Floyd - The Dark Side of the Moon.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "Speak to Me"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Breathe (In the Air)"
+    PERFORMER "Pink Floyd feat. Nobody"
+    ISRC GBN9Y1100088
+    INDEX 00 01:05:60
+    INDEX 01 01:07:30
+"#;
+
+    #[test]
+    fn test_parse_sheet() {
+        let sheet = CueSheet::parse(SHEET).unwrap();
+        assert_eq!(sheet.title.as_deref(), Some("The Dark Side of the Moon"));
+        assert_eq!(sheet.performer.as_deref(), Some("Pink Floyd"));
+        assert_eq!(sheet.rem("genre"), Some("Progressive Rock"));
+        assert_eq!(sheet.rem("DATE"), Some("1973"));
+        assert_eq!(sheet.files(), vec!["Pink Floyd - The Dark Side of the Moon.flac"]);
+
+        assert_eq!(sheet.tracks.len(), 2);
+        let breathe = &sheet.tracks[1];
+        assert_eq!(breathe.number, 2);
+        assert_eq!(breathe.title.as_deref(), Some("Breathe (In the Air)"));
+        assert_eq!(breathe.performer.as_deref(), Some("Pink Floyd feat. Nobody"));
+        assert_eq!(breathe.isrc.as_deref(), Some("GBN9Y1100088"));
+        assert_eq!(breathe.start(), Some(Duration::from_millis(67_400)));
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_time("74:59:74"), Some(Duration::from_nanos(4_499_986_666_666)));
+        assert_eq!(parse_time("01:60:00"), None);
+        assert_eq!(parse_time("01:02"), None);
+    }
+
+    #[test]
+    fn test_read_latin1_sheet() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("album.cue");
+        fs::write(&path, b"TITLE \"Caf\xE9\"\r\nTRACK 01 AUDIO\r\nINDEX 01 00:00:00\r\n").unwrap();
+
+        let sheet = read_cue_sheet(&path).unwrap();
+        assert_eq!(sheet.title.as_deref(), Some("Café"));
+        assert_eq!(sheet.tracks[0].start(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_invalid_index() {
+        assert!(matches!(CueSheet::parse("TRACK 01 AUDIO\nINDEX 01 soon"), Err(Error::InvalidMetadata(_))));
+    }
+}
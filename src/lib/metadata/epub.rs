@@ -0,0 +1,270 @@
+//! EPUB package metadata
+//!
+//! An EPUB is a ZIP archive whose first entry is an uncompressed `mimetype`
+//! file. `META-INF/container.xml` points at the OPF package document, and
+//! the `<metadata>` element of that package holds the Dublin Core title and
+//! creators along with calibre or EPUB3 series information.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::{Error, Result};
+
+const EPUB_MIMETYPE: &[u8] = b"application/epub+zip";
+
+/// Metadata from the OPF package document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EpubPackage {
+    pub title: Option<String>,
+    /// Creators with an author role, or no role at all
+    pub authors: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f32>,
+    pub language: Option<String>,
+}
+
+/// Check for the stored `mimetype` entry that must open every EPUB
+pub fn is_epub(bytes: &[u8]) -> bool {
+    if bytes.len() < 30 || &bytes[0..4] != b"PK\x03\x04" {
+        return false;
+    }
+    let name_len = u16::from_le_bytes([bytes[26], bytes[27]]) as usize;
+    let extra_len = u16::from_le_bytes([bytes[28], bytes[29]]) as usize;
+    let data = 30 + name_len + extra_len;
+
+    bytes.get(30..30 + name_len) == Some(b"mimetype".as_slice())
+        && bytes.get(data..data + EPUB_MIMETYPE.len()) == Some(EPUB_MIMETYPE)
+}
+
+/// Read the package metadata of an EPUB file
+pub fn read_package(path: &Path) -> Result<EpubPackage> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+
+    let container = read_entry(&mut archive, "META-INF/container.xml")?;
+    let opf_path = rootfile_path(&container)?
+        .ok_or_else(|| Error::InvalidMetadata("container.xml has no rootfile".to_string()))?;
+
+    let opf = read_entry(&mut archive, &opf_path)?;
+    parse_opf(&opf)
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<fs::File>, name: &str) -> Result<String> {
+    let mut content = String::new();
+    archive.by_name(name)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// The `full-path` of the first `rootfile` in `container.xml`
+fn rootfile_path(container: &str) -> Result<Option<String>> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+                return attribute(&e, "full-path");
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// Parse the `<metadata>` element of an OPF package document
+pub fn parse_opf(opf: &str) -> Result<EpubPackage> {
+    let mut reader = Reader::from_str(opf);
+    reader.config_mut().trim_text(true);
+
+    let mut package = EpubPackage::default();
+    // Creators in document order with their id and opf:role, if any
+    let mut creators: Vec<(Option<String>, Option<String>, String)> = Vec::new();
+    // EPUB3 `refines` metadata, keyed by the refined id
+    let mut refines: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    let mut collections: Vec<(Option<String>, String)> = Vec::new();
+
+    // The element whose text we're waiting for
+    let mut current: Option<BytesStart<'static>> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => current = Some(e.into_owned()),
+            Event::Empty(e) if e.local_name().as_ref() == b"meta" => {
+                // EPUB2 style <meta name="calibre:series" content="..."/>
+                let (Some(name), Some(content)) = (attribute(&e, "name")?, attribute(&e, "content")?) else {
+                    continue;
+                };
+                match name.as_str() {
+                    "calibre:series" => package.series = Some(content),
+                    "calibre:series_index" => package.series_index = content.trim().parse().ok(),
+                    _ => {}
+                }
+            }
+            Event::Text(t) => {
+                let Some(start) = current.take() else {
+                    continue;
+                };
+                let text = t.unescape()?.trim().to_string();
+                if text.is_empty() {
+                    continue;
+                }
+                match start.local_name().as_ref() {
+                    b"title" if package.title.is_none() => package.title = Some(text),
+                    b"language" if package.language.is_none() => package.language = Some(text),
+                    b"creator" => creators.push((attribute(&start, "id")?, attribute(&start, "opf:role")?, text)),
+                    b"meta" => {
+                        let property = attribute(&start, "property")?;
+                        match (attribute(&start, "refines")?, property) {
+                            (Some(target), Some(property)) => refines
+                                .entry(target.trim_start_matches('#').to_string())
+                                .or_default()
+                                .push((property, text)),
+                            (None, Some(property)) if property == "belongs-to-collection" => {
+                                collections.push((attribute(&start, "id")?, text));
+                            }
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(_) => current = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    package.authors = creators
+        .into_iter()
+        .filter(|(id, role, _)| {
+            let refined_role = id.as_ref().and_then(|id| refined(&refines, id, "role"));
+            match role.as_deref().or(refined_role) {
+                Some(role) => role == "aut",
+                None => true,
+            }
+        })
+        .map(|(_, _, name)| name)
+        .collect();
+
+    // calibre metadata wins, it's what most libraries are managed with
+    if package.series.is_none() {
+        if let Some((id, name)) = collections.into_iter().next() {
+            package.series_index = id
+                .as_ref()
+                .and_then(|id| refined(&refines, id, "group-position"))
+                .and_then(|position| position.trim().parse().ok());
+            package.series = Some(name);
+        }
+    }
+
+    Ok(package)
+}
+
+/// The value of a refining `<meta property="...">` for an id
+fn refined<'a>(refines: &'a HashMap<String, Vec<(String, String)>>, id: &str, property: &str) -> Option<&'a str> {
+    refines.get(id)?.iter().find(|(p, _)| p == property).map(|(_, v)| v.as_str())
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>> {
+    match element.try_get_attribute(name).map_err(quick_xml::Error::from)? {
+        Some(attr) => Ok(Some(attr.unescape_value()?.to_string())),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn write_epub(path: &Path, opf: &str) {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::CompressionMethod;
+
+    let mut writer = zip::ZipWriter::new(fs::File::create(path).unwrap());
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    writer.start_file("mimetype", stored).unwrap();
+    writer.write_all(EPUB_MIMETYPE).unwrap();
+
+    let deflated = SimpleFileOptions::default();
+    writer.start_file("META-INF/container.xml", deflated).unwrap();
+    writer.write_all(br#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#).unwrap();
+    writer.start_file("OEBPS/content.opf", deflated).unwrap();
+    writer.write_all(opf.as_bytes()).unwrap();
+    writer.finish().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const CALIBRE_OPF: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>The Fellowship of the Ring</dc:title>
+    <dc:creator opf:role="aut" opf:file-as="Tolkien, J. R. R.">J. R. R. Tolkien</dc:creator>
+    <dc:creator opf:role="ill">Alan Lee</dc:creator>
+    <dc:language>en</dc:language>
+    <meta name="calibre:series" content="The Lord of the Rings"/>
+    <meta name="calibre:series_index" content="1.0"/>
+  </metadata>
+</package>"#;
+
+    const EPUB3_OPF: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title id="t1">Leviathan Wakes</dc:title>
+    <dc:creator id="c1">James S. A. Corey</dc:creator>
+    <meta refines="#c1" property="role" scheme="marc:relators">aut</meta>
+    <dc:creator id="c2">Jefferson Mays</dc:creator>
+    <meta refines="#c2" property="role" scheme="marc:relators">nrt</meta>
+    <meta property="belongs-to-collection" id="s1">The Expanse</meta>
+    <meta refines="#s1" property="collection-type">series</meta>
+    <meta refines="#s1" property="group-position">1</meta>
+  </metadata>
+</package>"##;
+
+    #[test]
+    fn test_parse_calibre_opf() {
+        let package = parse_opf(CALIBRE_OPF).unwrap();
+        assert_eq!(package.title.as_deref(), Some("The Fellowship of the Ring"));
+        assert_eq!(package.authors, vec!["J. R. R. Tolkien".to_string()]);
+        assert_eq!(package.series.as_deref(), Some("The Lord of the Rings"));
+        assert_eq!(package.series_index, Some(1.0));
+        assert_eq!(package.language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn test_parse_epub3_opf() {
+        let package = parse_opf(EPUB3_OPF).unwrap();
+        assert_eq!(package.title.as_deref(), Some("Leviathan Wakes"));
+        assert_eq!(package.authors, vec!["James S. A. Corey".to_string()]);
+        assert_eq!(package.series.as_deref(), Some("The Expanse"));
+        assert_eq!(package.series_index, Some(1.0));
+    }
+
+    #[test]
+    fn test_read_package() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("book.epub");
+        write_epub(&path, CALIBRE_OPF);
+
+        let header = fs::read(&path).unwrap();
+        assert!(is_epub(&header));
+        assert_eq!(read_package(&path).unwrap().title.as_deref(), Some("The Fellowship of the Ring"));
+    }
+
+    #[test]
+    fn test_plain_zip_is_not_epub() {
+        let mut bytes = b"PK\x03\x04".to_vec();
+        bytes.extend_from_slice(&[0; 22]);
+        bytes.extend_from_slice(&[8, 0, 0, 0]);
+        bytes.extend_from_slice(b"readme.txt");
+        assert!(!is_epub(&bytes));
+    }
+}
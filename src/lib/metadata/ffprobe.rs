@@ -0,0 +1,314 @@
+//! ffprobe JSON backend
+//!
+//! Runs `ffprobe -print_format json -show_format -show_streams -show_chapters`
+//! and deserializes the result. ffprobe reads far more formats than the
+//! native parsers, at the cost of spawning a process per file. Numbers that
+//! ffprobe prints as strings, like durations and sample rates, are kept as
+//! strings and parsed by the accessors.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::files::{AudioCodec, VideoCodec};
+use crate::metadata::tags::{MetadataReader, Tags};
+use crate::metadata::vorbis::VorbisComments;
+use crate::metadata::{Chapter, VideoInfo, VideoStream};
+use crate::{Error, Result};
+
+/// Container tags ffmpeg adds on its own, which aren't worth keeping as custom fields
+const CONTAINER_KEYS: &[&str] = &[
+    "ENCODER", "MAJOR_BRAND", "MINOR_VERSION", "COMPATIBLE_BRANDS", "CREATION_TIME", "HANDLER_NAME", "VENDOR_ID",
+    "LANGUAGE", "DURATION",
+];
+
+/// The output of `ffprobe -show_format -show_streams -show_chapters`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Probe {
+    pub format: ProbeFormat,
+    pub streams: Vec<ProbeStream>,
+    pub chapters: Vec<ProbeChapter>,
+}
+
+/// The container as a whole
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProbeFormat {
+    pub filename: String,
+    /// Comma separated demuxer names, e.g. `mov,mp4,m4a,3gp,3g2,mj2`
+    pub format_name: String,
+    pub duration: Option<String>,
+    pub bit_rate: Option<String>,
+    pub tags: BTreeMap<String, String>,
+}
+
+/// A single audio, video, subtitle or attachment stream
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProbeStream {
+    pub index: u32,
+    /// `audio`, `video`, `subtitle`, `attachment` or `data`
+    pub codec_type: Option<String>,
+    pub codec_name: Option<String>,
+    pub sample_rate: Option<String>,
+    pub channels: Option<u32>,
+    pub bits_per_raw_sample: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration: Option<String>,
+    pub tags: BTreeMap<String, String>,
+}
+
+/// A chapter, with times in seconds
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProbeChapter {
+    pub id: i64,
+    pub start_time: String,
+    pub end_time: String,
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Probe {
+    /// Parse the JSON printed by ffprobe
+    pub fn parse(json: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    /// The streams of a type, e.g. `audio`
+    pub fn streams_of<'a>(&'a self, codec_type: &'a str) -> impl Iterator<Item = &'a ProbeStream> + 'a {
+        self.streams.iter().filter(move |s| s.codec_type.as_deref() == Some(codec_type))
+    }
+
+    /// The length of the container
+    pub fn duration(&self) -> Option<Duration> {
+        seconds(self.format.duration.as_deref())
+    }
+
+    /// The codec of the first audio stream, if we know it
+    pub fn audio_codec(&self) -> Option<AudioCodec> {
+        self.streams_of("audio").next()?.audio_codec()
+    }
+
+    /// Title, duration and streams, in the shape of the native video readers
+    pub fn video_info(&self) -> VideoInfo {
+        VideoInfo {
+            title: self.tag("title").map(str::to_string),
+            duration: self.duration(),
+            video: self
+                .streams_of("video")
+                // Cover art shows up as a single frame video stream
+                .filter(|s| s.tags.get("comment").is_none_or(|c| !c.starts_with("Cover")))
+                .map(|s| VideoStream {
+                    codec: s.codec_name.as_deref().and_then(|name| {
+                        <VideoCodec as strum::IntoEnumIterator>::iter().find(|c| c.matches_ffprobe_name(name))
+                    }),
+                    width: s.width.unwrap_or_default(),
+                    height: s.height.unwrap_or_default(),
+                })
+                .collect(),
+            audio: self.streams_of("audio").map(ProbeStream::audio_codec).collect(),
+        }
+    }
+
+    /// Chapters with their titles, numbered when untitled
+    pub fn chapters(&self) -> Vec<Chapter> {
+        self.chapters
+            .iter()
+            .enumerate()
+            .filter_map(|(i, chapter)| {
+                Some(Chapter {
+                    title: chapter.tags.get("title").cloned().unwrap_or_else(|| format!("Chapter {}", i + 1)),
+                    start: seconds(Some(&chapter.start_time))?,
+                })
+            })
+            .collect()
+    }
+
+    /// A container tag, falling back to the first audio stream's, ignoring case
+    ///
+    /// Ogg and Opus files carry their comments on the stream rather than the container.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        find_tag(&self.format.tags, key).or_else(|| find_tag(&self.streams_of("audio").next()?.tags, key))
+    }
+
+    /// Map ffprobe's tag names onto `Tags`
+    ///
+    /// ffmpeg renames the tags of most formats to its own keys, e.g. ID3's
+    /// `TPE2` and MP4's `aART` both become `album_artist`, while Vorbis
+    /// comments keep their names. Both are translated to Vorbis keys and
+    /// read like any other comment block.
+    pub fn tags(&self) -> Tags {
+        let stream_tags = self.streams_of("audio").next().map(|s| &s.tags);
+        let comments = self
+            .format
+            .tags
+            .iter()
+            .chain(stream_tags.into_iter().flatten())
+            .map(|(key, value)| (vorbis_key(key), value.clone()))
+            .filter(|(key, _)| !CONTAINER_KEYS.contains(&key.as_str()))
+            .collect();
+        Tags::from_vorbis(&VorbisComments { vendor: String::new(), comments })
+    }
+}
+
+impl ProbeStream {
+    /// The codec of an audio stream, if we know it
+    pub fn audio_codec(&self) -> Option<AudioCodec> {
+        let name = self.codec_name.as_deref()?;
+        <AudioCodec as strum::IntoEnumIterator>::iter().find(|c| c.matches_ffprobe_name(name))
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate.as_deref()?.parse().ok()
+    }
+}
+
+/// Runs ffprobe, by default whichever one is first on `PATH`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ffprobe {
+    program: PathBuf,
+}
+
+impl Default for Ffprobe {
+    fn default() -> Self {
+        Self::new("ffprobe")
+    }
+}
+
+impl Ffprobe {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self { program: program.into() }
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    /// The ffprobe invocation for a file, printing everything as JSON
+    pub fn command(&self, path: &Path) -> Result<tokio::process::Command> {
+        Ok(self.std_command(path)?.into())
+    }
+
+    /// Probe a file
+    pub async fn probe(&self, path: &Path) -> Result<Probe> {
+        let output = self.command(path)?.output().await?;
+        parse_output(output)
+    }
+
+    /// Probe a file, blocking the current thread until ffprobe exits
+    pub fn probe_blocking(&self, path: &Path) -> Result<Probe> {
+        let output = self.std_command(path)?.output()?;
+        parse_output(output)
+    }
+
+    fn std_command(&self, path: &Path) -> Result<std::process::Command> {
+        let mut cmd = std::process::Command::new(&self.program);
+        cmd.args(["-v", "quiet", "-print_format", "json", "-show_format", "-show_streams", "-show_chapters"]);
+        cmd.arg(path.to_str().ok_or(Error::InvalidFilePath)?);
+        Ok(cmd)
+    }
+}
+
+impl MetadataReader for Ffprobe {
+    fn read_tags(&self, path: &Path) -> Result<Tags> {
+        Ok(self.probe_blocking(path)?.tags())
+    }
+}
+
+fn parse_output(output: std::process::Output) -> Result<Probe> {
+    if !output.status.success() {
+        return Err(Error::Ffprobe(format!("could not read the file ({})", output.status)));
+    }
+    Probe::parse(&output.stdout)
+}
+
+fn find_tag<'a>(tags: &'a BTreeMap<String, String>, key: &str) -> Option<&'a str> {
+    tags.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str())
+}
+
+/// Parse a number of seconds as printed by ffprobe, e.g. `185.466000`
+fn seconds(value: Option<&str>) -> Option<Duration> {
+    value?.trim().parse::<f64>().ok().filter(|s| s.is_finite() && *s >= 0.0).map(Duration::from_secs_f64)
+}
+
+/// Translate an ffmpeg tag name to the Vorbis comment it corresponds to
+fn vorbis_key(key: &str) -> String {
+    let key = key.to_lowercase();
+    let vorbis = match key.as_str() {
+        "album_artist" | "album artist" => "ALBUMARTIST",
+        "track" => "TRACKNUMBER",
+        "disc" => "DISCNUMBER",
+        "title-sort" | "sort_name" => "TITLESORT",
+        "artist-sort" | "sort_artist" => "ARTISTSORT",
+        "album_artist-sort" | "sort_album_artist" => "ALBUMARTISTSORT",
+        "album-sort" | "sort_album" => "ALBUMSORT",
+        "musicbrainz track id" => "MUSICBRAINZ_TRACKID",
+        "musicbrainz release track id" => "MUSICBRAINZ_RELEASETRACKID",
+        "musicbrainz album id" => "MUSICBRAINZ_ALBUMID",
+        "musicbrainz release group id" => "MUSICBRAINZ_RELEASEGROUPID",
+        "musicbrainz artist id" => "MUSICBRAINZ_ARTISTID",
+        "musicbrainz album artist id" => "MUSICBRAINZ_ALBUMARTISTID",
+        _ => return key.to_uppercase(),
+    };
+    vorbis.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const M4A: &str = r#"{
+        "streams": [
+            {"index": 0, "codec_name": "alac", "codec_type": "audio", "sample_rate": "44100", "channels": 2,
+             "bits_per_raw_sample": "16", "duration": "382.000000", "tags": {"handler_name": "SoundHandler"}},
+            {"index": 1, "codec_name": "png", "codec_type": "video", "width": 600, "height": 600,
+             "tags": {"comment": "Cover (front)"}}
+        ],
+        "chapters": [
+            {"id": 0, "time_base": "1/1000", "start": 0, "start_time": "0.000000", "end_time": "12.500000", "tags": {"title": "Intro"}},
+            {"id": 1, "time_base": "1/1000", "start": 12500, "start_time": "12.500000", "end_time": "382.000000", "tags": {}}
+        ],
+        "format": {
+            "filename": "06 Money.m4a", "nb_streams": 2, "format_name": "mov,mp4,m4a,3gp,3g2,mj2",
+            "duration": "382.000000", "bit_rate": "1012345",
+            "tags": {"major_brand": "M4A ", "title": "Money", "artist": "Pink Floyd", "album_artist": "Pink Floyd",
+                     "track": "6/10", "disc": "1/1", "date": "1973", "sort_album": "Dark Side of the Moon, The",
+                     "MusicBrainz Album Id": "f5093c06", "iTunSMPB": "00000000"}
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_probe() {
+        let probe = Probe::parse(M4A.as_bytes()).unwrap();
+        assert_eq!(probe.duration(), Some(Duration::from_secs(382)));
+        assert_eq!(probe.audio_codec(), Some(AudioCodec::Alac));
+        assert_eq!(probe.streams_of("audio").next().unwrap().sample_rate(), Some(44_100));
+
+        // The cover art isn't a video track
+        assert!(probe.video_info().video.is_empty());
+
+        let chapters = probe.chapters();
+        assert_eq!(chapters[0], Chapter { title: "Intro".to_string(), start: Duration::ZERO });
+        assert_eq!(chapters[1].title, "Chapter 2");
+    }
+
+    #[test]
+    fn test_probe_tags() {
+        let tags = Probe::parse(M4A.as_bytes()).unwrap().tags();
+        assert_eq!(tags.title.as_deref(), Some("Money"));
+        assert_eq!(tags.album_artist.as_deref(), Some("Pink Floyd"));
+        assert_eq!((tags.track_number, tags.track_total), (Some(6), Some(10)));
+        assert_eq!(tags.sort_album.as_deref(), Some("Dark Side of the Moon, The"));
+        assert_eq!(tags.musicbrainz.release.as_deref(), Some("f5093c06"));
+        assert_eq!(tags.custom, vec![("ITUNSMPB".to_string(), "00000000".to_string())]);
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        assert!(matches!(Probe::parse(b"not json"), Err(Error::Json(_))));
+    }
+}
@@ -0,0 +1,597 @@
+//! Native FLAC metadata blocks
+//!
+//! A FLAC file is the `fLaC` marker followed by metadata blocks, each a one
+//! byte header (last-block flag and block type) and a 24 bit length. The
+//! audio frames start after the block with the last-block flag set. Some
+//! taggers put an ID3v2 tag in front of the marker, which is skipped when
+//! reading and kept when writing.
+//!
+//! Tags are written back into the VORBIS_COMMENT block. When the new
+//! metadata fits in the space taken by the old metadata and its PADDING
+//! block, only that region is overwritten, otherwise the file is rewritten.
+
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::metadata::cue::{CueSheet, CueTrack};
+use crate::metadata::rewrite_atomically;
+use crate::metadata::vorbis::VorbisComments;
+use crate::sniff::id3v2_tag_len;
+use crate::{Error, Result};
+
+pub const STREAMINFO: u8 = 0;
+pub const PADDING: u8 = 1;
+pub const VORBIS_COMMENT: u8 = 4;
+pub const CUESHEET: u8 = 5;
+pub const PICTURE: u8 = 6;
+
+/// Padding left behind when a file has to be rewritten, so later edits fit in place
+const DEFAULT_PADDING: usize = 8192;
+/// Block lengths are 24 bits
+const MAX_BLOCK_LEN: usize = 0xFF_FFFF;
+
+/// Stream properties from the mandatory STREAMINFO block
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
+    /// Samples per channel, zero if the encoder didn't know
+    pub total_samples: u64,
+    /// MD5 of the unencoded audio, all zero if it wasn't computed
+    pub md5: [u8; 16],
+}
+
+impl StreamInfo {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 34 {
+            return Err(Error::InvalidMetadata("truncated flac streaminfo block".to_string()));
+        }
+        // 20 bits of sample rate, 3 bits of channels - 1, 5 bits of bits per sample - 1, 36 bits of samples
+        let packed = u64::from_be_bytes(data[10..18].try_into().unwrap_or_default());
+        Ok(Self {
+            min_block_size: u16::from_be_bytes([data[0], data[1]]),
+            max_block_size: u16::from_be_bytes([data[2], data[3]]),
+            sample_rate: (packed >> 44) as u32,
+            channels: ((packed >> 41) & 0x07) as u8 + 1,
+            bits_per_sample: ((packed >> 36) & 0x1F) as u8 + 1,
+            total_samples: packed & 0xF_FFFF_FFFF,
+            md5: data[18..34].try_into().unwrap_or_default(),
+        })
+    }
+
+    /// Length of the audio, if the sample count is known
+    pub fn duration(&self) -> Option<Duration> {
+        (self.sample_rate > 0 && self.total_samples > 0)
+            .then(|| samples_to_duration(self.total_samples, self.sample_rate))
+    }
+}
+
+/// The length of `samples` at `sample_rate`, scaled in two parts so long streams can't overflow
+fn samples_to_duration(samples: u64, sample_rate: u32) -> Duration {
+    let rate = sample_rate as u64;
+    Duration::from_secs(samples / rate) + Duration::from_nanos(samples % rate * 1_000_000_000 / rate)
+}
+
+/// An embedded picture from a PICTURE block
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picture {
+    /// The ID3v2 APIC picture type, 3 is the front cover
+    pub picture_type: u32,
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = BeReader { bytes: data, offset: 0 };
+        let picture_type = reader.u32()?;
+        let mime_len = reader.u32()? as usize;
+        let mime_type = String::from_utf8_lossy(reader.take(mime_len)?).to_string();
+        let description_len = reader.u32()? as usize;
+        let description = String::from_utf8_lossy(reader.take(description_len)?).to_string();
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        // Colour depth and palette size
+        reader.take(8)?;
+        let data_len = reader.u32()? as usize;
+        let data = reader.take(data_len)?.to_vec();
+
+        Ok(Self { picture_type, mime_type, description, width, height, data })
+    }
+
+    /// Encode the picture as the payload of a PICTURE block
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.picture_type.to_be_bytes().to_vec();
+        for field in [self.mime_type.as_bytes(), self.description.as_bytes()] {
+            out.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            out.extend_from_slice(field);
+        }
+        // Width and height, then colour depth and palette size which are left unknown
+        for value in [self.width, self.height, 0, 0, self.data.len() as u32] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        out.extend_from_slice(&self.data);
+        out
+    }
+}
+
+/// The parsed metadata of a FLAC file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlacMetadata {
+    pub stream_info: StreamInfo,
+    pub comments: Option<VorbisComments>,
+    pub pictures: Vec<Picture>,
+}
+
+/// A raw metadata block
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataBlock {
+    pub block_type: u8,
+    pub data: Vec<u8>,
+}
+
+/// Read every metadata block of a FLAC file
+pub fn read_blocks(path: &Path) -> Result<Option<Vec<MetadataBlock>>> {
+    read_blocks_where(path, |_| true)
+}
+
+/// Read the metadata blocks whose type passes `wanted`, seeking past the rest
+///
+/// Pictures and padding can be megabytes, so skipping them keeps tag reads cheap.
+pub fn read_blocks_where(path: &Path, wanted: impl Fn(u8) -> bool) -> Result<Option<Vec<MetadataBlock>>> {
+    Ok(read_blocks_from(path, wanted)?.map(|(_, blocks)| blocks))
+}
+
+/// Like `read_blocks_where`, also giving the offset of the `fLaC` marker
+fn read_blocks_from(path: &Path, wanted: impl Fn(u8) -> bool) -> Result<Option<(u64, Vec<MetadataBlock>)>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+
+    let mut id3 = [0u8; 10];
+    if reader.read_exact(&mut id3).is_err() {
+        return Ok(None);
+    }
+    let start = id3v2_tag_len(&id3).unwrap_or(0);
+    reader.seek(SeekFrom::Start(start))?;
+
+    let mut marker = [0u8; 4];
+    if reader.read_exact(&mut marker).is_err() || &marker != b"fLaC" {
+        return Ok(None);
+    }
+
+    let mut blocks = Vec::new();
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header)?;
+        let block_type = header[0] & 0x7F;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        if wanted(block_type) {
+            let mut data = vec![0u8; len];
+            reader.read_exact(&mut data)?;
+            blocks.push(MetadataBlock { block_type, data });
+        } else {
+            reader.seek(SeekFrom::Current(len as i64))?;
+        }
+
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+
+    Ok(Some((start, blocks)))
+}
+
+/// Read the stream info, Vorbis comments and pictures of a FLAC file
+pub fn read_metadata(path: &Path) -> Result<Option<FlacMetadata>> {
+    let Some(blocks) = read_blocks_where(path, |t| matches!(t, STREAMINFO | VORBIS_COMMENT | PICTURE))? else {
+        return Ok(None);
+    };
+
+    let stream_info = blocks
+        .iter()
+        .find(|b| b.block_type == STREAMINFO)
+        .map(|b| StreamInfo::parse(&b.data))
+        .ok_or_else(|| Error::InvalidMetadata("flac file has no streaminfo block".to_string()))??;
+    let comments = blocks
+        .iter()
+        .find(|b| b.block_type == VORBIS_COMMENT)
+        .map(|b| VorbisComments::parse(&b.data))
+        .transpose()?;
+    let pictures = blocks
+        .iter()
+        .filter(|b| b.block_type == PICTURE)
+        .map(|b| Picture::parse(&b.data))
+        .collect::<Result<_>>()?;
+
+    Ok(Some(FlacMetadata { stream_info, comments, pictures }))
+}
+
+/// Read only the Vorbis comments of a FLAC file
+pub fn read_comments(path: &Path) -> Result<Option<VorbisComments>> {
+    let Some(blocks) = read_blocks_where(path, |t| t == VORBIS_COMMENT)? else {
+        return Ok(None);
+    };
+    blocks.first().map(|b| VorbisComments::parse(&b.data)).transpose()
+}
+
+/// Replace the Vorbis comments of a FLAC file
+///
+/// Every other block, pictures included, is kept as it is. A file without a
+/// comment block gets one straight after STREAMINFO.
+pub fn write_comments(path: &Path, comments: &VorbisComments) -> Result<()> {
+    let data = comments.to_bytes();
+    write_blocks(path, |blocks| {
+        match blocks.iter().position(|b| b.block_type == VORBIS_COMMENT) {
+            Some(i) => {
+                blocks[i].data = data;
+                let mut seen = 0;
+                blocks.retain(|b| b.block_type != VORBIS_COMMENT || { seen += 1; seen == 1 });
+            }
+            None => blocks.insert(1.min(blocks.len()), MetadataBlock { block_type: VORBIS_COMMENT, data }),
+        }
+    })
+}
+
+/// Rewrite the metadata blocks of a FLAC file
+///
+/// `edit` gets every block except PADDING in file order. When the edited
+/// blocks fit in the space taken by the old ones, only the metadata region
+/// is overwritten and the slack becomes padding, otherwise the file is
+/// rewritten with fresh padding so later edits fit in place. A leading ID3v2
+/// tag is kept either way.
+pub fn write_blocks(path: &Path, edit: impl FnOnce(&mut Vec<MetadataBlock>)) -> Result<()> {
+    let (start, mut blocks) =
+        read_blocks_from(path, |_| true)?.ok_or_else(|| Error::InvalidMetadata("not a flac file".to_string()))?;
+    let metadata_offset = start + 4;
+    let audio_offset = metadata_offset + blocks.iter().map(|b| 4 + b.data.len() as u64).sum::<u64>();
+
+    blocks.retain(|b| b.block_type != PADDING);
+    edit(&mut blocks);
+    if blocks.first().map(|b| b.block_type) != Some(STREAMINFO) {
+        return Err(Error::InvalidMetadata("flac streaminfo must be the first block".to_string()));
+    }
+
+    // Reuse the old metadata region if the blocks fit, with any slack as padding
+    let used: u64 = blocks.iter().map(|b| 4 + b.data.len() as u64).sum();
+    let available = audio_offset - metadata_offset;
+    if used == available || used + 4 <= available {
+        let padding = available.checked_sub(used + 4).map(|p| p as usize);
+        let metadata = encode_blocks(&blocks, padding)?;
+
+        let mut file = fs::OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(metadata_offset))?;
+        file.write_all(&metadata)?;
+        file.sync_all()?;
+        return Ok(());
+    }
+
+    let metadata = encode_blocks(&blocks, Some(DEFAULT_PADDING))?;
+    rewrite_atomically(path, |out| {
+        let mut original = fs::File::open(path)?;
+        io::copy(&mut (&mut original).take(start), out)?;
+        original.seek(SeekFrom::Start(audio_offset))?;
+        out.write_all(b"fLaC")?;
+        out.write_all(&metadata)?;
+        io::copy(&mut original, out)?;
+        Ok(())
+    })
+}
+
+/// Encode metadata blocks, followed by a PADDING block of `padding` bytes if given
+fn encode_blocks(blocks: &[MetadataBlock], padding: Option<usize>) -> Result<Vec<u8>> {
+    let zeros = vec![0u8; padding.unwrap_or_default()];
+    let padding = padding.map(|_| (PADDING, zeros.as_slice()));
+
+    let all: Vec<(u8, &[u8])> = blocks.iter().map(|b| (b.block_type, b.data.as_slice())).chain(padding).collect();
+    let mut out = Vec::new();
+    for (i, (block_type, data)) in all.iter().enumerate() {
+        if data.len() > MAX_BLOCK_LEN {
+            return Err(Error::InvalidMetadata(format!("flac metadata block of {} bytes is too large", data.len())));
+        }
+        let last = if i + 1 == all.len() { 0x80 } else { 0 };
+        out.push(block_type | last);
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(data);
+    }
+    Ok(out)
+}
+
+/// Read a cue sheet embedded in a FLAC file
+///
+/// The `CUESHEET` Vorbis comment carries the full text with titles and is
+/// preferred, the binary CUESHEET block only has track numbers and offsets.
+pub fn read_cue_sheet(path: &Path) -> Result<Option<CueSheet>> {
+    let Some(blocks) = read_blocks_where(path, |t| matches!(t, STREAMINFO | VORBIS_COMMENT | CUESHEET))? else {
+        return Ok(None);
+    };
+
+    let block = |block_type: u8| blocks.iter().find(|b| b.block_type == block_type).map(|b| b.data.as_slice());
+
+    if let Some(comments) = block(VORBIS_COMMENT) {
+        if let Some(text) = VorbisComments::parse(comments)?.get("CUESHEET") {
+            return CueSheet::parse(text).map(Some);
+        }
+    }
+
+    let sample_rate = block(STREAMINFO).map(StreamInfo::parse).transpose()?.map(|info| info.sample_rate);
+    match (block(CUESHEET), sample_rate) {
+        (Some(data), Some(sample_rate)) if sample_rate > 0 => Ok(Some(parse_cuesheet_block(data, sample_rate)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Parse a binary CUESHEET block, whose offsets count samples
+fn parse_cuesheet_block(data: &[u8], sample_rate: u32) -> Result<CueSheet> {
+    let invalid = || Error::InvalidMetadata("truncated flac cuesheet block".to_string());
+    let to_duration = |samples: u64| samples_to_duration(samples, sample_rate);
+
+    // Catalog number, lead-in samples, CD flag and reserved bits
+    let track_count = *data.get(395).ok_or_else(invalid)? as usize;
+    let mut offset = 396;
+
+    let mut sheet = CueSheet::default();
+    for _ in 0..track_count {
+        let track = data.get(offset..offset + 36).ok_or_else(invalid)?;
+        let start = u64::from_be_bytes(track[0..8].try_into().map_err(|_| invalid())?);
+        let number = track[8] as u32;
+        let isrc = String::from_utf8_lossy(&track[9..21]).trim_matches('\0').to_string();
+        let index_count = track[35] as usize;
+        offset += 36;
+
+        let mut indexes = Vec::new();
+        for _ in 0..index_count {
+            let index = data.get(offset..offset + 12).ok_or_else(invalid)?;
+            let index_offset = u64::from_be_bytes(index[0..8].try_into().map_err(|_| invalid())?);
+            indexes.push((index[8] as u32, to_duration(start + index_offset)));
+            offset += 12;
+        }
+
+        // Track 170 (or 255 for non-CD sheets) is the lead-out
+        if number == 170 || number == 255 {
+            continue;
+        }
+        sheet.tracks.push(CueTrack {
+            number,
+            isrc: (!isrc.is_empty()).then_some(isrc),
+            indexes,
+            ..Default::default()
+        });
+    }
+
+    Ok(sheet)
+}
+
+/// Minimal big endian cursor for PICTURE blocks
+struct BeReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> BeReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.offset.checked_add(len).filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| Error::InvalidMetadata("truncated flac picture block".to_string()))?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Build a FLAC file from the given metadata blocks
+#[cfg(test)]
+pub(crate) fn flac(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let mut out = b"fLaC".to_vec();
+    for (i, (block_type, data)) in blocks.iter().enumerate() {
+        let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+        out.push(block_type | last);
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+/// Build a STREAMINFO block for 16 bit stereo audio
+#[cfg(test)]
+pub(crate) fn streaminfo(sample_rate: u32, total_samples: u64) -> Vec<u8> {
+    let mut data = vec![0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0];
+    // 20 bits of sample rate, 3 bits of channels - 1, 5 bits of bits per sample - 1, 36 bits of samples
+    let packed = (sample_rate as u64) << 44 | 1 << 41 | 15 << 36 | (total_samples & 0xF_FFFF_FFFF);
+    data.extend_from_slice(&packed.to_be_bytes());
+    data.extend_from_slice(&[0; 16]);
+    data
+}
+
+/// Build a PICTURE block
+#[cfg(test)]
+pub(crate) fn picture(picture_type: u32, mime_type: &str, data: &[u8]) -> Vec<u8> {
+    let mut out = picture_type.to_be_bytes().to_vec();
+    out.extend_from_slice(&(mime_type.len() as u32).to_be_bytes());
+    out.extend_from_slice(mime_type.as_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 24, 0, 0, 0, 0]);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::vorbis::encode;
+    use tempfile::TempDir;
+
+    fn cuesheet_block(sample_rate: u64, starts: &[u64]) -> Vec<u8> {
+        let mut data = vec![0u8; 395];
+        data.push(starts.len() as u8 + 1);
+        for (i, start) in starts.iter().chain([&(starts.last().unwrap() + sample_rate)]).enumerate() {
+            let number = if i == starts.len() { 170 } else { i as u8 + 1 };
+            data.extend_from_slice(&(start * sample_rate).to_be_bytes());
+            data.push(number);
+            data.extend_from_slice(&[0; 26]);
+            data.push(if number == 170 { 0 } else { 1 });
+            if number != 170 {
+                data.extend_from_slice(&[0; 8]);
+                data.extend_from_slice(&[1, 0, 0, 0]);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_read_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        fs::write(&path, flac(&[(STREAMINFO, streaminfo(44_100, 0)), (PADDING, vec![0; 8])])).unwrap();
+
+        let blocks = read_blocks(&path).unwrap().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1], MetadataBlock { block_type: PADDING, data: vec![0; 8] });
+        assert_eq!(StreamInfo::parse(&blocks[0].data).unwrap().sample_rate, 44_100);
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        let comments = encode("reference libFLAC", &[("TITLE", "Song"), ("TRACKNUMBER", "3")]);
+        fs::write(&path, flac(&[
+            (STREAMINFO, streaminfo(96_000, 96_000 * 185)),
+            (PADDING, vec![0; 1024]),
+            (VORBIS_COMMENT, comments),
+            (PICTURE, picture(3, "image/jpeg", b"\xFF\xD8\xFF")),
+        ])).unwrap();
+
+        let metadata = read_metadata(&path).unwrap().unwrap();
+        let info = &metadata.stream_info;
+        assert_eq!((info.sample_rate, info.channels, info.bits_per_sample), (96_000, 2, 16));
+        assert_eq!(info.duration(), Some(Duration::from_secs(185)));
+
+        assert_eq!(metadata.comments.unwrap().get("title"), Some("Song"));
+        assert_eq!(metadata.pictures.len(), 1);
+        assert_eq!(metadata.pictures[0].mime_type, "image/jpeg");
+        assert_eq!(metadata.pictures[0].data, b"\xFF\xD8\xFF");
+
+        assert_eq!(read_comments(&path).unwrap().unwrap().get("TRACKNUMBER"), Some("3"));
+    }
+
+    #[test]
+    fn test_duration_of_long_stream() {
+        // The largest sample count STREAMINFO holds
+        let info = StreamInfo::parse(&streaminfo(1, 0xF_FFFF_FFFF)).unwrap();
+        assert_eq!(info.duration(), Some(Duration::from_secs(0xF_FFFF_FFFF)));
+        let info = StreamInfo::parse(&streaminfo(3, 4)).unwrap();
+        assert_eq!(info.duration(), Some(Duration::from_nanos(1_333_333_333)));
+    }
+
+    #[test]
+    fn test_read_cue_sheet_from_comment() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("image.flac");
+        let comments = encode("reference libFLAC", &[("CUESHEET", "TITLE \"Image\"\nTRACK 01 AUDIO\nINDEX 01 00:00:00")]);
+        fs::write(&path, flac(&[(STREAMINFO, streaminfo(44_100, 0)), (VORBIS_COMMENT, comments)])).unwrap();
+
+        let sheet = read_cue_sheet(&path).unwrap().unwrap();
+        assert_eq!(sheet.title.as_deref(), Some("Image"));
+        assert_eq!(sheet.tracks.len(), 1);
+    }
+
+    #[test]
+    fn test_read_cue_sheet_from_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("image.flac");
+        fs::write(&path, flac(&[(STREAMINFO, streaminfo(48_000, 0)), (CUESHEET, cuesheet_block(48_000, &[0, 200]))])).unwrap();
+
+        let sheet = read_cue_sheet(&path).unwrap().unwrap();
+        let starts: Vec<_> = sheet.tracks.iter().map(|t| (t.number, t.start())).collect();
+        assert_eq!(starts, vec![(1, Some(Duration::ZERO)), (2, Some(Duration::from_secs(200)))]);
+    }
+
+    #[test]
+    fn test_write_comments_in_place() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        let mut bytes = flac(&[
+            (STREAMINFO, streaminfo(44_100, 0)),
+            (VORBIS_COMMENT, encode("libFLAC", &[("TITLE", "Song")])),
+            (PADDING, vec![0; 256]),
+        ]);
+        bytes.extend_from_slice(b"\xFF\xF8audio");
+        fs::write(&path, &bytes).unwrap();
+
+        let mut comments = read_comments(&path).unwrap().unwrap();
+        comments.set("ALBUMARTIST", &["Pink Floyd"]);
+        write_comments(&path, &comments).unwrap();
+
+        // The padding absorbs the new comment, so nothing after the metadata moves
+        let written = fs::read(&path).unwrap();
+        assert_eq!(written.len(), bytes.len());
+        assert!(written.ends_with(b"\xFF\xF8audio"));
+        assert_eq!(read_comments(&path).unwrap().unwrap().get("albumartist"), Some("Pink Floyd"));
+        assert_eq!(read_blocks(&path).unwrap().unwrap().last().unwrap().block_type, PADDING);
+    }
+
+    #[test]
+    fn test_write_comments_rewrites_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        let mut bytes = flac(&[
+            (STREAMINFO, streaminfo(44_100, 0)),
+            (PICTURE, picture(3, "image/png", b"\x89PNG")),
+        ]);
+        bytes.extend_from_slice(b"\xFF\xF8audio");
+        fs::write(&path, &bytes).unwrap();
+
+        let comments = VorbisComments { vendor: "arcanio".to_string(), comments: vec![("TITLE".to_string(), "Song".to_string())] };
+        write_comments(&path, &comments).unwrap();
+
+        let blocks: Vec<u8> = read_blocks(&path).unwrap().unwrap().iter().map(|b| b.block_type).collect();
+        assert_eq!(blocks, vec![STREAMINFO, VORBIS_COMMENT, PICTURE, PADDING]);
+        assert_eq!(read_metadata(&path).unwrap().unwrap().pictures[0].data, b"\x89PNG");
+        assert!(fs::read(&path).unwrap().ends_with(b"\xFF\xF8audio"));
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_flac_after_id3v2() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        let mut id3 = b"ID3\x04\x00\x00\x00\x00\x00\x0A".to_vec();
+        id3.extend_from_slice(&[0; 10]);
+        let mut bytes = id3.clone();
+        bytes.extend_from_slice(&flac(&[
+            (STREAMINFO, streaminfo(44_100, 0)),
+            (VORBIS_COMMENT, encode("libFLAC", &[("TITLE", "Song")])),
+            (PADDING, vec![0; 64]),
+        ]));
+        bytes.extend_from_slice(b"\xFF\xF8audio");
+        fs::write(&path, &bytes).unwrap();
+
+        assert_eq!(read_metadata(&path).unwrap().unwrap().stream_info.sample_rate, 44_100);
+        let mut comments = read_comments(&path).unwrap().unwrap();
+        assert_eq!(comments.get("TITLE"), Some("Song"));
+
+        // Once in place, then too large for the padding so the file is rewritten
+        for artist in ["Pink Floyd", &"x".repeat(256)] {
+            comments.set("ARTIST", &[artist]);
+            write_comments(&path, &comments).unwrap();
+
+            let written = fs::read(&path).unwrap();
+            assert!(written.starts_with(&id3));
+            assert!(written.ends_with(b"\xFF\xF8audio"));
+            assert_eq!(read_comments(&path).unwrap().unwrap().get("ARTIST"), Some(artist));
+        }
+    }
+}
@@ -0,0 +1,626 @@
+//! ID3v1 and ID3v2.3/2.4 tag reading
+//!
+//! ID3v2 tags live at the start of a file and are made of frames keyed by a
+//! four character id (`TIT2`, `TPE1`, `APIC`, ...). ID3v1 tags are a fixed
+//! 128 byte block at the very end of the file.
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::metadata::Chapter;
+use crate::sniff::{id3v2_tag_len, synchsafe_u32};
+use crate::{Error, Result};
+
+/// Size of an ID3v1 tag, which always sits at the end of the file
+const ID3V1_LEN: u64 = 128;
+
+/// Genre names referenced by index from ID3v1 tags and `TCON` frames
+const ID3V1_GENRES: &[&str] = &[
+    "Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop",
+    "Jazz", "Metal", "New Age", "Oldies", "Other", "Pop", "R&B", "Rap", "Reggae", "Rock",
+    "Techno", "Industrial", "Alternative", "Ska", "Death Metal", "Pranks", "Soundtrack",
+    "Euro-Techno", "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk", "Fusion", "Trance",
+    "Classical", "Instrumental", "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise",
+    "AlternRock", "Bass", "Soul", "Punk", "Space", "Meditative", "Instrumental Pop",
+    "Instrumental Rock", "Ethnic", "Gothic", "Darkwave", "Techno-Industrial", "Electronic",
+    "Pop-Folk", "Eurodance", "Dream", "Southern Rock", "Comedy", "Cult", "Gangsta",
+    "Top 40", "Christian Rap", "Pop/Funk", "Jungle", "Native American", "Cabaret",
+    "New Wave", "Psychadelic", "Rave", "Showtunes", "Trailer", "Lo-Fi", "Tribal",
+    "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock",
+];
+
+/// Look up the name of an ID3v1 genre index
+pub fn genre_name(index: u8) -> Option<&'static str> {
+    ID3V1_GENRES.get(index as usize).copied()
+}
+
+/// The fixed layout tag stored in the last 128 bytes of a file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Id3v1Tag {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub year: Option<String>,
+    pub comment: Option<String>,
+    /// Only present in ID3v1.1 tags
+    pub track: Option<u8>,
+    pub genre: Option<u8>,
+}
+
+impl Id3v1Tag {
+    /// Parse a 128 byte ID3v1 block
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ID3V1_LEN as usize || &bytes[0..3] != b"TAG" {
+            return None;
+        }
+
+        // ID3v1.1 steals the last two comment bytes for a zero byte and the track number
+        let (comment, track) = if bytes[125] == 0 && bytes[126] != 0 {
+            (&bytes[97..125], Some(bytes[126]))
+        } else {
+            (&bytes[97..127], None)
+        };
+
+        Some(Self {
+            title: fixed_field(&bytes[3..33]),
+            artist: fixed_field(&bytes[33..63]),
+            album: fixed_field(&bytes[63..93]),
+            year: fixed_field(&bytes[93..97]),
+            comment: fixed_field(comment),
+            track,
+            genre: (bytes[127] != 0xFF).then_some(bytes[127]),
+        })
+    }
+}
+
+/// Content of a single ID3v2 frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameContent {
+    /// Text information frames (`T***`), one entry per null separated value
+    Text(Vec<String>),
+    /// User defined text (`TXXX`)
+    UserText { description: String, values: Vec<String> },
+    /// Comments (`COMM`) and unsynchronised lyrics (`USLT`)
+    Comment { language: String, description: String, text: String },
+    /// Chapters (`CHAP`) with their own sub frames, usually a `TIT2` title
+    Chapter { element_id: String, start_ms: u32, end_ms: u32, frames: Vec<Id3v2Frame> },
+    /// Attached pictures (`APIC`)
+    Picture { mime_type: String, picture_type: u8, description: String, data: Vec<u8> },
+    /// Any frame we don't interpret, kept verbatim
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Id3v2Frame {
+    pub id: String,
+    pub content: FrameContent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Id3v2Tag {
+    /// Major version, either 3 or 4
+    pub version: u8,
+    pub frames: Vec<Id3v2Frame>,
+}
+
+impl Id3v2Tag {
+    /// Parse a complete ID3v2 tag including its 10 byte header
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let tag_len = id3v2_tag_len(bytes)
+            .ok_or_else(|| Error::InvalidMetadata("missing ID3v2 header".to_string()))?;
+
+        let version = bytes[3];
+        if version != 3 && version != 4 {
+            return Err(Error::InvalidMetadata(format!("unsupported ID3v2.{} tag", version)));
+        }
+
+        let flags = bytes[5];
+        let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+        let end = (tag_len as usize - footer).min(bytes.len());
+        let mut body = bytes[10..end].to_vec();
+
+        // v2.3 applies unsynchronisation to the whole tag, v2.4 does it per frame
+        if version == 3 && flags & 0x80 != 0 {
+            body = remove_unsynchronisation(&body);
+        }
+
+        let mut offset = 0;
+        if flags & 0x40 != 0 {
+            offset = extended_header_len(&body, version)?;
+        }
+
+        let frames = parse_frames(body.get(offset..).unwrap_or_default(), version);
+        Ok(Self { version, frames })
+    }
+
+    /// The first frame with the given id
+    pub fn get(&self, id: &str) -> Option<&FrameContent> {
+        self.frames.iter().find(|f| f.id == id).map(|f| &f.content)
+    }
+
+    /// The first value of a text information frame
+    pub fn text(&self, id: &str) -> Option<&str> {
+        match self.get(id)? {
+            FrameContent::Text(values) => values.first().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// All values of a `TXXX` frame with a matching description
+    pub fn user_text(&self, description: &str) -> Option<&[String]> {
+        self.frames.iter().find_map(|f| match &f.content {
+            FrameContent::UserText { description: d, values } if d.eq_ignore_ascii_case(description) => {
+                Some(values.as_slice())
+            }
+            _ => None,
+        })
+    }
+
+    /// Chapters from `CHAP` frames, ordered by start time
+    pub fn chapters(&self) -> Vec<Chapter> {
+        let mut chapters: Vec<Chapter> = self.frames.iter().filter_map(|f| match &f.content {
+            FrameContent::Chapter { element_id, start_ms, frames, .. } => {
+                let title = frames.iter().find_map(|f| match (&f.id[..], &f.content) {
+                    ("TIT2", FrameContent::Text(values)) => values.first().cloned(),
+                    _ => None,
+                });
+                Some(Chapter {
+                    title: title.unwrap_or_else(|| element_id.clone()),
+                    start: Duration::from_millis(*start_ms as u64),
+                })
+            }
+            _ => None,
+        }).collect();
+        chapters.sort_by_key(|c| c.start);
+        chapters
+    }
+
+    /// Resolve the `TCON` frame, expanding ID3v1 genre references like `(17)`
+    pub fn genre(&self) -> Option<String> {
+        let raw = self.text("TCON")?;
+        let index = raw.trim_start_matches('(').split(')').next().unwrap_or(raw);
+        match index.parse::<u8>().ok().and_then(genre_name) {
+            Some(name) => Some(name.to_string()),
+            None => Some(raw.to_string()),
+        }
+    }
+}
+
+/// Read the ID3v2 tag at the start of a file, if one is present
+pub fn read_id3v2(path: &Path) -> Result<Option<Id3v2Tag>> {
+    let mut file = fs::File::open(path)?;
+
+    let mut header = [0u8; 10];
+    if file.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+    let Some(len) = id3v2_tag_len(&header) else {
+        return Ok(None);
+    };
+
+    // A truncated file still gives us the frames before the cut
+    let mut bytes = header.to_vec();
+    file.take(len - 10).read_to_end(&mut bytes)?;
+
+    Id3v2Tag::parse(&bytes).map(Some)
+}
+
+/// Read the ID3v1 tag at the end of a file, if one is present
+pub fn read_id3v1(path: &Path) -> Result<Option<Id3v1Tag>> {
+    let mut file = fs::File::open(path)?;
+    if file.metadata()?.len() < ID3V1_LEN {
+        return Ok(None);
+    }
+
+    let mut bytes = [0u8; ID3V1_LEN as usize];
+    file.seek(SeekFrom::End(-(ID3V1_LEN as i64)))?;
+    file.read_exact(&mut bytes)?;
+
+    Ok(Id3v1Tag::parse(&bytes))
+}
+
+/// Size of the v2.3/v2.4 extended header that follows the tag header
+fn extended_header_len(body: &[u8], version: u8) -> Result<usize> {
+    let size_bytes = body
+        .get(0..4)
+        .ok_or_else(|| Error::InvalidMetadata("truncated extended header".to_string()))?;
+    match version {
+        // v2.4 sizes are synchsafe and include the size field itself
+        4 => synchsafe_u32(size_bytes)
+            .map(|s| s as usize)
+            .ok_or_else(|| Error::InvalidMetadata("invalid extended header size".to_string())),
+        _ => Ok(u32::from_be_bytes([size_bytes[0], size_bytes[1], size_bytes[2], size_bytes[3]]) as usize + 4),
+    }
+}
+
+/// Undo per frame encodings, returning None for frames we can't decode
+fn frame_data(raw: &[u8], version: u8, format_flags: u8) -> Option<Vec<u8>> {
+    match version {
+        4 => {
+            // Compression and encryption aren't supported
+            if format_flags & 0x0C != 0 {
+                return None;
+            }
+            let mut data = raw;
+            if format_flags & 0x01 != 0 {
+                data = data.get(4..)?;
+            }
+            if format_flags & 0x02 != 0 {
+                Some(remove_unsynchronisation(data))
+            } else {
+                Some(data.to_vec())
+            }
+        }
+        _ => {
+            if format_flags & 0xC0 != 0 {
+                return None;
+            }
+            // Skip the group identifier byte
+            if format_flags & 0x20 != 0 {
+                return raw.get(1..).map(<[u8]>::to_vec);
+            }
+            Some(raw.to_vec())
+        }
+    }
+}
+
+/// Parse the frames of a tag body or `CHAP` frame up to the padding, keeping those before any frame with a bad size
+fn parse_frames(body: &[u8], version: u8) -> Vec<Id3v2Frame> {
+    let mut offset = 0;
+    let mut frames = Vec::new();
+    while offset + 10 <= body.len() {
+        let header = &body[offset..offset + 10];
+        // Padding fills the rest of the tag with zero bytes
+        if header[0] == 0 {
+            break;
+        }
+
+        let id = String::from_utf8_lossy(&header[0..4]).to_string();
+        let size = match version {
+            4 => synchsafe_u32(&header[4..8]),
+            _ => Some(u32::from_be_bytes([header[4], header[5], header[6], header[7]])),
+        };
+
+        // Taggers truncate tags and miscount sizes, what came before is still good
+        offset += 10;
+        let Some(size) = size.map(|size| size as usize).filter(|size| offset + size <= body.len()) else {
+            break;
+        };
+
+        if let Some(data) = frame_data(&body[offset..offset + size], version, header[9]) {
+            frames.push(Id3v2Frame { content: parse_frame(&id, &data, version), id });
+        }
+        offset += size;
+    }
+    frames
+}
+
+fn parse_frame(id: &str, data: &[u8], version: u8) -> FrameContent {
+    let parsed = match id {
+        "CHAP" => parse_chapter(data, version),
+        "TXXX" => parse_user_text(data),
+        "COMM" | "USLT" => parse_comment(data),
+        "APIC" => parse_picture(data),
+        _ if id.starts_with('T') => data
+            .split_first()
+            .map(|(encoding, text)| FrameContent::Text(split_values(*encoding, text))),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| FrameContent::Binary(data.to_vec()))
+}
+
+fn parse_chapter(data: &[u8], version: u8) -> Option<FrameContent> {
+    let (element_id, rest) = split_terminated(0, data);
+    let times: Vec<u32> = rest
+        .get(0..16)?
+        .chunks_exact(4)
+        .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+        .collect();
+    Some(FrameContent::Chapter {
+        element_id: decode_latin1(element_id),
+        start_ms: times[0],
+        end_ms: times[1],
+        frames: parse_frames(&rest[16..], version),
+    })
+}
+
+fn parse_user_text(data: &[u8]) -> Option<FrameContent> {
+    let (encoding, rest) = data.split_first()?;
+    let (description, rest) = split_terminated(*encoding, rest);
+    Some(FrameContent::UserText {
+        description: decode_text(*encoding, description),
+        values: split_values(*encoding, rest),
+    })
+}
+
+fn parse_comment(data: &[u8]) -> Option<FrameContent> {
+    let (encoding, rest) = data.split_first()?;
+    let language = String::from_utf8_lossy(rest.get(0..3)?).to_string();
+    let (description, text) = split_terminated(*encoding, &rest[3..]);
+    Some(FrameContent::Comment {
+        language,
+        description: decode_text(*encoding, description),
+        text: decode_text(*encoding, text),
+    })
+}
+
+fn parse_picture(data: &[u8]) -> Option<FrameContent> {
+    let (encoding, rest) = data.split_first()?;
+    // The mime type is always ISO-8859-1
+    let (mime_type, rest) = split_terminated(0, rest);
+    let (picture_type, rest) = rest.split_first()?;
+    let (description, data) = split_terminated(*encoding, rest);
+    Some(FrameContent::Picture {
+        mime_type: decode_text(0, mime_type),
+        picture_type: *picture_type,
+        description: decode_text(*encoding, description),
+        data: data.to_vec(),
+    })
+}
+
+/// Split a null separated multi value text field
+fn split_values(encoding: u8, mut data: &[u8]) -> Vec<String> {
+    let mut values = Vec::new();
+    while !data.is_empty() {
+        let (value, rest) = split_terminated(encoding, data);
+        values.push(decode_text(encoding, value));
+        data = rest;
+    }
+    // Trailing terminators shouldn't produce empty values
+    while values.last().is_some_and(String::is_empty) {
+        values.pop();
+    }
+    values
+}
+
+/// Split `data` at the first string terminator for the given encoding
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    match encoding {
+        1 | 2 => {
+            let mut i = 0;
+            while i + 1 < data.len() {
+                if data[i] == 0 && data[i + 1] == 0 {
+                    return (&data[..i], &data[i + 2..]);
+                }
+                i += 2;
+            }
+            (data, &[])
+        }
+        _ => match data.iter().position(|b| *b == 0) {
+            Some(i) => (&data[..i], &data[i + 1..]),
+            None => (data, &[]),
+        },
+    }
+}
+
+/// Decode text using an ID3v2 encoding byte
+pub(crate) fn decode_text(encoding: u8, data: &[u8]) -> String {
+    match encoding {
+        1 => match data {
+            [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+            [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+            _ => decode_utf16(data, u16::from_le_bytes),
+        },
+        2 => decode_utf16(data, u16::from_be_bytes),
+        3 => String::from_utf8_lossy(data).to_string(),
+        _ => decode_latin1(data),
+    }
+}
+
+fn decode_utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = data.chunks_exact(2).map(|c| from_bytes([c[0], c[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Decode ISO-8859-1 text, where every byte maps to the same code point
+pub(crate) fn decode_latin1(data: &[u8]) -> String {
+    data.iter().map(|b| *b as char).collect()
+}
+
+/// Decode a fixed width ID3v1 field padded with zeros or spaces
+fn fixed_field(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    let value = decode_latin1(&data[..end]).trim_end().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// Replace every `0xFF 0x00` pair with `0xFF`
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        out.push(data[i]);
+        if data[i] == 0xFF && data.get(i + 1) == Some(&0x00) {
+            i += 1;
+        }
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn frame(version: u8, id: &str, data: &[u8]) -> Vec<u8> {
+        let mut out = id.as_bytes().to_vec();
+        let size = data.len() as u32;
+        match version {
+            4 => out.extend_from_slice(&[
+                ((size >> 21) & 0x7F) as u8,
+                ((size >> 14) & 0x7F) as u8,
+                ((size >> 7) & 0x7F) as u8,
+                (size & 0x7F) as u8,
+            ]),
+            _ => out.extend_from_slice(&size.to_be_bytes()),
+        }
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn tag(version: u8, frames: &[Vec<u8>], padding: usize) -> Vec<u8> {
+        let body: Vec<u8> = frames.concat();
+        let size = (body.len() + padding) as u32;
+        let mut out = vec![b'I', b'D', b'3', version, 0, 0];
+        out.extend_from_slice(&[
+            ((size >> 21) & 0x7F) as u8,
+            ((size >> 14) & 0x7F) as u8,
+            ((size >> 7) & 0x7F) as u8,
+            (size & 0x7F) as u8,
+        ]);
+        out.extend_from_slice(&body);
+        out.resize(out.len() + padding, 0);
+        out
+    }
+
+    #[test]
+    fn test_parse_id3v23_text_frames() {
+        let bytes = tag(3, &[
+            frame(3, "TIT2", b"\x00Song Title"),
+            frame(3, "TPE1", b"\x01\xFF\xFEA\x00r\x00t\x00"),
+            frame(3, "TRCK", b"\x0003/12"),
+        ], 32);
+
+        let tag = Id3v2Tag::parse(&bytes).unwrap();
+        assert_eq!(tag.version, 3);
+        assert_eq!(tag.text("TIT2"), Some("Song Title"));
+        assert_eq!(tag.text("TPE1"), Some("Art"));
+        assert_eq!(tag.text("TRCK"), Some("03/12"));
+    }
+
+    #[test]
+    fn test_parse_id3v24_multi_value_and_user_text() {
+        let bytes = tag(4, &[
+            frame(4, "TPE1", b"\x03First\x00Second\x00"),
+            frame(4, "TXXX", b"\x03MusicBrainz Album Id\x00abc-123"),
+            frame(4, "TCON", b"\x00(17)"),
+        ], 0);
+
+        let tag = Id3v2Tag::parse(&bytes).unwrap();
+        assert_eq!(tag.get("TPE1"), Some(&FrameContent::Text(vec!["First".to_string(), "Second".to_string()])));
+        assert_eq!(tag.user_text("musicbrainz album id"), Some(&["abc-123".to_string()][..]));
+        assert_eq!(tag.genre().as_deref(), Some("Rock"));
+    }
+
+    #[test]
+    fn test_parse_picture_and_comment() {
+        let bytes = tag(3, &[
+            frame(3, "APIC", b"\x00image/jpeg\x00\x03cover\x00\xFF\xD8\xFF"),
+            frame(3, "COMM", b"\x00engdesc\x00a comment"),
+        ], 0);
+
+        let tag = Id3v2Tag::parse(&bytes).unwrap();
+        assert_eq!(tag.get("APIC"), Some(&FrameContent::Picture {
+            mime_type: "image/jpeg".to_string(),
+            picture_type: 3,
+            description: "cover".to_string(),
+            data: vec![0xFF, 0xD8, 0xFF],
+        }));
+        assert_eq!(tag.get("COMM"), Some(&FrameContent::Comment {
+            language: "eng".to_string(),
+            description: "desc".to_string(),
+            text: "a comment".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_parse_chapters() {
+        let mut chap2 = b"ch2\x00".to_vec();
+        chap2.extend_from_slice(&[0, 0, 0x75, 0x30, 0, 0, 0xEA, 0x60, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        chap2.extend_from_slice(&frame(4, "TIT2", b"\x03Chapter Two"));
+        let mut chap1 = b"ch1\x00".to_vec();
+        chap1.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0x75, 0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let bytes = tag(4, &[frame(4, "CHAP", &chap2), frame(4, "CHAP", &chap1)], 0);
+        let chapters = Id3v2Tag::parse(&bytes).unwrap().chapters();
+
+        assert_eq!(chapters, vec![
+            Chapter { title: "ch1".to_string(), start: Duration::ZERO },
+            Chapter { title: "Chapter Two".to_string(), start: Duration::from_secs(30) },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_keeps_frames_before_a_bad_one() {
+        let mut overrun = frame(4, "TALB", b"\x03Album");
+        overrun[4..8].copy_from_slice(&[0, 0, 0x7F, 0]);
+        let bytes = tag(4, &[frame(4, "TIT2", b"\x03Title"), overrun, frame(4, "TPE1", b"\x03Artist")], 0);
+        let parsed = Id3v2Tag::parse(&bytes).unwrap();
+        assert_eq!(parsed.text("TIT2"), Some("Title"));
+        assert_eq!(parsed.text("TALB"), None);
+        assert_eq!(parsed.frames.len(), 1);
+
+        // A v2.4 size that isn't synchsafe
+        let mut bad_size = frame(4, "TALB", b"\x03Album");
+        bad_size[7] = 0x80;
+        let bytes = tag(4, &[frame(4, "TIT2", b"\x03Title"), bad_size], 0);
+        assert_eq!(Id3v2Tag::parse(&bytes).unwrap().frames.len(), 1);
+    }
+
+    #[test]
+    fn test_reject_id3v22() {
+        let mut bytes = tag(3, &[], 10);
+        bytes[3] = 2;
+        assert!(Id3v2Tag::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_remove_unsynchronisation() {
+        assert_eq!(remove_unsynchronisation(&[0xFF, 0x00, 0xE0, 0x01]), vec![0xFF, 0xE0, 0x01]);
+    }
+
+    #[test]
+    fn test_parse_id3v11() {
+        let mut bytes = vec![0u8; 128];
+        bytes[0..3].copy_from_slice(b"TAG");
+        bytes[3..8].copy_from_slice(b"Title");
+        bytes[33..39].copy_from_slice(b"Artist");
+        bytes[93..97].copy_from_slice(b"1999");
+        bytes[126] = 7;
+        bytes[127] = 13;
+
+        let tag = Id3v1Tag::parse(&bytes).unwrap();
+        assert_eq!(tag.title.as_deref(), Some("Title"));
+        assert_eq!(tag.artist.as_deref(), Some("Artist"));
+        assert_eq!(tag.album, None);
+        assert_eq!(tag.year.as_deref(), Some("1999"));
+        assert_eq!(tag.track, Some(7));
+        assert_eq!(tag.genre.and_then(genre_name), Some("Pop"));
+    }
+
+    #[test]
+    fn test_read_tags_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.mp3");
+
+        let mut bytes = tag(4, &[frame(4, "TALB", b"\x03Album")], 16);
+        bytes.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        let mut v1 = vec![0u8; 128];
+        v1[0..3].copy_from_slice(b"TAG");
+        v1[63..68].copy_from_slice(b"Album");
+        bytes.extend_from_slice(&v1);
+        fs::write(&path, &bytes).unwrap();
+
+        assert_eq!(read_id3v2(&path).unwrap().unwrap().text("TALB"), Some("Album"));
+        assert_eq!(read_id3v1(&path).unwrap().unwrap().album.as_deref(), Some("Album"));
+    }
+
+    #[test]
+    fn test_read_truncated_tag() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.mp3");
+
+        let mut bytes = tag(4, &[frame(4, "TIT2", b"\x03Title"), frame(4, "TALB", b"\x03Album")], 1024);
+        bytes.truncate(bytes.len() - 1024 - 4);
+        fs::write(&path, &bytes).unwrap();
+
+        let parsed = read_id3v2(&path).unwrap().unwrap();
+        assert_eq!(parsed.text("TIT2"), Some("Title"));
+        assert_eq!(parsed.get("TALB"), None);
+    }
+}
@@ -0,0 +1,355 @@
+//! Matroska and WebM (EBML) parsing
+//!
+//! EBML is a binary XML: every element is a variable length ID, a variable
+//! length size and its payload. The `EBML` header names the document type,
+//! and the `Segment` that follows holds `Info` (title and duration) and
+//! `Tracks`, which are written before the first `Cluster` of media data.
+
+use std::fs;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::files::{AudioCodec, VideoCodec};
+use crate::metadata::{VideoInfo, VideoStream};
+use crate::{Error, Result};
+
+const EBML_HEADER: u32 = 0x1A45DFA3;
+const DOC_TYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x18538067;
+const CLUSTER: u32 = 0x1F43B675;
+
+const INFO: u32 = 0x1549A966;
+const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const DURATION: u32 = 0x4489;
+const TITLE: u32 = 0x7BA9;
+
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+
+/// Largest top level element we're willing to buffer, `Info` and `Tracks` are tiny
+const MAX_ELEMENT_LEN: u64 = 16 * 1024 * 1024;
+
+/// Default `TimestampScale`, one millisecond in nanoseconds
+const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+
+/// Read a variable length integer, returning its value and encoded length
+///
+/// IDs keep their length marker bit, sizes have it masked off.
+fn vint(bytes: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *bytes.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 || bytes.len() < len {
+        return None;
+    }
+
+    let mut value = if keep_marker { first as u64 } else { (first as u64) & (0xFF >> len) };
+    for byte in &bytes[1..len] {
+        value = (value << 8) | *byte as u64;
+    }
+    Some((value, len))
+}
+
+/// Whether a size vint has all of its value bits set, meaning the size is unknown
+fn is_unknown_size(value: u64, len: usize) -> bool {
+    value == (1u64 << (7 * len)) - 1
+}
+
+/// Iterator over the elements laid out back to back in a buffer
+pub struct Elements<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = (u32, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, id_len) = vint(self.data, true)?;
+        let (size, size_len) = vint(&self.data[id_len..], false)?;
+        let start = id_len + size_len;
+        let end = match is_unknown_size(size, size_len) {
+            true => self.data.len(),
+            false => start.checked_add(usize::try_from(size).ok()?).filter(|end| *end <= self.data.len())?,
+        };
+
+        let data = &self.data[start..end];
+        self.data = &self.data[end..];
+        Some((id as u32, data))
+    }
+}
+
+/// Iterate over the child elements contained in `data`
+pub fn elements(data: &[u8]) -> Elements<'_> {
+    Elements { data }
+}
+
+/// The `DocType` of an in-memory EBML header, e.g. `matroska` or `webm`
+pub fn doc_type(bytes: &[u8]) -> Option<String> {
+    let (id, header) = elements(bytes).next()?;
+    if id != EBML_HEADER {
+        return None;
+    }
+    elements(header).find(|(id, _)| *id == DOC_TYPE).map(|(_, value)| string(value))
+}
+
+/// Read the title, duration and streams of a Matroska or WebM file
+pub fn read_video_info(path: &Path) -> Result<Option<VideoInfo>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+
+    match read_element(&mut reader)? {
+        Some((EBML_HEADER, _)) => {}
+        _ => return Ok(None),
+    }
+    match read_element_header(&mut reader)? {
+        Some((SEGMENT, _)) => {}
+        _ => return Err(Error::InvalidMetadata("matroska file has no segment".to_string())),
+    }
+
+    let mut info = VideoInfo::default();
+    let (mut seen_info, mut seen_tracks) = (false, false);
+
+    while !(seen_info && seen_tracks) {
+        let Some((id, size)) = read_element_header(&mut reader)? else {
+            break;
+        };
+        match (id, size) {
+            (INFO | TRACKS, Some(size)) if size <= MAX_ELEMENT_LEN => {
+                let mut data = vec![0u8; size as usize];
+                reader.read_exact(&mut data)?;
+                if id == INFO {
+                    parse_info(&data, &mut info);
+                    seen_info = true;
+                } else {
+                    parse_tracks(&data, &mut info);
+                    seen_tracks = true;
+                }
+            }
+            // Media data starts here, and anything of unknown size can't be skipped
+            (CLUSTER, _) | (_, None) => break,
+            (_, Some(size)) => {
+                reader.seek(SeekFrom::Current(size as i64))?;
+            }
+        }
+    }
+
+    Ok(Some(info))
+}
+
+/// Read an element ID and size, the size is `None` when unknown
+fn read_element_header(reader: &mut impl Read) -> Result<Option<(u32, Option<u64>)>> {
+    let Some((id, _)) = read_vint(reader, true)? else {
+        return Ok(None);
+    };
+    let Some((size, len)) = read_vint(reader, false)? else {
+        return Ok(None);
+    };
+    Ok(Some((id as u32, (!is_unknown_size(size, len)).then_some(size))))
+}
+
+/// Read a complete element with a known size
+fn read_element(reader: &mut impl Read) -> Result<Option<(u32, Vec<u8>)>> {
+    match read_element_header(reader)? {
+        Some((id, Some(size))) if size <= MAX_ELEMENT_LEN => {
+            let mut data = vec![0u8; size as usize];
+            reader.read_exact(&mut data)?;
+            Ok(Some((id, data)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn read_vint(reader: &mut impl Read, keep_marker: bool) -> Result<Option<(u64, usize)>> {
+    let mut bytes = [0u8; 8];
+    if reader.read_exact(&mut bytes[..1]).is_err() {
+        return Ok(None);
+    }
+    let len = bytes[0].leading_zeros() as usize + 1;
+    if len > 8 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut bytes[1..len])?;
+    Ok(vint(&bytes[..len], keep_marker))
+}
+
+fn parse_info(data: &[u8], info: &mut VideoInfo) {
+    let mut scale = DEFAULT_TIMESTAMP_SCALE;
+    let mut duration = None;
+    for (id, value) in elements(data) {
+        match id {
+            TIMESTAMP_SCALE => scale = uint(value),
+            DURATION => duration = float(value),
+            TITLE => info.title = Some(string(value)).filter(|t| !t.is_empty()),
+            _ => {}
+        }
+    }
+    // Duration is a float counted in TimestampScale nanosecond ticks
+    info.duration = duration
+        .filter(|d| d.is_finite() && *d >= 0.0)
+        .map(|d| Duration::from_nanos((d * scale as f64) as u64));
+}
+
+fn parse_tracks(data: &[u8], info: &mut VideoInfo) {
+    for (_, entry) in elements(data).filter(|(id, _)| *id == TRACK_ENTRY) {
+        let mut track_type = 0;
+        let mut codec_id = String::new();
+        let mut resolution = (0, 0);
+        for (id, value) in elements(entry) {
+            match id {
+                TRACK_TYPE => track_type = uint(value),
+                CODEC_ID => codec_id = string(value),
+                VIDEO => {
+                    for (id, value) in elements(value) {
+                        match id {
+                            PIXEL_WIDTH => resolution.0 = uint(value) as u32,
+                            PIXEL_HEIGHT => resolution.1 = uint(value) as u32,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match track_type {
+            1 => info.video.push(VideoStream {
+                codec: video_codec(&codec_id),
+                width: resolution.0,
+                height: resolution.1,
+            }),
+            2 => info.audio.push(audio_codec(&codec_id)),
+            _ => {}
+        }
+    }
+}
+
+/// Map a Matroska `CodecID` to the video codec it names
+pub fn video_codec(codec_id: &str) -> Option<VideoCodec> {
+    match codec_id {
+        "V_MPEG4/ISO/AVC" => Some(VideoCodec::H264),
+        "V_MPEGH/ISO/HEVC" => Some(VideoCodec::Hevc),
+        "V_AV1" => Some(VideoCodec::Av1),
+        "V_VP8" => Some(VideoCodec::Vp8),
+        "V_VP9" => Some(VideoCodec::Vp9),
+        "V_MPEG2" => Some(VideoCodec::Mpeg2),
+        id if id.starts_with("V_MPEG4/ISO/") => Some(VideoCodec::Mpeg4),
+        _ => None,
+    }
+}
+
+/// Map a Matroska `CodecID` to the audio codec it names
+pub fn audio_codec(codec_id: &str) -> Option<AudioCodec> {
+    match codec_id {
+        "A_AC3" => Some(AudioCodec::Ac3),
+        "A_EAC3" => Some(AudioCodec::Eac3),
+        "A_ALAC" => Some(AudioCodec::Alac),
+        "A_FLAC" => Some(AudioCodec::Flac),
+        "A_MPEG/L3" => Some(AudioCodec::Mp3),
+        "A_VORBIS" => Some(AudioCodec::Vorbis),
+        "A_OPUS" => Some(AudioCodec::Opus),
+        // AAC ids carry the profile, e.g. A_AAC/MPEG4/LC
+        id if id.starts_with("A_AAC") => Some(AudioCodec::Aac),
+        id if id.starts_with("A_PCM/") => Some(AudioCodec::Pcm),
+        _ => None,
+    }
+}
+
+fn uint(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// Decode a string element, which may be padded with trailing nulls
+fn string(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_string()
+}
+
+/// Encode an element with a one to four byte ID and an eight byte size
+#[cfg(test)]
+pub(crate) fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = id.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+    out.push(0x01);
+    out.extend_from_slice(&(payload.len() as u64).to_be_bytes()[1..]);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Build a minimal Matroska file with a title, duration and the given tracks
+#[cfg(test)]
+pub(crate) fn matroska(doc_type: &str, title: &str, tracks: &[(u64, &str)]) -> Vec<u8> {
+    let mut out = element(EBML_HEADER, &element(DOC_TYPE, doc_type.as_bytes()));
+
+    let mut info = element(TIMESTAMP_SCALE, &[0x0F, 0x42, 0x40]);
+    info.extend_from_slice(&element(DURATION, &90_000f64.to_be_bytes()));
+    info.extend_from_slice(&element(TITLE, title.as_bytes()));
+
+    let mut entries = Vec::new();
+    for (track_type, codec_id) in tracks {
+        let mut entry = element(TRACK_TYPE, &[*track_type as u8]);
+        entry.extend_from_slice(&element(CODEC_ID, codec_id.as_bytes()));
+        if *track_type == 1 {
+            let mut video = element(PIXEL_WIDTH, &1920u16.to_be_bytes());
+            video.extend_from_slice(&element(PIXEL_HEIGHT, &1080u16.to_be_bytes()));
+            entry.extend_from_slice(&element(VIDEO, &video));
+        }
+        entries.extend_from_slice(&element(TRACK_ENTRY, &entry));
+    }
+
+    let mut segment = element(0x114D9B74, &[0; 12]);
+    segment.extend_from_slice(&element(INFO, &info));
+    segment.extend_from_slice(&element(TRACKS, &entries));
+    segment.extend_from_slice(&element(CLUSTER, &[0; 32]));
+
+    // Segments of a live recording have an unknown size
+    out.extend_from_slice(&[0x18, 0x53, 0x80, 0x67, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    out.extend_from_slice(&segment);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_vint() {
+        assert_eq!(vint(&[0x81], false), Some((1, 1)));
+        assert_eq!(vint(&[0x40, 0x02], false), Some((2, 2)));
+        assert_eq!(vint(&[0x1A, 0x45, 0xDF, 0xA3], true), Some((EBML_HEADER as u64, 4)));
+        assert!(is_unknown_size(0x7F, 1));
+        assert_eq!(vint(&[0x00], false), None);
+    }
+
+    #[test]
+    fn test_doc_type() {
+        assert_eq!(doc_type(&matroska("webm", "", &[])).as_deref(), Some("webm"));
+        assert_eq!(doc_type(&matroska("matroska", "", &[])).as_deref(), Some("matroska"));
+        assert_eq!(doc_type(b"RIFF\0\0\0\0WAVE"), None);
+    }
+
+    #[test]
+    fn test_read_video_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("movie.mkv");
+        let tracks = [(1, "V_MPEGH/ISO/HEVC"), (2, "A_EAC3"), (2, "A_AAC/MPEG4/LC"), (17, "S_TEXT/UTF8")];
+        fs::write(&path, matroska("matroska", "Big Buck Bunny", &tracks)).unwrap();
+
+        let info = read_video_info(&path).unwrap().unwrap();
+        assert_eq!(info.title.as_deref(), Some("Big Buck Bunny"));
+        assert_eq!(info.duration, Some(Duration::from_secs(90)));
+        assert_eq!(info.video, vec![VideoStream { codec: Some(VideoCodec::Hevc), width: 1920, height: 1080 }]);
+        assert_eq!(info.audio, vec![Some(AudioCodec::Eac3), Some(AudioCodec::Aac)]);
+    }
+}
@@ -0,0 +1,70 @@
+//! Native readers and writers for the tag and package formats used by `SupportedFiletype`
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::files::{AudioCodec, VideoCodec};
+use crate::{Error, Result};
+
+pub mod cue;
+pub mod epub;
+pub mod ffprobe;
+pub mod flac;
+pub mod id3;
+pub mod matroska;
+pub mod mp4;
+pub mod ogg;
+pub mod riff;
+pub mod tags;
+pub mod vorbis;
+
+/// A named position within a single media file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+}
+
+/// Title, duration and streams of a video container
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoInfo {
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+    pub video: Vec<VideoStream>,
+    /// Codec of each audio track, `None` for codecs we don't know
+    pub audio: Vec<Option<AudioCodec>>,
+}
+
+/// A single video track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoStream {
+    pub codec: Option<VideoCodec>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Rewrite a file through a temporary sibling that is renamed over it
+///
+/// The original stays untouched until the new contents are fully written
+/// and synced, so a failed or interrupted write never leaves half a file.
+pub(crate) fn rewrite_atomically(path: &Path, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let file_name = path.file_name().ok_or(Error::InvalidFilePath)?.to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.arcanio-tmp", file_name));
+
+    let result = (|| {
+        let mut writer = BufWriter::new(fs::File::create(&temp)?);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::set_permissions(&temp, fs::metadata(path)?.permissions())?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
@@ -0,0 +1,1265 @@
+//! MP4 box walking
+//!
+//! MP4 files are a tree of boxes, each a 32 bit size and a four character
+//! type followed by its payload. Everything we need lives under the `moov`
+//! box, so that box is read into memory and walked from there.
+//!
+//! Tags are written by rebuilding `moov` with a new `ilst`. It replaces the
+//! old `moov` in place when it fits with any `free` box after it, otherwise
+//! the file is rewritten and the chunk offsets are moved along with the data.
+
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::files::{AudioCodec, VideoCodec};
+use crate::metadata::id3::decode_latin1;
+use crate::metadata::{rewrite_atomically, Chapter, VideoInfo, VideoStream};
+use crate::{Error, Result};
+
+/// A box and its payload, excluding the size and type header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mp4Box<'a> {
+    pub kind: [u8; 4],
+    pub data: &'a [u8],
+}
+
+/// Iterator over the boxes laid out back to back in a buffer
+pub struct Boxes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = Mp4Box<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (header_len, size) = parse_box_size(self.data, self.data.len() as u64)?;
+        let size = usize::try_from(size).ok().filter(|s| *s <= self.data.len() && *s >= header_len)?;
+
+        let kind = self.data[4..8].try_into().ok()?;
+        let data = &self.data[header_len..size];
+        self.data = &self.data[size..];
+        Some(Mp4Box { kind, data })
+    }
+}
+
+/// Iterate over the boxes contained in `data`
+pub fn boxes(data: &[u8]) -> Boxes<'_> {
+    Boxes { data }
+}
+
+/// Follow a path of box types from `data`, returning the payload of the last one
+pub fn find<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter().try_fold(data, |data, kind| {
+        boxes(data).find(|b| &b.kind == *kind).map(|b| b.data)
+    })
+}
+
+/// Padding left behind when a file has to be rewritten, so later edits fit in place
+const DEFAULT_PADDING: usize = 8192;
+
+/// Position of a box at the top level of a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TopLevelBox {
+    kind: [u8; 4],
+    offset: u64,
+    header_len: usize,
+    size: u64,
+}
+
+/// Walk the top level boxes of a file, reading only their headers
+fn top_level_boxes(file: &mut fs::File) -> Result<Vec<TopLevelBox>> {
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut layout = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= file_len {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 16];
+        let read = (file_len - offset).min(16) as usize;
+        reader.read_exact(&mut header[..read])?;
+
+        let (header_len, size) = parse_box_size(&header[..read], file_len - offset)
+            .filter(|(header_len, size)| *size >= *header_len as u64)
+            .ok_or_else(|| Error::InvalidMetadata(format!("invalid mp4 box at offset {}", offset)))?;
+
+        let kind = header[4..8].try_into().unwrap_or_default();
+        layout.push(TopLevelBox { kind, offset, header_len, size });
+        offset += size;
+    }
+
+    Ok(layout)
+}
+
+/// Read the payload of a top level box
+fn read_payload(file: &mut fs::File, top: &TopLevelBox) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(top.offset + top.header_len as u64))?;
+    let mut payload = vec![0u8; (top.size - top.header_len as u64) as usize];
+    file.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Read the `moov` box of an MP4 file into memory
+pub fn read_moov(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut file = fs::File::open(path)?;
+    match top_level_boxes(&mut file)?.iter().find(|b| &b.kind == b"moov") {
+        Some(moov) => read_payload(&mut file, moov).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The `trak` boxes in a `moov` payload whose handler matches `handler`, e.g. `soun`
+pub fn tracks<'a>(moov: &'a [u8], handler: &'a [u8; 4]) -> impl Iterator<Item = &'a [u8]> + 'a {
+    boxes(moov)
+        .filter(|b| &b.kind == b"trak")
+        .map(|b| b.data)
+        .filter(move |trak| {
+            find(trak, &[b"mdia", b"hdlr"])
+                // Skip the version/flags and pre_defined fields before the handler type
+                .and_then(|hdlr| hdlr.get(8..12))
+                .is_some_and(|kind| kind == handler)
+        })
+}
+
+/// The first sample entry of a track's `stsd` box
+pub fn sample_entry(trak: &[u8]) -> Option<Mp4Box<'_>> {
+    let stsd = find(trak, &[b"mdia", b"minf", b"stbl", b"stsd"])?;
+    // Skip version/flags and the entry count
+    boxes(stsd.get(8..)?).next()
+}
+
+/// Read the codec of the first audio track of an MP4 file
+pub fn read_audio_codec(path: &Path) -> Result<Option<AudioCodec>> {
+    let Some(moov) = read_moov(path)? else {
+        return Ok(None);
+    };
+    let codec = tracks(&moov, b"soun").find_map(|trak| sample_entry(trak).and_then(audio_codec));
+    Ok(codec)
+}
+
+/// Read the title, duration and streams of an MP4 video file
+pub fn read_video_info(path: &Path) -> Result<Option<VideoInfo>> {
+    let Some(moov) = read_moov(path)? else {
+        return Ok(None);
+    };
+
+    let video = tracks(&moov, b"vide")
+        .filter_map(sample_entry)
+        .map(|entry| {
+            // Width and height follow the fixed fields of a visual sample entry
+            let dimension = |offset: usize| {
+                entry.data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32).unwrap_or(0)
+            };
+            VideoStream { codec: video_codec(entry), width: dimension(24), height: dimension(26) }
+        })
+        .collect();
+    let audio = tracks(&moov, b"soun").map(|trak| sample_entry(trak).and_then(audio_codec)).collect();
+
+    let title = ilst(&moov)
+        .map(parse_ilst)
+        .and_then(|tags| tags.text("©nam").map(|title| title.trim().to_string()))
+        .filter(|title| !title.is_empty());
+    Ok(Some(VideoInfo { title, duration: movie_duration(&moov), video, audio }))
+}
+
+/// The duration from the `mvhd` movie header
+fn movie_duration(moov: &[u8]) -> Option<Duration> {
+    let mvhd = find(moov, &[b"mvhd"])?;
+    let (timescale, duration) = match mvhd.first()? {
+        1 => (be_u32s(mvhd.get(20..24)?)[0] as u64, u64::from_be_bytes(mvhd.get(24..32)?.try_into().ok()?)),
+        _ => (be_u32s(mvhd.get(12..16)?)[0] as u64, be_u32s(mvhd.get(16..20)?)[0] as u64),
+    };
+    (timescale > 0).then(|| Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
+/// The value of an iTunes `ilst` item, decided by the well-known type of its `data` box
+#[derive(Debug, Clone, PartialEq)]
+pub enum IlstValue {
+    Text(String),
+    /// A `trkn` or `disk` number and total, zero if unset
+    Pair(u16, u16),
+    Integer(i64),
+    Jpeg(Vec<u8>),
+    Png(Vec<u8>),
+    Binary(Vec<u8>),
+}
+
+/// The items of an iTunes style `moov/udta/meta/ilst` box
+///
+/// Keys are the four character item type with `©` decoded, e.g. `©nam` or
+/// `aART`, and freeform items are keyed as `----:mean:name`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IlstTags {
+    pub items: Vec<(String, IlstValue)>,
+}
+
+impl IlstTags {
+    /// The first value of an item
+    pub fn get(&self, key: &str) -> Option<&IlstValue> {
+        self.items.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// The first text value of an item, freeform keys ignore case
+    pub fn text(&self, key: &str) -> Option<&str> {
+        self.items
+            .iter()
+            .filter(|(k, _)| key_matches(k, key))
+            .find_map(|(_, v)| match v {
+                IlstValue::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+    }
+
+    /// Track number and total from `trkn`
+    pub fn track(&self) -> Option<(u16, u16)> {
+        self.pair("trkn")
+    }
+
+    /// Disc number and total from `disk`
+    pub fn disc(&self) -> Option<(u16, u16)> {
+        self.pair("disk")
+    }
+
+    /// Every `covr` image in order
+    pub fn covers(&self) -> Vec<&IlstValue> {
+        self.items
+            .iter()
+            .filter(|(k, v)| k == "covr" && matches!(v, IlstValue::Jpeg(_) | IlstValue::Png(_) | IlstValue::Binary(_)))
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    fn pair(&self, key: &str) -> Option<(u16, u16)> {
+        match self.get(key)? {
+            IlstValue::Pair(number, total) => Some((*number, *total)),
+            _ => None,
+        }
+    }
+}
+
+/// Read the `ilst` tags of an MP4 file
+pub fn read_tags(path: &Path) -> Result<Option<IlstTags>> {
+    let Some(moov) = read_moov(path)? else {
+        return Ok(None);
+    };
+    Ok(ilst(&moov).map(parse_ilst))
+}
+
+/// The payload of the `ilst` box
+fn ilst(moov: &[u8]) -> Option<&[u8]> {
+    let meta = find(moov, &[b"udta", b"meta"])?;
+    // ISO meta is a full box, QuickTime meta starts straight away with its children
+    let children = match meta.get(4..8) {
+        Some(b"hdlr") => meta,
+        _ => meta.get(4..)?,
+    };
+    find(children, &[b"ilst"])
+}
+
+/// Parse every item of an `ilst` box
+pub fn parse_ilst(ilst: &[u8]) -> IlstTags {
+    let mut tags = IlstTags::default();
+    for item in boxes(ilst) {
+        let Some(key) = item_key(&item) else {
+            continue;
+        };
+
+        for data in boxes(item.data).filter(|b| &b.kind == b"data") {
+            if let Some(value) = parse_data(&key, data.data) {
+                tags.items.push((key.clone(), value));
+            }
+        }
+    }
+    tags
+}
+
+/// The key of an `ilst` item, `None` for freeform items missing their name
+fn item_key(item: &Mp4Box<'_>) -> Option<String> {
+    match &item.kind {
+        b"----" => {
+            // Freeform items name themselves with `mean` and `name` full boxes
+            let string = |kind: &[u8; 4]| {
+                find(item.data, &[kind]).and_then(|b| b.get(4..)).map(|b| String::from_utf8_lossy(b).to_string())
+            };
+            Some(format!("----:{}:{}", string(b"mean")?, string(b"name")?))
+        }
+        kind => Some(decode_latin1(kind)),
+    }
+}
+
+/// Whether an item key matches, freeform keys ignore case
+fn key_matches(key: &str, wanted: &str) -> bool {
+    key == wanted || (wanted.starts_with("----:") && key.eq_ignore_ascii_case(wanted))
+}
+
+/// Parse a `data` box, which starts with a type indicator and a locale
+fn parse_data(key: &str, data: &[u8]) -> Option<IlstValue> {
+    let data_type = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?) & 0x00FF_FFFF;
+    let value = data.get(8..)?;
+
+    let value = match data_type {
+        1 => IlstValue::Text(String::from_utf8_lossy(value).to_string()),
+        2 => {
+            let units: Vec<u16> = value.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            IlstValue::Text(String::from_utf16_lossy(&units))
+        }
+        13 => IlstValue::Jpeg(value.to_vec()),
+        14 => IlstValue::Png(value.to_vec()),
+        21 | 22 if !value.is_empty() && value.len() <= 8 => {
+            let signed = data_type == 21 && value[0] & 0x80 != 0;
+            let mut bytes = [if signed { 0xFF } else { 0 }; 8];
+            bytes[8 - value.len()..].copy_from_slice(value);
+            IlstValue::Integer(i64::from_be_bytes(bytes))
+        }
+        // trkn and disk are implicit binary: reserved, number, total
+        0 if key == "trkn" || key == "disk" => {
+            let number = u16::from_be_bytes(value.get(2..4)?.try_into().ok()?);
+            let total = value.get(4..6).map(|t| u16::from_be_bytes([t[0], t[1]])).unwrap_or(0);
+            IlstValue::Pair(number, total)
+        }
+        _ => IlstValue::Binary(value.to_vec()),
+    };
+    Some(value)
+}
+
+/// Replace items in the `ilst` tags of an MP4 file
+///
+/// Existing items keyed in `remove` or `items` are dropped and `items` are
+/// appended, every other item, cover art included, is kept byte for byte.
+/// Consecutive values with the same key are written as one item.
+pub fn write_tags(path: &Path, items: &[(String, IlstValue)], remove: &[&str]) -> Result<()> {
+    let mut file = fs::File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let layout = top_level_boxes(&mut file)?;
+    let index = layout
+        .iter()
+        .position(|b| &b.kind == b"moov")
+        .ok_or_else(|| Error::InvalidMetadata("mp4 file has no moov box".to_string()))?;
+    let moov_box = layout[index];
+    let moov = read_payload(&mut file, &moov_box)?;
+
+    // A free box straight after moov is padding the new moov can grow into
+    let region_start = moov_box.offset;
+    let region_end = match layout.get(index + 1) {
+        Some(next) if matches!(&next.kind, b"free" | b"skip") => next.offset + next.size,
+        _ => moov_box.offset + moov_box.size,
+    };
+    let available = region_end - region_start;
+
+    let replaced = |key: &str| remove.iter().copied().chain(items.iter().map(|(k, _)| k.as_str())).any(|k| key_matches(key, k));
+    let mut new_ilst = Vec::new();
+    for item in boxes(ilst(&moov).unwrap_or_default()) {
+        if !item_key(&item).is_some_and(|key| replaced(&key)) {
+            new_ilst.extend_from_slice(&mp4_box(&item.kind, item.data));
+        }
+    }
+    new_ilst.extend_from_slice(&encode_items(items)?);
+    let new_moov = with_ilst(&moov, &new_ilst);
+
+    let new_len = new_moov.len() as u64 + 8;
+    // Leftover space needs room for a free box header, so 1-7 spare bytes don't fit
+    let slack = available.checked_sub(new_len).filter(|&slack| slack == 0 || slack >= 8);
+    if slack.is_some() || region_end == file_len {
+        // Nothing after the region moves, so chunk offsets stay valid
+        let padding = match slack {
+            Some(0) => None,
+            Some(slack) => Some(slack as usize - 8),
+            None => Some(DEFAULT_PADDING),
+        };
+        let mut region = mp4_box(b"moov", &new_moov);
+        if let Some(padding) = padding {
+            region.extend_from_slice(&mp4_box(b"free", &vec![0; padding]));
+        }
+
+        let mut file = fs::OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(region_start))?;
+        file.write_all(&region)?;
+        file.set_len(file_len.max(region_start + region.len() as u64))?;
+        file.sync_all()?;
+        return Ok(());
+    }
+
+    // Everything after the region moves by the change in its size
+    let region_len = new_len + 8 + DEFAULT_PADDING as u64;
+    let delta = region_len as i64 - available as i64;
+    let mut region = mp4_box(b"moov", &shift_chunk_offsets(&new_moov, region_end, delta)?);
+    region.extend_from_slice(&mp4_box(b"free", &vec![0; DEFAULT_PADDING]));
+
+    rewrite_atomically(path, |out| {
+        let mut original = fs::File::open(path)?;
+        io::copy(&mut (&mut original).take(region_start), out)?;
+        out.write_all(&region)?;
+        original.seek(SeekFrom::Start(region_end))?;
+        io::copy(&mut original, out)?;
+        Ok(())
+    })
+}
+
+/// Rebuild a `moov` payload with `ilst` as the contents of its `udta/meta/ilst`
+fn with_ilst(moov: &[u8], ilst: &[u8]) -> Vec<u8> {
+    let replace_child = |container: &[u8], kind: &[u8; 4], rebuild: &dyn Fn(&[u8]) -> Vec<u8>| {
+        let mut out = Vec::new();
+        let mut found = false;
+        for b in boxes(container) {
+            if &b.kind == kind && !found {
+                found = true;
+                out.extend_from_slice(&mp4_box(kind, &rebuild(b.data)));
+            } else {
+                out.extend_from_slice(&mp4_box(&b.kind, b.data));
+            }
+        }
+        if !found {
+            out.extend_from_slice(&mp4_box(kind, &rebuild(&[])));
+        }
+        out
+    };
+
+    let rebuild_meta = |meta: &[u8]| {
+        // ISO meta is a full box, QuickTime meta starts straight away with its children
+        let (header, children) = match meta.get(4..8) {
+            Some(b"hdlr") => (&[][..], meta),
+            _ => (meta.get(..4).unwrap_or(&[0; 4]), meta.get(4..).unwrap_or_default()),
+        };
+
+        let mut out = header.to_vec();
+        if find(children, &[b"hdlr"]).is_none() {
+            // Version/flags, pre_defined, handler type, reserved and an empty name
+            let mut hdlr = vec![0u8; 8];
+            hdlr.extend_from_slice(b"mdirappl");
+            hdlr.extend_from_slice(&[0; 9]);
+            out.extend_from_slice(&mp4_box(b"hdlr", &hdlr));
+        }
+        // The old ilst and any padding inside meta are replaced by the new ilst
+        for b in boxes(children).filter(|b| !matches!(&b.kind, b"ilst" | b"free")) {
+            out.extend_from_slice(&mp4_box(&b.kind, b.data));
+        }
+        out.extend_from_slice(&mp4_box(b"ilst", ilst));
+        out
+    };
+
+    replace_child(moov, b"udta", &|udta| {
+        // A new meta is an empty ISO full box
+        replace_child(udta, b"meta", &|meta| rebuild_meta(if meta.is_empty() { &[0; 4] } else { meta }))
+    })
+}
+
+/// Encode `ilst` items, grouping consecutive values of the same key
+fn encode_items(items: &[(String, IlstValue)]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for group in items.chunk_by(|a, b| a.0 == b.0) {
+        let key = &group[0].0;
+        let mut payload = Vec::new();
+
+        let kind = match key.strip_prefix("----:").and_then(|rest| rest.split_once(':')) {
+            Some((mean, name)) => {
+                for (kind, value) in [(b"mean", mean), (b"name", name)] {
+                    let mut full_box = vec![0u8; 4];
+                    full_box.extend_from_slice(value.as_bytes());
+                    payload.extend_from_slice(&mp4_box(kind, &full_box));
+                }
+                *b"----"
+            }
+            // `©` and friends are single latin1 bytes
+            None => key
+                .chars()
+                .map(|c| u8::try_from(c as u32).ok())
+                .collect::<Option<Vec<u8>>>()
+                .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+                .ok_or_else(|| Error::InvalidMetadata(format!("invalid mp4 item key {:?}", key)))?,
+        };
+
+        for (_, value) in group {
+            let (data_type, bytes): (u32, Vec<u8>) = match value {
+                IlstValue::Text(text) => (1, text.as_bytes().to_vec()),
+                IlstValue::Pair(number, total) => {
+                    let mut bytes = [[0, 0], number.to_be_bytes(), total.to_be_bytes()].concat();
+                    // trkn carries two more reserved bytes than disk
+                    if key == "trkn" {
+                        bytes.extend_from_slice(&[0, 0]);
+                    }
+                    (0, bytes)
+                }
+                IlstValue::Integer(value) => {
+                    let bytes = value.to_be_bytes();
+                    let len = [1, 2, 4].into_iter().find(|len| i64::from_be_bytes(sign_extend(&bytes[8 - len..])) == *value).unwrap_or(8);
+                    (21, bytes[8 - len..].to_vec())
+                }
+                IlstValue::Jpeg(data) => (13, data.clone()),
+                IlstValue::Png(data) => (14, data.clone()),
+                IlstValue::Binary(data) => (0, data.clone()),
+            };
+            let mut data = data_type.to_be_bytes().to_vec();
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&bytes);
+            payload.extend_from_slice(&mp4_box(b"data", &data));
+        }
+
+        out.extend_from_slice(&mp4_box(&kind, &payload));
+    }
+    Ok(out)
+}
+
+/// Sign extend a big endian integer of up to 8 bytes
+fn sign_extend(value: &[u8]) -> [u8; 8] {
+    let fill = if value.first().is_some_and(|b| b & 0x80 != 0) { 0xFF } else { 0 };
+    let mut bytes = [fill; 8];
+    bytes[8 - value.len()..].copy_from_slice(value);
+    bytes
+}
+
+/// Shift the `stco` and `co64` chunk offsets at or past `from` by `delta`
+///
+/// Needed when the data after `moov` moves because `moov` changed size.
+fn shift_chunk_offsets(container: &[u8], from: u64, delta: i64) -> Result<Vec<u8>> {
+    let overflow = || Error::InvalidMetadata("mp4 chunk offset out of range".to_string());
+
+    let mut out = Vec::new();
+    for b in boxes(container) {
+        let payload = match &b.kind {
+            b"trak" | b"mdia" | b"minf" | b"stbl" => shift_chunk_offsets(b.data, from, delta)?,
+            b"stco" | b"co64" => {
+                let width = if &b.kind == b"stco" { 4 } else { 8 };
+                let mut data = b.data.to_vec();
+                // Skip version/flags and the entry count
+                for entry in data.get_mut(8..).unwrap_or_default().chunks_exact_mut(width) {
+                    let offset = u64::from_be_bytes(zero_extend(entry));
+                    if offset < from {
+                        continue;
+                    }
+                    let shifted = offset.checked_add_signed(delta).ok_or_else(overflow)?;
+                    match width {
+                        4 => entry.copy_from_slice(&u32::try_from(shifted).map_err(|_| overflow())?.to_be_bytes()),
+                        _ => entry.copy_from_slice(&shifted.to_be_bytes()),
+                    }
+                }
+                data
+            }
+            _ => b.data.to_vec(),
+        };
+        out.extend_from_slice(&mp4_box(&b.kind, &payload));
+    }
+    Ok(out)
+}
+
+/// Zero extend a big endian unsigned integer of up to 8 bytes
+fn zero_extend(value: &[u8]) -> [u8; 8] {
+    let mut bytes = [0u8; 8];
+    bytes[8 - value.len()..].copy_from_slice(value);
+    bytes
+}
+
+/// Stream properties of the first audio track
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioInfo {
+    pub codec: Option<AudioCodec>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub duration: Option<Duration>,
+}
+
+/// Read the stream properties of the first audio track from its `mdhd` and `stsd`
+pub fn read_audio_info(path: &Path) -> Result<Option<AudioInfo>> {
+    let Some(moov) = read_moov(path)? else {
+        return Ok(None);
+    };
+    let Some(trak) = tracks(&moov, b"soun").next() else {
+        return Ok(None);
+    };
+    let Some(entry) = sample_entry(trak) else {
+        return Ok(None);
+    };
+
+    let u16_at = |offset: usize| entry.data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).unwrap_or(0);
+    let timescale = media_timescale(trak);
+    // The entry's 16.16 fixed point rate overflows above 65535 Hz, the media timescale doesn't
+    let sample_rate = match timescale {
+        Some(timescale) if timescale > 0 => timescale,
+        _ => u16_at(24) as u32,
+    };
+
+    Ok(Some(AudioInfo {
+        codec: audio_codec(entry),
+        sample_rate,
+        channels: u16_at(16),
+        bits_per_sample: u16_at(18),
+        duration: media_duration(trak),
+    }))
+}
+
+/// The duration of a track's media from its `mdhd` box
+fn media_duration(trak: &[u8]) -> Option<Duration> {
+    let mdhd = find(trak, &[b"mdia", b"mdhd"])?;
+    let duration = match mdhd.first()? {
+        1 => u64::from_be_bytes(mdhd.get(24..32)?.try_into().ok()?),
+        _ => u32::from_be_bytes(mdhd.get(16..20)?.try_into().ok()?) as u64,
+    };
+    let timescale = media_timescale(trak).filter(|t| *t > 0)?;
+    Some(Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
+/// Read chapters from a QuickTime chapter track, falling back to a Nero `chpl` box
+pub fn read_chapters(path: &Path) -> Result<Vec<Chapter>> {
+    let Some(moov) = read_moov(path)? else {
+        return Ok(Vec::new());
+    };
+
+    let chapters = match chapter_track(&moov) {
+        Some(trak) => read_chapter_track(path, trak)?,
+        None => Vec::new(),
+    };
+    if !chapters.is_empty() {
+        return Ok(chapters);
+    }
+
+    Ok(find(&moov, &[b"udta", b"chpl"]).map(parse_chpl).unwrap_or_default())
+}
+
+/// The track referenced by the first `tref/chap` of any track
+fn chapter_track(moov: &[u8]) -> Option<&[u8]> {
+    let chapter_id = boxes(moov)
+        .filter(|b| &b.kind == b"trak")
+        .find_map(|b| find(b.data, &[b"tref", b"chap"]))
+        .and_then(|chap| chap.get(0..4))
+        .map(|id| u32::from_be_bytes([id[0], id[1], id[2], id[3]]))?;
+
+    boxes(moov)
+        .filter(|b| &b.kind == b"trak")
+        .map(|b| b.data)
+        .find(|trak| track_id(trak) == Some(chapter_id))
+}
+
+/// The id of a track from its `tkhd` box
+fn track_id(trak: &[u8]) -> Option<u32> {
+    let tkhd = find(trak, &[b"tkhd"])?;
+    // Creation and modification times are 64 bit in version 1
+    let offset = if *tkhd.first()? == 1 { 20 } else { 12 };
+    Some(u32::from_be_bytes(tkhd.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// The timescale of a track's media from its `mdhd` box
+pub(crate) fn media_timescale(trak: &[u8]) -> Option<u32> {
+    let mdhd = find(trak, &[b"mdia", b"mdhd"])?;
+    let offset = if *mdhd.first()? == 1 { 20 } else { 12 };
+    Some(u32::from_be_bytes(mdhd.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// Read every sample of a text track as a chapter title
+fn read_chapter_track(path: &Path, trak: &[u8]) -> Result<Vec<Chapter>> {
+    let (Some(timescale), Some(stbl)) = (media_timescale(trak), find(trak, &[b"mdia", b"minf", b"stbl"])) else {
+        return Ok(Vec::new());
+    };
+    let durations = sample_durations(stbl);
+    let locations = sample_locations(stbl);
+
+    let mut file = fs::File::open(path)?;
+    let mut chapters = Vec::new();
+    let mut time = 0u64;
+    for (i, (offset, size)) in locations.into_iter().enumerate() {
+        let mut sample = vec![0u8; size as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut sample)?;
+
+        // Text samples are a 16 bit length followed by the text
+        let len = sample.get(0..2).map(|l| u16::from_be_bytes([l[0], l[1]]) as usize).unwrap_or(0);
+        let text = sample.get(2..2 + len).unwrap_or_default();
+        let title = match text {
+            [0xFE, 0xFF, rest @ ..] => {
+                let units: Vec<u16> = rest.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+                String::from_utf16_lossy(&units)
+            }
+            _ => String::from_utf8_lossy(text).to_string(),
+        };
+
+        chapters.push(Chapter {
+            title,
+            start: Duration::from_secs_f64(time as f64 / timescale.max(1) as f64),
+        });
+        time += durations.get(i).copied().unwrap_or(0) as u64;
+    }
+
+    Ok(chapters)
+}
+
+/// Per sample durations expanded from the `stts` run lengths
+fn sample_durations(stbl: &[u8]) -> Vec<u32> {
+    let Some(stts) = find(stbl, &[b"stts"]) else {
+        return Vec::new();
+    };
+    be_u32s(stts.get(8..).unwrap_or_default())
+        .chunks_exact(2)
+        .flat_map(|run| std::iter::repeat_n(run[1], run[0] as usize))
+        .collect()
+}
+
+/// File offset and size of each sample, from `stsz`, `stsc` and `stco`/`co64`
+fn sample_locations(stbl: &[u8]) -> Vec<(u64, u32)> {
+    let sizes = match find(stbl, &[b"stsz"]).map(|stsz| be_u32s(stsz.get(4..).unwrap_or_default())) {
+        Some(fields) if fields.len() >= 2 && fields[0] != 0 => vec![fields[0]; fields[1] as usize],
+        Some(fields) if fields.len() >= 2 => fields[2..].to_vec(),
+        _ => return Vec::new(),
+    };
+
+    let chunk_offsets: Vec<u64> = if let Some(stco) = find(stbl, &[b"stco"]) {
+        be_u32s(stco.get(8..).unwrap_or_default()).into_iter().map(u64::from).collect()
+    } else if let Some(co64) = find(stbl, &[b"co64"]) {
+        co64.get(8..).unwrap_or_default()
+            .chunks_exact(8)
+            .map(|c| u64::from_be_bytes(c.try_into().unwrap_or_default()))
+            .collect()
+    } else {
+        return Vec::new();
+    };
+
+    // Each stsc entry is (first chunk, samples per chunk, description index)
+    let runs: Vec<(u32, u32)> = find(stbl, &[b"stsc"])
+        .map(|stsc| be_u32s(stsc.get(8..).unwrap_or_default()).chunks_exact(3).map(|e| (e[0], e[1])).collect())
+        .unwrap_or_default();
+
+    let mut locations = Vec::new();
+    let mut sample = 0;
+    for (i, chunk_offset) in chunk_offsets.iter().enumerate() {
+        let chunk = i as u32 + 1;
+        let per_chunk = runs.iter().rev().find(|(first, _)| *first <= chunk).map(|(_, n)| *n).unwrap_or(1);
+
+        let mut offset = *chunk_offset;
+        for _ in 0..per_chunk {
+            let Some(size) = sizes.get(sample) else {
+                return locations;
+            };
+            locations.push((offset, *size));
+            offset += *size as u64;
+            sample += 1;
+        }
+    }
+    locations
+}
+
+/// Parse a Nero chapter list, whose start times are in 100ns units
+fn parse_chpl(chpl: &[u8]) -> Vec<Chapter> {
+    let Some(version) = chpl.first() else {
+        return Vec::new();
+    };
+    // Version 1 adds a 4 byte reserved field after version/flags
+    let mut offset = if *version == 1 { 8 } else { 4 };
+    let Some(count) = chpl.get(offset) else {
+        return Vec::new();
+    };
+    offset += 1;
+
+    let mut chapters = Vec::new();
+    for _ in 0..*count {
+        let Some(start) = chpl.get(offset..offset + 8) else {
+            break;
+        };
+        let start = u64::from_be_bytes(start.try_into().unwrap_or_default());
+        let Some(len) = chpl.get(offset + 8) else {
+            break;
+        };
+        let Some(title) = chpl.get(offset + 9..offset + 9 + *len as usize) else {
+            break;
+        };
+        chapters.push(Chapter {
+            title: String::from_utf8_lossy(title).to_string(),
+            start: Duration::from_nanos(start * 100),
+        });
+        offset += 9 + *len as usize;
+    }
+    chapters
+}
+
+/// Split a buffer into big endian u32 values
+fn be_u32s(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4).map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]])).collect()
+}
+
+/// Map an audio sample entry to the codec it carries
+pub fn audio_codec(entry: Mp4Box<'_>) -> Option<AudioCodec> {
+    match &entry.kind {
+        b"alac" => Some(AudioCodec::Alac),
+        b"ec-3" => Some(AudioCodec::Eac3),
+        b"ac-3" => Some(AudioCodec::Ac3),
+        b"fLaC" => Some(AudioCodec::Flac),
+        b"Opus" => Some(AudioCodec::Opus),
+        b".mp3" => Some(AudioCodec::Mp3),
+        b"lpcm" | b"sowt" | b"twos" | b"in24" | b"in32" | b"fl32" | b"fl64" => Some(AudioCodec::Pcm),
+        // mp4a is a generic MPEG-4 audio entry, the esds descriptor says what's inside
+        b"mp4a" => match esds_object_type(entry.data) {
+            Some(0x69 | 0x6B) => Some(AudioCodec::Mp3),
+            _ => Some(AudioCodec::Aac),
+        },
+        _ => None,
+    }
+}
+
+/// Map a visual sample entry to the codec it carries
+pub fn video_codec(entry: Mp4Box<'_>) -> Option<VideoCodec> {
+    match &entry.kind {
+        b"avc1" | b"avc3" => Some(VideoCodec::H264),
+        b"hvc1" | b"hev1" => Some(VideoCodec::Hevc),
+        b"av01" => Some(VideoCodec::Av1),
+        b"vp08" => Some(VideoCodec::Vp8),
+        b"vp09" => Some(VideoCodec::Vp9),
+        b"mp4v" => Some(VideoCodec::Mpeg4),
+        _ => None,
+    }
+}
+
+/// Offset of the child boxes inside an audio sample entry
+pub(crate) fn audio_sample_entry_children(entry: &[u8]) -> Option<&[u8]> {
+    // QuickTime sound sample descriptions v1 and v2 extend the v0 layout
+    let version = u16::from_be_bytes(entry.get(8..10)?.try_into().ok()?);
+    let extra = match version {
+        1 => 16,
+        2 => 36,
+        _ => 0,
+    };
+    entry.get(28 + extra..)
+}
+
+/// The objectTypeIndication from the decoder config of an `mp4a` entry
+fn esds_object_type(entry: &[u8]) -> Option<u8> {
+    let esds = find(audio_sample_entry_children(entry)?, &[b"esds"])?;
+    // Skip version/flags
+    let mut data = esds.get(4..)?;
+
+    let (tag, body) = descriptor(data)?;
+    if tag != 0x03 {
+        return None;
+    }
+    // ES_ID followed by flags selecting optional fields
+    let flags = *body.get(2)?;
+    let mut offset = 3;
+    if flags & 0x80 != 0 {
+        offset += 2;
+    }
+    if flags & 0x40 != 0 {
+        offset += 1 + *body.get(offset)? as usize;
+    }
+    if flags & 0x20 != 0 {
+        offset += 2;
+    }
+    data = body.get(offset..)?;
+
+    let (tag, body) = descriptor(data)?;
+    (tag == 0x04).then(|| body.first().copied()).flatten()
+}
+
+/// Split an MPEG-4 descriptor into its tag and body
+fn descriptor(data: &[u8]) -> Option<(u8, &[u8])> {
+    let tag = *data.first()?;
+    let mut len = 0usize;
+    let mut offset = 1;
+    // Lengths use up to four bytes with the high bit flagging continuation
+    for _ in 0..4 {
+        let byte = *data.get(offset)?;
+        offset += 1;
+        len = (len << 7) | (byte & 0x7F) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Some((tag, data.get(offset..offset + len)?))
+}
+
+/// Header length and total size of the box starting at `data`
+fn parse_box_size(data: &[u8], remaining: u64) -> Option<(usize, u64)> {
+    if data.len() < 8 {
+        return None;
+    }
+    match u32::from_be_bytes(data[0..4].try_into().ok()?) {
+        // A size of zero means the box runs to the end of the file
+        0 => Some((8, remaining)),
+        // A size of one means a 64 bit size follows the type
+        1 => Some((16, u64::from_be_bytes(data.get(8..16)?.try_into().ok()?))),
+        size => Some((8, size as u64)),
+    }
+}
+
+/// Build a box from its type and payload
+pub(crate) fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Build a minimal `moov/trak` with a handler and a single sample entry
+#[cfg(test)]
+pub(crate) fn track(handler: &[u8; 4], entry: &[u8]) -> Vec<u8> {
+    let mut hdlr = vec![0u8; 8];
+    hdlr.extend_from_slice(handler);
+    hdlr.extend_from_slice(&[0; 12]);
+
+    let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    stsd.extend_from_slice(entry);
+
+    let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+    let minf = mp4_box(b"minf", &stbl);
+    let mut mdia = mp4_box(b"hdlr", &hdlr);
+    mdia.extend_from_slice(&minf);
+    mp4_box(b"trak", &mp4_box(b"mdia", &mdia))
+}
+
+/// Build an audio sample entry with the given child boxes
+#[cfg(test)]
+pub(crate) fn audio_entry(kind: &[u8; 4], children: &[u8]) -> Vec<u8> {
+    let mut payload = vec![0u8; 28];
+    payload.extend_from_slice(children);
+    mp4_box(kind, &payload)
+}
+
+/// Build an `ilst` item holding a single `data` box
+#[cfg(test)]
+pub(crate) fn ilst_item(kind: &[u8; 4], data_type: u32, value: &[u8]) -> Vec<u8> {
+    let mut data = data_type.to_be_bytes().to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(value);
+    mp4_box(kind, &mp4_box(b"data", &data))
+}
+
+/// Build a `moov/udta/meta` box around the given `ilst` items
+#[cfg(test)]
+pub(crate) fn udta(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut meta = vec![0, 0, 0, 0];
+    meta.extend_from_slice(&mp4_box(b"ilst", &items.concat()));
+    mp4_box(b"udta", &mp4_box(b"meta", &meta))
+}
+
+/// Build a visual sample entry with the given dimensions
+#[cfg(test)]
+pub(crate) fn video_entry(kind: &[u8; 4], width: u16, height: u16) -> Vec<u8> {
+    let mut payload = vec![0u8; 24];
+    payload.extend_from_slice(&width.to_be_bytes());
+    payload.extend_from_slice(&height.to_be_bytes());
+    payload.extend_from_slice(&[0; 50]);
+    mp4_box(kind, &payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn esds(object_type: u8) -> Vec<u8> {
+        let decoder_config = [0x04, 0x02, object_type, 0x15];
+        let mut es = vec![0x03, 3 + decoder_config.len() as u8, 0x00, 0x01, 0x00];
+        es.extend_from_slice(&decoder_config);
+        let mut payload = vec![0, 0, 0, 0];
+        payload.extend_from_slice(&es);
+        mp4_box(b"esds", &payload)
+    }
+
+    fn m4a(entry: &[u8]) -> Vec<u8> {
+        let mut bytes = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        bytes.extend_from_slice(&mp4_box(b"mdat", &[0; 16]));
+        let mut moov = track(b"vide", &mp4_box(b"avc1", &[0; 8]));
+        moov.extend_from_slice(&track(b"soun", entry));
+        bytes.extend_from_slice(&mp4_box(b"moov", &moov));
+        bytes
+    }
+
+    #[test]
+    fn test_find_nested_box() {
+        let data = mp4_box(b"moov", &mp4_box(b"udta", &mp4_box(b"meta", b"abc")));
+        assert_eq!(find(&data, &[b"moov", b"udta", b"meta"]), Some(&b"abc"[..]));
+        assert_eq!(find(&data, &[b"moov", b"trak"]), None);
+    }
+
+    #[test]
+    fn test_codec_from_sample_entry() {
+        let aac = audio_entry(b"mp4a", &esds(0x40));
+        let mp3 = audio_entry(b"mp4a", &esds(0x6B));
+        let alac = audio_entry(b"alac", &[]);
+
+        assert_eq!(audio_codec(boxes(&aac).next().unwrap()), Some(AudioCodec::Aac));
+        assert_eq!(audio_codec(boxes(&mp3).next().unwrap()), Some(AudioCodec::Mp3));
+        assert_eq!(audio_codec(boxes(&alac).next().unwrap()), Some(AudioCodec::Alac));
+    }
+
+    #[test]
+    fn test_read_audio_codec_skips_video_tracks() {
+        let temp_dir = TempDir::new().unwrap();
+        let alac = temp_dir.path().join("alac.m4a");
+        let eac3 = temp_dir.path().join("book.m4b");
+
+        fs::write(&alac, m4a(&audio_entry(b"alac", &[]))).unwrap();
+        fs::write(&eac3, m4a(&audio_entry(b"ec-3", &[]))).unwrap();
+
+        assert_eq!(read_audio_codec(&alac).unwrap(), Some(AudioCodec::Alac));
+        assert_eq!(read_audio_codec(&eac3).unwrap(), Some(AudioCodec::Eac3));
+    }
+
+    #[test]
+    fn test_read_ilst_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.m4a");
+
+        let mut freeform = mp4_box(b"mean", b"\0\0\0\0com.apple.iTunes");
+        freeform.extend_from_slice(&mp4_box(b"name", b"\0\0\0\0MusicBrainz Album Id"));
+        freeform.extend_from_slice(&mp4_box(b"data", b"\0\0\0\x01\0\0\0\0b7c1e6c0"));
+
+        let mut moov = track(b"soun", &audio_entry(b"alac", &[]));
+        moov.extend_from_slice(&udta(&[
+            ilst_item(b"\xA9nam", 1, b"Money"),
+            ilst_item(b"\xA9ART", 1, b"Pink Floyd"),
+            ilst_item(b"aART", 1, b"Pink Floyd"),
+            ilst_item(b"\xA9alb", 1, b"The Dark Side of the Moon"),
+            ilst_item(b"trkn", 0, &[0, 0, 0, 6, 0, 10, 0, 0]),
+            ilst_item(b"disk", 0, &[0, 0, 0, 1, 0, 1]),
+            ilst_item(b"cpil", 21, &[1]),
+            ilst_item(b"covr", 14, b"\x89PNG"),
+            mp4_box(b"----", &freeform),
+        ]));
+        let mut bytes = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        bytes.extend_from_slice(&mp4_box(b"moov", &moov));
+        fs::write(&path, &bytes).unwrap();
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.text("©nam"), Some("Money"));
+        assert_eq!(tags.text("aART"), Some("Pink Floyd"));
+        assert_eq!(tags.text("©alb"), Some("The Dark Side of the Moon"));
+        assert_eq!(tags.track(), Some((6, 10)));
+        assert_eq!(tags.disc(), Some((1, 1)));
+        assert_eq!(tags.get("cpil"), Some(&IlstValue::Integer(1)));
+        assert_eq!(tags.covers(), vec![&IlstValue::Png(b"\x89PNG".to_vec())]);
+        assert_eq!(tags.text("----:com.apple.iTunes:MUSICBRAINZ ALBUM ID"), Some("b7c1e6c0"));
+    }
+
+    #[test]
+    fn test_read_audio_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.m4a");
+
+        let mut entry = vec![0u8; 16];
+        entry.extend_from_slice(&2u16.to_be_bytes());
+        entry.extend_from_slice(&24u16.to_be_bytes());
+        entry.extend_from_slice(&[0; 4]);
+        entry.extend_from_slice(&[0xBB, 0x80, 0, 0]);
+        let trak = track(b"soun", &mp4_box(b"alac", &entry));
+
+        // Splice an mdhd with a 96 kHz timescale in front of the handler
+        let mut mdhd = vec![0u8; 12];
+        mdhd.extend_from_slice(&96_000u32.to_be_bytes());
+        mdhd.extend_from_slice(&(96_000u32 * 30).to_be_bytes());
+        mdhd.extend_from_slice(&[0; 4]);
+        let mdia = find(&trak, &[b"trak", b"mdia"]).unwrap();
+        let mut mdia_payload = mp4_box(b"mdhd", &mdhd);
+        mdia_payload.extend_from_slice(mdia);
+
+        let mut bytes = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        bytes.extend_from_slice(&mp4_box(b"moov", &mp4_box(b"trak", &mp4_box(b"mdia", &mdia_payload))));
+        fs::write(&path, &bytes).unwrap();
+
+        let info = read_audio_info(&path).unwrap().unwrap();
+        assert_eq!(info.codec, Some(AudioCodec::Alac));
+        assert_eq!((info.sample_rate, info.channels, info.bits_per_sample), (96_000, 2, 24));
+        assert_eq!(info.duration, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_read_video_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("movie.mp4");
+
+        let mut mvhd = vec![0u8; 12];
+        mvhd.extend_from_slice(&600u32.to_be_bytes());
+        mvhd.extend_from_slice(&(600u32 * 5400).to_be_bytes());
+        let mut moov = mp4_box(b"mvhd", &mvhd);
+        moov.extend_from_slice(&track(b"vide", &video_entry(b"avc1", 1280, 720)));
+        moov.extend_from_slice(&track(b"soun", &audio_entry(b"ac-3", &[])));
+        moov.extend_from_slice(&udta(&[ilst_item(b"\xA9nam", 1, b"Sintel")]));
+
+        let mut bytes = mp4_box(b"ftyp", b"isom\0\0\0\0isomavc1");
+        bytes.extend_from_slice(&mp4_box(b"moov", &moov));
+        fs::write(&path, &bytes).unwrap();
+
+        let info = read_video_info(&path).unwrap().unwrap();
+        assert_eq!(info.title.as_deref(), Some("Sintel"));
+        assert_eq!(info.duration, Some(Duration::from_secs(5400)));
+        assert_eq!(info.video, vec![VideoStream { codec: Some(VideoCodec::H264), width: 1280, height: 720 }]);
+        assert_eq!(info.audio, vec![Some(AudioCodec::Ac3)]);
+    }
+
+    #[test]
+    fn test_parse_nero_chapters() {
+        let mut chpl = vec![1, 0, 0, 0, 0, 0, 0, 0, 2];
+        chpl.extend_from_slice(&0u64.to_be_bytes());
+        chpl.push(5);
+        chpl.extend_from_slice(b"Intro");
+        chpl.extend_from_slice(&600_000_000u64.to_be_bytes());
+        chpl.push(9);
+        chpl.extend_from_slice(b"Chapter 1");
+
+        assert_eq!(parse_chpl(&chpl), vec![
+            Chapter { title: "Intro".to_string(), start: Duration::ZERO },
+            Chapter { title: "Chapter 1".to_string(), start: Duration::from_secs(60) },
+        ]);
+    }
+
+    #[test]
+    fn test_read_quicktime_chapter_track() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("book.m4b");
+
+        let mut samples = Vec::new();
+        for title in ["Opening", "The End"] {
+            samples.extend_from_slice(&(title.len() as u16).to_be_bytes());
+            samples.extend_from_slice(title.as_bytes());
+        }
+
+        let ftyp = mp4_box(b"ftyp", b"M4B \0\0\0\0M4B isom");
+        let mdat_offset = (ftyp.len() + 8) as u32;
+        let mdat = mp4_box(b"mdat", &samples);
+
+        let tkhd = |id: u32| {
+            let mut payload = vec![0u8; 12];
+            payload.extend_from_slice(&id.to_be_bytes());
+            mp4_box(b"tkhd", &payload)
+        };
+
+        // Audio track 1 points at chapter track 2
+        let mut audio = tkhd(1);
+        audio.extend_from_slice(&mp4_box(b"tref", &mp4_box(b"chap", &2u32.to_be_bytes())));
+
+        let mut mdhd = vec![0u8; 12];
+        mdhd.extend_from_slice(&1000u32.to_be_bytes());
+        let mut stbl = mp4_box(b"stts", &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0x27, 0x10]);
+        stbl.extend_from_slice(&mp4_box(b"stsz", &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 9, 0, 0, 0, 9]));
+        stbl.extend_from_slice(&mp4_box(b"stsc", &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1]));
+        let mut stco = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stco.extend_from_slice(&mdat_offset.to_be_bytes());
+        stbl.extend_from_slice(&mp4_box(b"stco", &stco));
+
+        let mut mdia = mp4_box(b"mdhd", &mdhd);
+        mdia.extend_from_slice(&mp4_box(b"minf", &mp4_box(b"stbl", &stbl)));
+        let mut text = tkhd(2);
+        text.extend_from_slice(&mp4_box(b"mdia", &mdia));
+
+        let mut moov = mp4_box(b"trak", &audio);
+        moov.extend_from_slice(&mp4_box(b"trak", &text));
+
+        let mut bytes = ftyp;
+        bytes.extend_from_slice(&mdat);
+        bytes.extend_from_slice(&mp4_box(b"moov", &moov));
+        fs::write(&path, &bytes).unwrap();
+
+        assert_eq!(read_chapters(&path).unwrap(), vec![
+            Chapter { title: "Opening".to_string(), start: Duration::ZERO },
+            Chapter { title: "The End".to_string(), start: Duration::from_secs(10) },
+        ]);
+    }
+
+    #[test]
+    fn test_missing_moov() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("empty.m4a");
+        fs::write(&path, mp4_box(b"ftyp", b"M4A \0\0\0\0")).unwrap();
+
+        assert_eq!(read_moov(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_write_tags_in_place() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.m4a");
+
+        let mut moov = track(b"soun", &audio_entry(b"alac", &[]));
+        moov.extend_from_slice(&udta(&[
+            ilst_item(b"\xA9nam", 1, b"Money"),
+            ilst_item(b"aART", 1, b"Floyd"),
+            ilst_item(b"cpil", 21, &[1]),
+            ilst_item(b"covr", 14, b"\x89PNG"),
+        ]));
+        let mut bytes = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        bytes.extend_from_slice(&mp4_box(b"moov", &moov));
+        bytes.extend_from_slice(&mp4_box(b"free", &[0; 256]));
+        bytes.extend_from_slice(&mp4_box(b"mdat", b"audio"));
+        fs::write(&path, &bytes).unwrap();
+
+        let items = [("aART".to_string(), IlstValue::Text("Pink Floyd".to_string()))];
+        write_tags(&path, &items, &["©nam"]).unwrap();
+
+        // The free box absorbs the change, so mdat stays where it was
+        let written = fs::read(&path).unwrap();
+        assert_eq!(written.len(), bytes.len());
+        assert!(written.ends_with(&mp4_box(b"mdat", b"audio")));
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.text("©nam"), None);
+        assert_eq!(tags.text("aART"), Some("Pink Floyd"));
+        assert_eq!(tags.get("cpil"), Some(&IlstValue::Integer(1)));
+        assert_eq!(tags.covers(), vec![&IlstValue::Png(b"\x89PNG".to_vec())]);
+    }
+
+    #[test]
+    fn test_write_tags_shrinks_moov_at_eof() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.m4a");
+
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"mdirappl");
+        hdlr.extend_from_slice(&[0; 9]);
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend_from_slice(&mp4_box(b"hdlr", &hdlr));
+        meta.extend_from_slice(&mp4_box(b"ilst", &ilst_item(b"\xA9nam", 1, b"Money!")));
+        let mut bytes = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        bytes.extend_from_slice(&mp4_box(b"mdat", b"audio"));
+        let moov_start = bytes.len();
+        bytes.extend_from_slice(&mp4_box(b"moov", &mp4_box(b"udta", &mp4_box(b"meta", &meta))));
+        fs::write(&path, &bytes).unwrap();
+
+        // Three bytes shorter leaves too little room for a free box
+        let items = [("©nam".to_string(), IlstValue::Text("Mon".to_string()))];
+        write_tags(&path, &items, &[]).unwrap();
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.text("©nam"), Some("Mon"));
+        let moov = read_moov(&path).unwrap().unwrap();
+        let meta = find(&moov, &[b"udta", b"meta"]).unwrap();
+        assert!(find(&meta[4..], &[b"hdlr"]).is_some());
+        assert!(fs::read(&path).unwrap().starts_with(&bytes[..moov_start]));
+    }
+
+    #[test]
+    fn test_write_tags_shifts_chunk_offsets() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.m4a");
+
+        let ftyp = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        let trak = |chunk_offset: u32| {
+            let mut stco = vec![0, 0, 0, 0, 0, 0, 0, 1];
+            stco.extend_from_slice(&chunk_offset.to_be_bytes());
+            let stbl = mp4_box(b"stbl", &mp4_box(b"stco", &stco));
+            mp4_box(b"trak", &mp4_box(b"mdia", &mp4_box(b"minf", &stbl)))
+        };
+        // The chunk starts right after the mdat header, which follows moov
+        let moov_len = 8 + trak(0).len();
+        let chunk_offset = (ftyp.len() + moov_len + 8) as u32;
+        let mut bytes = ftyp.clone();
+        bytes.extend_from_slice(&mp4_box(b"moov", &trak(chunk_offset)));
+        bytes.extend_from_slice(&mp4_box(b"mdat", b"audio"));
+        fs::write(&path, &bytes).unwrap();
+
+        let items = [
+            ("©ART".to_string(), IlstValue::Text("Pink Floyd".to_string())),
+            ("©ART".to_string(), IlstValue::Text("Dick Parry".to_string())),
+            ("trkn".to_string(), IlstValue::Pair(6, 10)),
+            ("----:com.apple.iTunes:MOOD".to_string(), IlstValue::Text("Dark".to_string())),
+        ];
+        write_tags(&path, &items, &[]).unwrap();
+
+        let tags = read_tags(&path).unwrap().unwrap();
+        assert_eq!(tags.items.iter().filter(|(k, _)| k == "©ART").count(), 2);
+        assert_eq!(tags.track(), Some((6, 10)));
+        assert_eq!(tags.text("----:com.apple.iTunes:MOOD"), Some("Dark"));
+
+        let moov = read_moov(&path).unwrap().unwrap();
+        let stco = find(&moov, &[b"trak", b"mdia", b"minf", b"stbl", b"stco"]).unwrap();
+        let offset = be_u32s(&stco[8..])[0] as usize;
+        assert!(offset > chunk_offset as usize);
+        assert_eq!(&fs::read(&path).unwrap()[offset..offset + 5], b"audio");
+    }
+}
@@ -0,0 +1,250 @@
+//! Ogg page parsing for Vorbis, Opus and FLAC-in-Ogg streams
+//!
+//! An Ogg file is a sequence of pages, each carrying segments of one logical
+//! bitstream. Packets are rebuilt from the segment lacing values, and the
+//! first packet of a stream identifies its codec.
+
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::metadata::vorbis::VorbisComments;
+use crate::{Error, Result};
+
+/// Size of the fixed part of a page header, before the segment table
+const PAGE_HEADER_LEN: usize = 27;
+
+/// Codec carried by the first logical bitstream of an Ogg file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OggCodec {
+    Vorbis,
+    Opus,
+    Flac,
+}
+
+impl OggCodec {
+    /// Identify a codec from the first packet of a logical bitstream
+    pub fn identify(packet: &[u8]) -> Option<Self> {
+        if packet.starts_with(b"\x01vorbis") {
+            Some(Self::Vorbis)
+        } else if packet.starts_with(b"OpusHead") {
+            Some(Self::Opus)
+        } else if packet.starts_with(b"\x7FFLAC") {
+            Some(Self::Flac)
+        } else {
+            None
+        }
+    }
+
+    /// Strip the codec specific prefix from the comment header packet
+    fn comment_body(self, packet: &[u8]) -> Option<&[u8]> {
+        match self {
+            Self::Vorbis => packet.strip_prefix(b"\x03vorbis"),
+            Self::Opus => packet.strip_prefix(b"OpusTags"),
+            // FLAC-in-Ogg wraps a native VORBIS_COMMENT metadata block (type 4)
+            Self::Flac => (packet.first().map(|b| b & 0x7F) == Some(4)).then(|| &packet[4.min(packet.len())..]),
+        }
+    }
+}
+
+/// A single page header
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageHeader {
+    pub header_type: u8,
+    pub granule_position: i64,
+    pub serial: u32,
+    pub sequence: u32,
+    /// Segment lacing values, each segment is at most 255 bytes
+    pub lacing: Vec<u8>,
+}
+
+impl PageHeader {
+    /// Parse a page header, returning it with the total header length
+    pub fn parse(bytes: &[u8]) -> Option<(Self, usize)> {
+        if bytes.len() < PAGE_HEADER_LEN || &bytes[0..4] != b"OggS" || bytes[4] != 0 {
+            return None;
+        }
+
+        let segments = bytes[26] as usize;
+        let lacing = bytes.get(PAGE_HEADER_LEN..PAGE_HEADER_LEN + segments)?.to_vec();
+
+        Some((
+            Self {
+                header_type: bytes[5],
+                granule_position: i64::from_le_bytes(bytes[6..14].try_into().ok()?),
+                serial: u32::from_le_bytes(bytes[14..18].try_into().ok()?),
+                sequence: u32::from_le_bytes(bytes[18..22].try_into().ok()?),
+                lacing,
+            },
+            PAGE_HEADER_LEN + segments,
+        ))
+    }
+
+    /// Whether the first packet on this page continues one from the previous page
+    pub fn is_continuation(&self) -> bool {
+        self.header_type & 0x01 != 0
+    }
+
+    /// Number of data bytes following the header
+    pub fn data_len(&self) -> usize {
+        self.lacing.iter().map(|l| *l as usize).sum()
+    }
+}
+
+/// Identify the codec of an in-memory Ogg stream from its first page
+pub fn identify_stream(bytes: &[u8]) -> Option<OggCodec> {
+    let (header, header_len) = PageHeader::parse(bytes)?;
+    let first_packet_len = header
+        .lacing
+        .iter()
+        .position(|l| *l < 255)
+        .map(|i| header.lacing[..=i].iter().map(|l| *l as usize).sum())
+        .unwrap_or(header.data_len());
+    let end = (header_len + first_packet_len).min(bytes.len());
+    OggCodec::identify(&bytes[header_len..end])
+}
+
+/// Read the first `count` packets of the first logical bitstream
+pub fn read_packets(reader: &mut impl Read, count: usize) -> Result<Vec<Vec<u8>>> {
+    let mut packets = Vec::new();
+    let mut current = Vec::new();
+    let mut serial = None;
+
+    while packets.len() < count {
+        let mut fixed = [0u8; PAGE_HEADER_LEN];
+        if reader.read_exact(&mut fixed).is_err() {
+            break;
+        }
+        let mut header_bytes = fixed.to_vec();
+        header_bytes.resize(PAGE_HEADER_LEN + fixed[26] as usize, 0);
+        reader.read_exact(&mut header_bytes[PAGE_HEADER_LEN..])?;
+
+        let (header, _) = PageHeader::parse(&header_bytes)
+            .ok_or_else(|| Error::InvalidMetadata("invalid ogg page".to_string()))?;
+        let mut data = vec![0u8; header.data_len()];
+        reader.read_exact(&mut data)?;
+
+        // Ignore pages from any other multiplexed bitstream
+        if *serial.get_or_insert(header.serial) != header.serial {
+            continue;
+        }
+
+        let mut offset = 0;
+        for lacing in &header.lacing {
+            current.extend_from_slice(&data[offset..offset + *lacing as usize]);
+            offset += *lacing as usize;
+            if *lacing < 255 {
+                packets.push(std::mem::take(&mut current));
+                if packets.len() == count {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(packets)
+}
+
+/// Read the codec and Vorbis comments of an Ogg file
+pub fn read_comments(path: &Path) -> Result<Option<(OggCodec, VorbisComments)>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let packets = read_packets(&mut reader, 2)?;
+
+    let Some(codec) = packets.first().and_then(|p| OggCodec::identify(p)) else {
+        return Ok(None);
+    };
+    let body = packets
+        .get(1)
+        .and_then(|p| codec.comment_body(p))
+        .ok_or_else(|| Error::InvalidMetadata("missing ogg comment header".to_string()))?;
+
+    Ok(Some((codec, VorbisComments::parse(body)?)))
+}
+
+#[cfg(test)]
+pub(crate) fn page(header_type: u8, serial: u32, sequence: u32, packets: &[&[u8]]) -> Vec<u8> {
+    let mut lacing = Vec::new();
+    let mut data = Vec::new();
+    for packet in packets {
+        let mut remaining = packet.len();
+        while remaining >= 255 {
+            lacing.push(255);
+            remaining -= 255;
+        }
+        lacing.push(remaining as u8);
+        data.extend_from_slice(packet);
+    }
+
+    let mut out = b"OggS\0".to_vec();
+    out.push(header_type);
+    out.extend_from_slice(&0i64.to_le_bytes());
+    out.extend_from_slice(&serial.to_le_bytes());
+    out.extend_from_slice(&sequence.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.push(lacing.len() as u8);
+    out.extend_from_slice(&lacing);
+    out.extend_from_slice(&data);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::vorbis::encode;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_identify_codecs() {
+        assert_eq!(identify_stream(&page(2, 1, 0, &[b"\x01vorbis\0\0\0\0"])), Some(OggCodec::Vorbis));
+        assert_eq!(identify_stream(&page(2, 1, 0, &[b"OpusHead\x01\x02"])), Some(OggCodec::Opus));
+        assert_eq!(identify_stream(&page(2, 1, 0, &[b"\x7FFLAC\x01\x00\x00\x01fLaC"])), Some(OggCodec::Flac));
+        assert_eq!(identify_stream(&page(2, 1, 0, &[b"\x80theora"])), None);
+        assert_eq!(identify_stream(b"RIFF"), None);
+    }
+
+    #[test]
+    fn test_read_opus_comments_across_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.opus");
+
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&encode("libopus", &[("TITLE", "Song"), ("COMMENT", &"x".repeat(600))]));
+
+        // Split the comment packet over two pages with a continuation flag
+        let split = 510;
+        let mut first = page(0, 7, 1, &[&tags[..split]]);
+        // The first page must not terminate the packet, so drop its final lacing value
+        first[26] -= 1;
+        first.remove(PAGE_HEADER_LEN + first[26] as usize);
+
+        let mut bytes = page(2, 7, 0, &[b"OpusHead\x01\x02"]);
+        bytes.extend_from_slice(&page(2, 9, 0, &[b"\x80theora"]));
+        bytes.extend_from_slice(&first);
+        bytes.extend_from_slice(&page(1, 7, 2, &[&tags[split..]]));
+        fs::write(&path, &bytes).unwrap();
+
+        let (codec, comments) = read_comments(&path).unwrap().unwrap();
+        assert_eq!(codec, OggCodec::Opus);
+        assert_eq!(comments.vendor, "libopus");
+        assert_eq!(comments.get("title"), Some("Song"));
+        assert_eq!(comments.get("comment").map(str::len), Some(600));
+    }
+
+    #[test]
+    fn test_read_flac_in_ogg_comments() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.oga");
+
+        let body = encode("reference libFLAC", &[("ALBUM", "Album")]);
+        let mut block = vec![0x84, 0, 0, body.len() as u8];
+        block.extend_from_slice(&body);
+
+        let mut bytes = page(2, 1, 0, &[b"\x7FFLAC\x01\x00\x00\x01fLaC"]);
+        bytes.extend_from_slice(&page(0, 1, 1, &[&block]));
+        fs::write(&path, &bytes).unwrap();
+
+        let (codec, comments) = read_comments(&path).unwrap().unwrap();
+        assert_eq!(codec, OggCodec::Flac);
+        assert_eq!(comments.get("ALBUM"), Some("Album"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Chunk walking for RIFF (WAV) and IFF (AIFF) files
+//!
+//! Both formats are a container chunk holding a list of `id, size, data`
+//! chunks. RIFF stores sizes little endian, IFF stores them big endian, and
+//! both pad odd sized chunks to an even length. Tags come from the WAV
+//! `LIST/INFO` chunk, the AIFF text chunks and an embedded `id3 ` chunk.
+
+use std::fs;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::metadata::id3::{decode_latin1, Id3v2Tag};
+use crate::Result;
+
+/// AIFF chunks that hold plain text metadata
+const AIFF_TEXT_CHUNKS: &[&[u8; 4]] = &[b"NAME", b"AUTH", b"ANNO", b"(c) "];
+
+/// The form of the outer container chunk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChunkForm {
+    Wave,
+    Aiff,
+    /// Compressed AIFF
+    Aifc,
+}
+
+impl ChunkForm {
+    /// Identify the container from the first 12 bytes of a file
+    pub fn identify(bytes: &[u8]) -> Option<Self> {
+        let (id, form) = (bytes.get(0..4)?, bytes.get(8..12)?);
+        match (id, form) {
+            (b"RIFF" | b"RF64", b"WAVE") => Some(Self::Wave),
+            (b"FORM", b"AIFF") => Some(Self::Aiff),
+            (b"FORM", b"AIFC") => Some(Self::Aifc),
+            _ => None,
+        }
+    }
+
+    fn read_size(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Self::Wave => u32::from_le_bytes(bytes),
+            Self::Aiff | Self::Aifc => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// Metadata found in the chunks of a WAV or AIFF file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkMetadata {
+    pub form: ChunkForm,
+    /// `LIST/INFO` entries (`INAM`, `IART`, ...) or AIFF text chunks (`NAME`, `AUTH`, ...)
+    pub info: Vec<(String, String)>,
+    /// An embedded ID3v2 tag
+    pub id3: Option<Id3v2Tag>,
+}
+
+impl ChunkMetadata {
+    /// The value of an INFO entry or text chunk
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.info.iter().find(|(k, _)| k == id).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Read the INFO/text chunks and embedded ID3 tag of a WAV or AIFF file
+pub fn read_chunks(path: &Path) -> Result<Option<ChunkMetadata>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+
+    let mut header = [0u8; 12];
+    if reader.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+    let Some(form) = ChunkForm::identify(&header) else {
+        return Ok(None);
+    };
+
+    let mut metadata = ChunkMetadata { form, info: Vec::new(), id3: None };
+
+    let mut chunk_header = [0u8; 8];
+    while reader.read_exact(&mut chunk_header).is_ok() {
+        let id: [u8; 4] = [chunk_header[0], chunk_header[1], chunk_header[2], chunk_header[3]];
+        let size = form.read_size([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]) as u64;
+        let padded = size + (size & 1);
+
+        let wanted = matches!(&id, b"LIST" | b"id3 " | b"ID3 ") || AIFF_TEXT_CHUNKS.contains(&&id);
+        if !wanted {
+            // Skip audio data and any other chunk without buffering it
+            reader.seek(SeekFrom::Current(padded as i64))?;
+            continue;
+        }
+
+        // Read through `take` so a corrupt size can't allocate more than the file holds
+        let mut data = Vec::new();
+        (&mut reader).take(size).read_to_end(&mut data)?;
+        if data.len() as u64 != size {
+            // Truncated files are common for interrupted recordings, keep what we have
+            break;
+        }
+        if padded > size {
+            reader.seek(SeekFrom::Current(1))?;
+        }
+
+        match &id {
+            b"LIST" if data.starts_with(b"INFO") => metadata.info.extend(parse_info(&data[4..])),
+            b"LIST" => {}
+            // A tag we can't parse shouldn't cost us the INFO chunks
+            b"id3 " | b"ID3 " => metadata.id3 = Id3v2Tag::parse(&data).ok(),
+            _ => metadata.info.push((String::from_utf8_lossy(&id).to_string(), text_value(&data))),
+        }
+    }
+
+    Ok(Some(metadata))
+}
+
+/// Parse the subchunks of a `LIST/INFO` chunk, which are always little endian
+fn parse_info(mut data: &[u8]) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    while data.len() >= 8 {
+        let id = String::from_utf8_lossy(&data[0..4]).to_string();
+        let size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let Some(value) = data.get(8..8 + size) else {
+            break;
+        };
+        entries.push((id, text_value(value)));
+
+        let next = 8 + size + (size & 1);
+        data = data.get(next..).unwrap_or_default();
+    }
+    entries
+}
+
+/// Decode a null terminated text value, preferring UTF-8 and falling back to ISO-8859-1
+fn text_value(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    match std::str::from_utf8(&data[..end]) {
+        Ok(text) => text.trim().to_string(),
+        Err(_) => decode_latin1(&data[..end]).trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn chunk(form: ChunkForm, id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    match form {
+        ChunkForm::Wave => out.extend_from_slice(&(data.len() as u32).to_le_bytes()),
+        _ => out.extend_from_slice(&(data.len() as u32).to_be_bytes()),
+    }
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+    out
+}
+
+#[cfg(test)]
+pub(crate) fn container(form: ChunkForm, chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body: Vec<u8> = chunks.concat();
+    let (id, kind): (&[u8], &[u8]) = match form {
+        ChunkForm::Wave => (b"RIFF", b"WAVE"),
+        ChunkForm::Aiff => (b"FORM", b"AIFF"),
+        ChunkForm::Aifc => (b"FORM", b"AIFC"),
+    };
+    let size = (body.len() + 4) as u32;
+    let mut out = id.to_vec();
+    match form {
+        ChunkForm::Wave => out.extend_from_slice(&size.to_le_bytes()),
+        _ => out.extend_from_slice(&size.to_be_bytes()),
+    }
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&body);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_identify_forms() {
+        assert_eq!(ChunkForm::identify(b"RIFF\0\0\0\0WAVE"), Some(ChunkForm::Wave));
+        assert_eq!(ChunkForm::identify(b"FORM\0\0\0\0AIFF"), Some(ChunkForm::Aiff));
+        assert_eq!(ChunkForm::identify(b"FORM\0\0\0\0AIFC"), Some(ChunkForm::Aifc));
+        assert_eq!(ChunkForm::identify(b"RIFF\0\0\0\0AVI "), None);
+    }
+
+    #[test]
+    fn test_read_wav_info_and_id3() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("rip.wav");
+
+        let mut info = b"INFO".to_vec();
+        info.extend_from_slice(&chunk(ChunkForm::Wave, b"INAM", b"Side A\0"));
+        info.extend_from_slice(&chunk(ChunkForm::Wave, b"IART", b"Artist\0"));
+
+        let id3 = b"ID3\x04\x00\x00\x00\x00\x00\x0DTALB\x00\x00\x00\x03\x00\x00\x03LP".to_vec();
+
+        let bytes = container(ChunkForm::Wave, &[
+            chunk(ChunkForm::Wave, b"fmt ", &[0; 16]),
+            chunk(ChunkForm::Wave, b"data", &[0; 9]),
+            chunk(ChunkForm::Wave, b"LIST", &info),
+            chunk(ChunkForm::Wave, b"id3 ", &id3),
+        ]);
+        fs::write(&path, &bytes).unwrap();
+
+        let metadata = read_chunks(&path).unwrap().unwrap();
+        assert_eq!(metadata.form, ChunkForm::Wave);
+        assert_eq!(metadata.get("INAM"), Some("Side A"));
+        assert_eq!(metadata.get("IART"), Some("Artist"));
+        assert_eq!(metadata.id3.unwrap().text("TALB"), Some("LP"));
+    }
+
+    #[test]
+    fn test_read_aiff_text_chunks() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("field.aiff");
+
+        let bytes = container(ChunkForm::Aiff, &[
+            chunk(ChunkForm::Aiff, b"COMM", &[0; 18]),
+            chunk(ChunkForm::Aiff, b"NAME", b"Morning Birds"),
+            chunk(ChunkForm::Aiff, b"SSND", &[0; 32]),
+        ]);
+        fs::write(&path, &bytes).unwrap();
+
+        let metadata = read_chunks(&path).unwrap().unwrap();
+        assert_eq!(metadata.form, ChunkForm::Aiff);
+        assert_eq!(metadata.get("NAME"), Some("Morning Birds"));
+        assert_eq!(metadata.id3, None);
+    }
+
+    #[test]
+    fn test_read_chunk_with_corrupt_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("field.aiff");
+
+        let mut bytes = container(ChunkForm::Aiff, &[
+            chunk(ChunkForm::Aiff, b"COMM", &[0; 18]),
+            chunk(ChunkForm::Aiff, b"NAME", b"Morning Birds"),
+        ]);
+        bytes.extend_from_slice(b"ANNO\xFF\xFF\xFF\xF0Dawn");
+        fs::write(&path, &bytes).unwrap();
+
+        let metadata = read_chunks(&path).unwrap().unwrap();
+        assert_eq!(metadata.get("NAME"), Some("Morning Birds"));
+        assert_eq!(metadata.get("ANNO"), None);
+    }
+
+    #[test]
+    fn test_read_wav_with_bad_id3() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("rip.wav");
+
+        let mut info = b"INFO".to_vec();
+        info.extend_from_slice(&chunk(ChunkForm::Wave, b"INAM", b"Side A\0"));
+
+        let bytes = container(ChunkForm::Wave, &[
+            chunk(ChunkForm::Wave, b"LIST", &info),
+            chunk(ChunkForm::Wave, b"id3 ", b"ID3\x02\x00\x00\x00\x00\x00\x00"),
+        ]);
+        fs::write(&path, &bytes).unwrap();
+
+        let metadata = read_chunks(&path).unwrap().unwrap();
+        assert_eq!(metadata.get("INAM"), Some("Side A"));
+        assert_eq!(metadata.id3, None);
+    }
+}
@@ -0,0 +1,848 @@
+//! Format agnostic tags
+//!
+//! Every format stores the same handful of fields under different names:
+//! `TITLE` in Vorbis comments, `TIT2` in ID3v2, `©nam` in MP4 and `INAM` in
+//! a WAV INFO chunk. `MetadataReader` maps each of them onto `Tags` so path
+//! generation only ever deals with one model, and `MetadataWriter` maps them
+//! back for the formats we can write.
+
+use std::path::Path;
+
+use crate::files::{detect_filetype, SupportedFiletype};
+use crate::metadata::ffprobe::Ffprobe;
+use crate::metadata::id3::{self, FrameContent, Id3v1Tag, Id3v2Tag};
+use crate::metadata::mp4::{self, IlstTags, IlstValue};
+use crate::metadata::riff::{self, ChunkMetadata};
+use crate::metadata::vorbis::VorbisComments;
+use crate::metadata::{epub, flac, matroska, ogg};
+use crate::{Error, Result};
+
+/// Custom field holding an ebook or audiobook series name
+pub const SERIES: &str = "SERIES";
+/// Custom field holding the position within `SERIES`, which may be fractional
+pub const SERIES_INDEX: &str = "SERIES_INDEX";
+/// Custom field holding who reads an audiobook
+pub const NARRATOR: &str = "NARRATOR";
+/// Custom field holding the blurb of a book
+pub const DESCRIPTION: &str = "DESCRIPTION";
+
+/// Vorbis comments that map onto a `Tags` field rather than a custom one
+const VORBIS_FIELDS: &[&str] = &[
+    "TITLE", "SUBTITLE", "ARTIST", "ALBUMARTIST", "ALBUM ARTIST", "ALBUM", "TRACKNUMBER", "TRACKTOTAL", "TOTALTRACKS",
+    "DISCNUMBER", "DISCTOTAL", "TOTALDISCS", "DATE", "GENRE", "TITLESORT", "ARTISTSORT", "ALBUMARTISTSORT",
+    "ALBUMSORT", "MUSICBRAINZ_TRACKID", "MUSICBRAINZ_RELEASETRACKID", "MUSICBRAINZ_ALBUMID",
+    "MUSICBRAINZ_RELEASEGROUPID", "MUSICBRAINZ_ARTISTID", "MUSICBRAINZ_ALBUMARTISTID", "COMPILATION",
+    "COMPOSER",
+];
+
+/// Large embedded Vorbis comment blobs that aren't useful as text
+const VORBIS_BLOBS: &[&str] = &["METADATA_BLOCK_PICTURE", "COVERART", "CUESHEET"];
+
+/// Freeform `ilst` items are written under iTunes' own namespace
+const ILST_FREEFORM: &str = "----:com.apple.iTunes:";
+
+/// MusicBrainz identifiers, as written by Picard
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MusicBrainzIds {
+    pub recording: Option<String>,
+    pub release_track: Option<String>,
+    pub release: Option<String>,
+    pub release_group: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+}
+
+/// Tags read from any supported format
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub artists: Vec<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    /// The composer, which audiobook rippers often fill with the narrator
+    pub composer: Option<String>,
+    pub track_number: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    /// Release date as written, usually `YYYY` or `YYYY-MM-DD`
+    pub date: Option<String>,
+    pub genre: Option<String>,
+    /// Set on the tracks of a various artists release
+    pub compilation: bool,
+    pub sort_title: Option<String>,
+    pub sort_artist: Option<String>,
+    pub sort_album_artist: Option<String>,
+    pub sort_album: Option<String>,
+    pub musicbrainz: MusicBrainzIds,
+    /// Any other text fields, keyed as the format writes them
+    pub custom: Vec<(String, String)>,
+}
+
+impl Tags {
+    /// The album artist, falling back to the first track artist
+    pub fn primary_artist(&self) -> Option<&str> {
+        self.album_artist.as_deref().or(self.artists.first().map(String::as_str))
+    }
+
+    /// The first value of a custom field, ignoring case
+    pub fn custom(&self, key: &str) -> Option<&str> {
+        self.custom.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str())
+    }
+
+    /// The four digit year at the start of `date`
+    pub fn year(&self) -> Option<u32> {
+        let date = self.date.as_deref()?.trim();
+        date.get(..4).filter(|y| y.chars().all(|c| c.is_ascii_digit()))?.parse().ok()
+    }
+
+    /// Fill the fields this has no value for from `other`, keeping every value already set
+    pub fn fill_missing(&mut self, other: &Tags) {
+        fn fill<T: Clone>(field: &mut Option<T>, other: &Option<T>) {
+            if field.is_none() {
+                *field = other.clone();
+            }
+        }
+
+        fill(&mut self.title, &other.title);
+        fill(&mut self.subtitle, &other.subtitle);
+        if self.artists.is_empty() {
+            self.artists = other.artists.clone();
+        }
+        fill(&mut self.album_artist, &other.album_artist);
+        fill(&mut self.album, &other.album);
+        fill(&mut self.composer, &other.composer);
+        fill(&mut self.track_number, &other.track_number);
+        fill(&mut self.track_total, &other.track_total);
+        fill(&mut self.disc_number, &other.disc_number);
+        fill(&mut self.disc_total, &other.disc_total);
+        fill(&mut self.date, &other.date);
+        fill(&mut self.genre, &other.genre);
+    }
+
+    /// Build tags from Vorbis comments
+    pub fn from_vorbis(comments: &VorbisComments) -> Self {
+        let get = |key: &str| text(comments.get(key));
+        let (track_number, track_total) = number_pair(comments.get("TRACKNUMBER"));
+        let (disc_number, disc_total) = number_pair(comments.get("DISCNUMBER"));
+        let total = |keys: &[&str]| keys.iter().find_map(|k| comments.get(k)).and_then(|v| v.trim().parse().ok());
+
+        Self {
+            title: get("TITLE"),
+            subtitle: get("SUBTITLE"),
+            artists: comments.get_all("ARTIST").into_iter().filter_map(|a| text(Some(a))).collect(),
+            album_artist: get("ALBUMARTIST").or_else(|| get("ALBUM ARTIST")),
+            album: get("ALBUM"),
+            composer: get("COMPOSER"),
+            track_number,
+            track_total: track_total.or_else(|| total(&["TRACKTOTAL", "TOTALTRACKS"])),
+            disc_number,
+            disc_total: disc_total.or_else(|| total(&["DISCTOTAL", "TOTALDISCS"])),
+            date: get("DATE"),
+            genre: get("GENRE"),
+            compilation: flag(comments.get("COMPILATION")),
+            sort_title: get("TITLESORT"),
+            sort_artist: get("ARTISTSORT"),
+            sort_album_artist: get("ALBUMARTISTSORT"),
+            sort_album: get("ALBUMSORT"),
+            musicbrainz: MusicBrainzIds {
+                recording: get("MUSICBRAINZ_TRACKID"),
+                release_track: get("MUSICBRAINZ_RELEASETRACKID"),
+                release: get("MUSICBRAINZ_ALBUMID"),
+                release_group: get("MUSICBRAINZ_RELEASEGROUPID"),
+                artist: get("MUSICBRAINZ_ARTISTID"),
+                album_artist: get("MUSICBRAINZ_ALBUMARTISTID"),
+            },
+            custom: comments
+                .comments
+                .iter()
+                .filter(|(k, _)| !VORBIS_FIELDS.iter().chain(VORBIS_BLOBS).any(|known| k.eq_ignore_ascii_case(known)))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Build tags from an ID3v2 tag
+    pub fn from_id3v2(tag: &Id3v2Tag) -> Self {
+        let get = |id: &str| text(tag.text(id));
+        let user = |description: &str| text(tag.user_text(description).and_then(|v| v.first()).map(String::as_str));
+        let (track_number, track_total) = number_pair(tag.text("TRCK"));
+        let (disc_number, disc_total) = number_pair(tag.text("TPOS"));
+
+        let artists = match tag.get("TPE1") {
+            Some(FrameContent::Text(values)) => values.iter().filter_map(|a| text(Some(a))).collect(),
+            _ => Vec::new(),
+        };
+        // UFID holds the recording id as `owner\0id`
+        let recording = tag.frames.iter().find_map(|f| match (&f.id[..], &f.content) {
+            ("UFID", FrameContent::Binary(data)) => {
+                let (owner, id) = data.split_at(data.iter().position(|b| *b == 0)?);
+                (owner == b"http://musicbrainz.org").then(|| String::from_utf8_lossy(&id[1..]).to_string())
+            }
+            _ => None,
+        });
+
+        Self {
+            title: get("TIT2"),
+            subtitle: get("TIT3"),
+            artists,
+            album_artist: get("TPE2"),
+            album: get("TALB"),
+            composer: get("TCOM"),
+            track_number,
+            track_total,
+            disc_number,
+            disc_total,
+            // v2.4 recording time, v2.3 year
+            date: get("TDRC").or_else(|| get("TYER")),
+            genre: tag.genre(),
+            // iTunes' own frame, never part of the spec but written by every tagger
+            compilation: flag(tag.text("TCMP")),
+            sort_title: get("TSOT"),
+            sort_artist: get("TSOP"),
+            sort_album_artist: get("TSO2"),
+            sort_album: get("TSOA"),
+            musicbrainz: MusicBrainzIds {
+                recording,
+                release_track: user("MusicBrainz Release Track Id"),
+                release: user("MusicBrainz Album Id"),
+                release_group: user("MusicBrainz Release Group Id"),
+                artist: user("MusicBrainz Artist Id"),
+                album_artist: user("MusicBrainz Album Artist Id"),
+            },
+            custom: tag
+                .frames
+                .iter()
+                .filter_map(|f| match &f.content {
+                    FrameContent::UserText { description, values } if !description.starts_with("MusicBrainz ") => {
+                        Some((description.clone(), values.join("; ")))
+                    }
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Build tags from an ID3v1 tag, which only has the basics
+    pub fn from_id3v1(tag: &Id3v1Tag) -> Self {
+        Self {
+            title: text(tag.title.as_deref()),
+            artists: text(tag.artist.as_deref()).into_iter().collect(),
+            album: text(tag.album.as_deref()),
+            track_number: tag.track.filter(|t| *t > 0).map(u32::from),
+            date: text(tag.year.as_deref()),
+            genre: tag.genre.and_then(id3::genre_name).map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    /// Build tags from MP4 `ilst` items
+    pub fn from_ilst(tags: &IlstTags) -> Self {
+        let get = |key: &str| text(tags.text(key));
+        let freeform = |name: &str| get(&format!("{}{}", ILST_FREEFORM, name));
+        let pair = |pair: Option<(u16, u16)>| {
+            let (number, total) = pair.unwrap_or((0, 0));
+            ((number > 0).then_some(number as u32), (total > 0).then_some(total as u32))
+        };
+        let (track_number, track_total) = pair(tags.track());
+        let (disc_number, disc_total) = pair(tags.disc());
+
+        // `gnre` is an ID3v1 genre index plus one
+        let genre = get("©gen").or_else(|| {
+            let index = match tags.get("gnre")? {
+                IlstValue::Binary(data) if data.len() == 2 => u16::from_be_bytes([data[0], data[1]]) as i64,
+                IlstValue::Integer(index) => *index,
+                _ => return None,
+            };
+            id3::genre_name(u8::try_from(index - 1).ok()?).map(str::to_string)
+        });
+
+        Self {
+            title: get("©nam"),
+            subtitle: freeform("SUBTITLE"),
+            artists: tags
+                .items
+                .iter()
+                .filter(|(key, _)| key == "©ART")
+                .filter_map(|(_, value)| match value {
+                    IlstValue::Text(artist) => text(Some(artist)),
+                    _ => None,
+                })
+                .collect(),
+            album_artist: get("aART"),
+            album: get("©alb"),
+            composer: get("©wrt"),
+            track_number,
+            track_total,
+            disc_number,
+            disc_total,
+            date: get("©day"),
+            genre,
+            compilation: matches!(tags.get("cpil"), Some(IlstValue::Integer(n)) if *n != 0),
+            sort_title: get("sonm"),
+            sort_artist: get("soar"),
+            sort_album_artist: get("soaa"),
+            sort_album: get("soal"),
+            musicbrainz: MusicBrainzIds {
+                recording: freeform("MusicBrainz Track Id"),
+                release_track: freeform("MusicBrainz Release Track Id"),
+                release: freeform("MusicBrainz Album Id"),
+                release_group: freeform("MusicBrainz Release Group Id"),
+                artist: freeform("MusicBrainz Artist Id"),
+                album_artist: freeform("MusicBrainz Album Artist Id"),
+            },
+            custom: tags
+                .items
+                .iter()
+                .filter_map(|(key, value)| match (key.strip_prefix("----:"), value) {
+                    (Some(name), IlstValue::Text(text)) if !name.contains(":MusicBrainz ") => {
+                        let name = name.rsplit(':').next().unwrap_or(name);
+                        (!name.eq_ignore_ascii_case("SUBTITLE")).then(|| (name.to_string(), text.clone()))
+                    }
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Build tags from WAV INFO or AIFF text chunks, preferring an embedded ID3 tag
+    pub fn from_chunks(metadata: &ChunkMetadata) -> Self {
+        if let Some(tag) = &metadata.id3 {
+            return Self::from_id3v2(tag);
+        }
+
+        let get = |id: &str| text(metadata.get(id));
+        Self {
+            title: get("INAM").or_else(|| get("NAME")),
+            artists: get("IART").or_else(|| get("AUTH")).into_iter().collect(),
+            album: get("IPRD"),
+            track_number: number_pair(metadata.get("ITRK").or(metadata.get("IPRT"))).0,
+            date: get("ICRD"),
+            genre: get("IGNR"),
+            ..Default::default()
+        }
+    }
+
+    /// Build tags from an EPUB package, keeping the series as custom fields
+    pub fn from_epub(package: &epub::EpubPackage) -> Self {
+        let mut custom = Vec::new();
+        if let Some(series) = &package.series {
+            custom.push((SERIES.to_string(), series.clone()));
+        }
+        if let Some(index) = package.series_index {
+            custom.push((SERIES_INDEX.to_string(), index.to_string()));
+        }
+
+        Self {
+            title: package.title.clone(),
+            artists: package.authors.clone(),
+            custom,
+            ..Default::default()
+        }
+    }
+}
+
+impl Tags {
+    /// Write the fields that differ from `original` over the matching Vorbis comments
+    ///
+    /// Only changed fields are touched, so repeated values and the formatting
+    /// of everything else survive an edit. A changed field that's now unset
+    /// is removed, and changed custom fields replace every comment with the
+    /// same key.
+    pub fn merge_into_vorbis(&self, original: &Tags, comments: &mut VorbisComments) {
+        if self.artists != original.artists {
+            let artists: Vec<&str> = self.artists.iter().map(String::as_str).collect();
+            comments.set("ARTIST", &artists);
+        }
+        for ((key, value), (_, old)) in self.vorbis_fields().into_iter().zip(original.vorbis_fields()) {
+            if value != old {
+                comments.set(key, value.as_deref().as_slice());
+            }
+        }
+        // Spellings some taggers use, superseded by the fields above
+        if self.album_artist != original.album_artist {
+            comments.set("ALBUM ARTIST", &[]);
+        }
+
+        // A number and its total are written together, since `3/12` style numbers hold both
+        let number = |n: Option<u32>| n.map(|n| n.to_string());
+        let pairs = [
+            (["TRACKNUMBER", "TRACKTOTAL", "TOTALTRACKS"], (self.track_number, self.track_total), (original.track_number, original.track_total)),
+            (["DISCNUMBER", "DISCTOTAL", "TOTALDISCS"], (self.disc_number, self.disc_total), (original.disc_number, original.disc_total)),
+        ];
+        for ([number_key, total_key, alias], (n, total), old) in pairs {
+            if (n, total) != old {
+                comments.set(number_key, number(n).as_deref().as_slice());
+                comments.set(total_key, number(total).as_deref().as_slice());
+                comments.set(alias, &[]);
+            }
+        }
+
+        for (key, values) in self.changed_custom(original) {
+            comments.set(key, &values);
+        }
+    }
+
+    /// The single valued Vorbis comments `Tags` models, in the order they're written
+    fn vorbis_fields(&self) -> [(&'static str, Option<String>); 18] {
+        let mb = &self.musicbrainz;
+        [
+            ("TITLE", self.title.clone()),
+            ("SUBTITLE", self.subtitle.clone()),
+            ("ALBUMARTIST", self.album_artist.clone()),
+            ("ALBUM", self.album.clone()),
+            ("COMPOSER", self.composer.clone()),
+            ("DATE", self.date.clone()),
+            ("GENRE", self.genre.clone()),
+            ("COMPILATION", self.compilation.then(|| "1".to_string())),
+            ("TITLESORT", self.sort_title.clone()),
+            ("ARTISTSORT", self.sort_artist.clone()),
+            ("ALBUMARTISTSORT", self.sort_album_artist.clone()),
+            ("ALBUMSORT", self.sort_album.clone()),
+            ("MUSICBRAINZ_TRACKID", mb.recording.clone()),
+            ("MUSICBRAINZ_RELEASETRACKID", mb.release_track.clone()),
+            ("MUSICBRAINZ_ALBUMID", mb.release.clone()),
+            ("MUSICBRAINZ_RELEASEGROUPID", mb.release_group.clone()),
+            ("MUSICBRAINZ_ARTISTID", mb.artist.clone()),
+            ("MUSICBRAINZ_ALBUMARTISTID", mb.album_artist.clone()),
+        ]
+    }
+
+    /// Custom keys whose values differ from `original`, with every value they now have
+    fn changed_custom<'a>(&'a self, original: &'a Tags) -> Vec<(&'a str, Vec<&'a str>)> {
+        let values = |tags: &'a Tags, key: &str| -> Vec<&'a str> {
+            tags.custom.iter().filter(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str()).collect()
+        };
+
+        let mut keys: Vec<&str> = Vec::new();
+        for (key, _) in self.custom.iter().chain(&original.custom) {
+            if !keys.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+                keys.push(key);
+            }
+        }
+        keys.into_iter()
+            .filter(|key| values(self, key) != values(original, key))
+            .map(|key| (key, values(self, key)))
+            .collect()
+    }
+
+    /// The `ilst` items for the fields that differ from `current`, and the keys of the items they replace
+    pub fn to_ilst(&self, current: &IlstTags) -> (Vec<(String, IlstValue)>, Vec<String>) {
+        let original = Tags::from_ilst(current);
+        let mut items = Vec::new();
+        let mut remove = Vec::new();
+        let mut replace = |key: &str, values: Vec<IlstValue>| {
+            remove.push(key.to_string());
+            items.extend(values.into_iter().map(|value| (key.to_string(), value)));
+        };
+
+        if self.artists != original.artists {
+            replace("©ART", self.artists.iter().cloned().map(IlstValue::Text).collect());
+        }
+        for ((key, value), (_, old)) in self.ilst_fields().into_iter().zip(original.ilst_fields()) {
+            if value != old {
+                replace(&key, value.into_iter().collect());
+            }
+        }
+        // `gnre` is superseded by the `©gen` written above
+        if self.genre != original.genre {
+            replace("gnre", Vec::new());
+        }
+        for (key, values) in self.changed_custom(&original) {
+            // Custom items stay under the mean they were written with, iTunes' own for new ones
+            let mut existing: Vec<&str> = Vec::new();
+            for (item, value) in &current.items {
+                let name = item.strip_prefix("----:").and_then(|rest| rest.split_once(':')).map(|(_, name)| name);
+                let matches = name.is_some_and(|name| name.eq_ignore_ascii_case(key)) && matches!(value, IlstValue::Text(_));
+                if matches && !existing.contains(&item.as_str()) {
+                    existing.push(item);
+                }
+            }
+            let written = existing.first().map_or_else(|| format!("{}{}", ILST_FREEFORM, key), |key| key.to_string());
+            for key in existing.iter().skip(1) {
+                replace(key, Vec::new());
+            }
+            replace(&written, values.into_iter().map(|v| IlstValue::Text(v.to_string())).collect());
+        }
+
+        (items, remove)
+    }
+
+    /// The single valued `ilst` items `Tags` models, in the order they're written
+    fn ilst_fields(&self) -> Vec<(String, Option<IlstValue>)> {
+        let text = |value: &Option<String>| value.clone().map(IlstValue::Text);
+        let pair = |number: Option<u32>, total: Option<u32>| {
+            let clamp = |n: Option<u32>| n.map_or(0, |n| n.min(u16::MAX as u32) as u16);
+            (number.is_some() || total.is_some()).then(|| IlstValue::Pair(clamp(number), clamp(total)))
+        };
+        let mb = &self.musicbrainz;
+
+        let mut fields = vec![
+            ("©nam", text(&self.title)),
+            ("aART", text(&self.album_artist)),
+            ("©alb", text(&self.album)),
+            ("©wrt", text(&self.composer)),
+            ("trkn", pair(self.track_number, self.track_total)),
+            ("disk", pair(self.disc_number, self.disc_total)),
+            ("©day", text(&self.date)),
+            ("©gen", text(&self.genre)),
+            ("cpil", self.compilation.then_some(IlstValue::Integer(1))),
+            ("sonm", text(&self.sort_title)),
+            ("soar", text(&self.sort_artist)),
+            ("soaa", text(&self.sort_album_artist)),
+            ("soal", text(&self.sort_album)),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect::<Vec<_>>();
+
+        let freeform = [
+            ("SUBTITLE", &self.subtitle),
+            ("MusicBrainz Track Id", &mb.recording),
+            ("MusicBrainz Release Track Id", &mb.release_track),
+            ("MusicBrainz Album Id", &mb.release),
+            ("MusicBrainz Release Group Id", &mb.release_group),
+            ("MusicBrainz Artist Id", &mb.artist),
+            ("MusicBrainz Album Artist Id", &mb.album_artist),
+        ];
+        fields.extend(freeform.into_iter().map(|(name, value)| (format!("{}{}", ILST_FREEFORM, name), text(value))));
+        fields
+    }
+}
+
+/// Writes the common `Tags` model back into a file's own tags
+pub trait MetadataWriter {
+    /// Write the fields of `tags` that differ from the file's own, leaving the rest of its tags and embedded art alone
+    fn write_tags(&self, path: &Path, tags: &Tags) -> Result<()>;
+}
+
+impl MetadataWriter for SupportedFiletype {
+    fn write_tags(&self, path: &Path, tags: &Tags) -> Result<()> {
+        match self {
+            SupportedFiletype::Flac => {
+                let mut comments = flac::read_comments(path)?.unwrap_or_else(|| VorbisComments {
+                    vendor: concat!("arcanio ", env!("CARGO_PKG_VERSION")).to_string(),
+                    comments: Vec::new(),
+                });
+                let original = Tags::from_vorbis(&comments);
+                tags.merge_into_vorbis(&original, &mut comments);
+                flac::write_comments(path, &comments)
+            }
+            SupportedFiletype::M4a | SupportedFiletype::M4b | SupportedFiletype::Mp4 => {
+                let (items, remove) = tags.to_ilst(&mp4::read_tags(path)?.unwrap_or_default());
+                let remove: Vec<&str> = remove.iter().map(String::as_str).collect();
+                mp4::write_tags(path, &items, &remove)
+            }
+            _ => Err(Error::UnsupportedFiletype),
+        }
+    }
+}
+
+/// Reads the tags of a file into the common `Tags` model
+pub trait MetadataReader {
+    /// Read the tags of the file at `path`, files without tags give empty `Tags`
+    fn read_tags(&self, path: &Path) -> Result<Tags>;
+}
+
+impl MetadataReader for SupportedFiletype {
+    fn read_tags(&self, path: &Path) -> Result<Tags> {
+        let tags = match self {
+            SupportedFiletype::Flac => flac::read_comments(path)?.map(|c| Tags::from_vorbis(&c)),
+            SupportedFiletype::Ogg | SupportedFiletype::Opus | SupportedFiletype::OggFlac => {
+                ogg::read_comments(path)?.map(|(_, c)| Tags::from_vorbis(&c))
+            }
+            SupportedFiletype::M4a | SupportedFiletype::M4b | SupportedFiletype::Mp4 => {
+                mp4::read_tags(path)?.map(|t| Tags::from_ilst(&t))
+            }
+            // An ID3v2 tag we can't parse, such as ID3v2.2, shouldn't hide a usable ID3v1 tag
+            SupportedFiletype::Mp3 => match id3::read_id3v2(path) {
+                Ok(Some(tag)) => Some(Tags::from_id3v2(&tag)),
+                Ok(None) | Err(Error::InvalidMetadata(_)) => id3::read_id3v1(path)?.map(|tag| Tags::from_id3v1(&tag)),
+                Err(e) => return Err(e),
+            },
+            SupportedFiletype::Wav | SupportedFiletype::Aiff => riff::read_chunks(path)?.map(|m| Tags::from_chunks(&m)),
+            SupportedFiletype::Epub => Some(Tags::from_epub(&epub::read_package(path)?)),
+            SupportedFiletype::Mkv | SupportedFiletype::Webm => {
+                matroska::read_video_info(path)?.map(|info| Tags { title: info.title, ..Default::default() })
+            }
+        };
+        Ok(tags.unwrap_or_default())
+    }
+}
+
+/// Where tags are read from
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataBackend {
+    /// The built in parsers, picked by sniffing the file
+    Native,
+    /// An external ffprobe
+    Ffprobe(Ffprobe),
+}
+
+impl MetadataReader for MetadataBackend {
+    fn read_tags(&self, path: &Path) -> Result<Tags> {
+        match self {
+            MetadataBackend::Native => detect_filetype(path)?.read_tags(path),
+            MetadataBackend::Ffprobe(ffprobe) => ffprobe.read_tags(path),
+        }
+    }
+}
+
+/// Backends tried in order, moving on when one fails or finds no tags
+impl MetadataReader for [MetadataBackend] {
+    fn read_tags(&self, path: &Path) -> Result<Tags> {
+        // Only fail when no backend could read the file at all
+        let mut result = None;
+        for backend in self {
+            match backend.read_tags(path) {
+                Ok(tags) if tags != Tags::default() => return Ok(tags),
+                Ok(tags) => result = Some(Ok(tags)),
+                Err(e) => {
+                    result.get_or_insert(Err(e));
+                }
+            }
+        }
+        result.unwrap_or_else(|| Ok(Tags::default()))
+    }
+}
+
+/// Trim a value, treating blank values as missing
+fn text(value: Option<&str>) -> Option<String> {
+    value.map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+/// Read a boolean flag, written as `1` by most taggers and `true` by a few
+fn flag(value: Option<&str>) -> bool {
+    value.map(str::trim).is_some_and(|v| v.parse::<u32>().is_ok_and(|n| n > 0) || v.eq_ignore_ascii_case("true"))
+}
+
+/// Parse a `number/total` value like `3/12`, either half may be missing
+pub fn number_pair(value: Option<&str>) -> (Option<u32>, Option<u32>) {
+    let Some(value) = value else {
+        return (None, None);
+    };
+    let mut parts = value.splitn(2, '/').map(|p| p.trim().parse::<u32>().ok().filter(|n| *n > 0));
+    (parts.next().flatten(), parts.next().flatten())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::vorbis::encode;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_number_pair() {
+        assert_eq!(number_pair(Some("3/12")), (Some(3), Some(12)));
+        assert_eq!(number_pair(Some(" 7 ")), (Some(7), None));
+        assert_eq!(number_pair(Some("/12")), (None, Some(12)));
+        assert_eq!(number_pair(None), (None, None));
+    }
+
+    #[test]
+    fn test_from_vorbis() {
+        let comments = VorbisComments::parse(&encode("libFLAC", &[
+            ("TITLE", "Money"),
+            ("ARTIST", "Pink Floyd"),
+            ("ARTIST", "Dick Parry"),
+            ("ALBUM ARTIST", "Pink Floyd"),
+            ("TRACKNUMBER", "6"),
+            ("TRACKTOTAL", "10"),
+            ("DATE", "1973-03-01"),
+            ("MUSICBRAINZ_ALBUMID", "f5093c06"),
+            ("COMPILATION", "0"),
+            ("REPLAYGAIN_TRACK_GAIN", "-6.5 dB"),
+        ])).unwrap();
+
+        let tags = Tags::from_vorbis(&comments);
+        assert_eq!(tags.artists, vec!["Pink Floyd", "Dick Parry"]);
+        assert_eq!(tags.primary_artist(), Some("Pink Floyd"));
+        assert_eq!((tags.track_number, tags.track_total), (Some(6), Some(10)));
+        assert_eq!(tags.year(), Some(1973));
+        assert_eq!(tags.musicbrainz.release.as_deref(), Some("f5093c06"));
+        assert!(!tags.compilation);
+        assert_eq!(tags.custom, vec![("REPLAYGAIN_TRACK_GAIN".to_string(), "-6.5 dB".to_string())]);
+    }
+
+    #[test]
+    fn test_read_mp3_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.mp3");
+
+        let frame = |id: &[u8; 4], body: &[u8]| {
+            let mut out = id.to_vec();
+            out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            out.extend_from_slice(&[0, 0]);
+            out.extend_from_slice(body);
+            out
+        };
+        let frames = [
+            frame(b"TIT2", b"\x03Time"),
+            frame(b"TPE1", b"\x03Pink Floyd"),
+            frame(b"TRCK", b"\x034/10"),
+            frame(b"TYER", b"\x031973"),
+            frame(b"TCMP", b"\x031"),
+            frame(b"TIT3", b"\x03Live"),
+            frame(b"TCOM", b"\x03Roger Waters"),
+            frame(b"TXXX", b"\x03MusicBrainz Album Id\x00f5093c06"),
+            frame(b"TXXX", b"\x03MOOD\x00Dark"),
+        ]
+        .concat();
+        let mut bytes = b"ID3\x03\x00\x00\x00\x00".to_vec();
+        bytes.extend_from_slice(&[(frames.len() >> 7) as u8 & 0x7F, frames.len() as u8 & 0x7F]);
+        bytes.extend_from_slice(&frames);
+        bytes.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        fs::write(&path, &bytes).unwrap();
+
+        let tags = SupportedFiletype::Mp3.read_tags(&path).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Time"));
+        assert_eq!(tags.primary_artist(), Some("Pink Floyd"));
+        assert_eq!((tags.track_number, tags.track_total), (Some(4), Some(10)));
+        assert_eq!(tags.date.as_deref(), Some("1973"));
+        assert_eq!(tags.musicbrainz.release.as_deref(), Some("f5093c06"));
+        assert!(tags.compilation);
+        assert_eq!(tags.subtitle.as_deref(), Some("Live"));
+        assert_eq!(tags.composer.as_deref(), Some("Roger Waters"));
+        assert_eq!(tags.custom("mood"), Some("Dark"));
+    }
+
+    #[test]
+    fn test_untagged_file_has_empty_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0))])).unwrap();
+
+        assert_eq!(SupportedFiletype::Flac.read_tags(&path).unwrap(), Tags::default());
+    }
+
+    #[test]
+    fn test_write_flac_tags_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        let comments = encode("libFLAC", &[
+            ("TITLE", "Money"),
+            ("ALBUM ARTIST", "Floyd"),
+            ("REPLAYGAIN_TRACK_GAIN", "-6.5 dB"),
+            ("CUESHEET", "TRACK 01 AUDIO"),
+        ]);
+        fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+
+        let mut tags = SupportedFiletype::Flac.read_tags(&path).unwrap();
+        tags.album_artist = Some("Pink Floyd".to_string());
+        tags.track_number = Some(6);
+        tags.compilation = true;
+        SupportedFiletype::Flac.write_tags(&path, &tags).unwrap();
+
+        assert_eq!(SupportedFiletype::Flac.read_tags(&path).unwrap(), tags);
+        let comments = flac::read_comments(&path).unwrap().unwrap();
+        assert_eq!(comments.get("ALBUM ARTIST"), None);
+        assert_eq!(comments.get("CUESHEET"), Some("TRACK 01 AUDIO"));
+        assert_eq!(comments.get("COMPILATION"), Some("1"));
+    }
+
+    #[test]
+    fn test_write_keeps_unedited_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        let comments = encode("libFLAC", &[
+            ("TITLE", "Money"),
+            ("GENRE", "Rock"),
+            ("GENRE", "Progressive"),
+            ("TRACKNUMBER", "06"),
+            ("PERFORMER", "David Gilmour"),
+            ("PERFORMER", "Roger Waters"),
+            ("MOOD", "Dark"),
+        ]);
+        fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+
+        let mut tags = SupportedFiletype::Flac.read_tags(&path).unwrap();
+        tags.title = Some("Time".to_string());
+        tags.custom.retain(|(key, _)| key != "MOOD");
+        tags.custom.push(("PERFORMER".to_string(), "Rick Wright".to_string()));
+        SupportedFiletype::Flac.write_tags(&path, &tags).unwrap();
+
+        let comments = flac::read_comments(&path).unwrap().unwrap();
+        assert_eq!(comments.get("TITLE"), Some("Time"));
+        assert_eq!(comments.get_all("GENRE"), vec!["Rock", "Progressive"]);
+        assert_eq!(comments.get("TRACKNUMBER"), Some("06"));
+        assert_eq!(comments.get_all("PERFORMER"), vec!["David Gilmour", "Roger Waters", "Rick Wright"]);
+        assert_eq!(comments.get("MOOD"), None);
+    }
+
+    #[test]
+    fn test_write_mp4_keeps_freeform_mean() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.m4a");
+
+        let freeform = |mean: &str, name: &str, value: &str| {
+            let full_box = |kind: &[u8; 4], value: &str| mp4::mp4_box(kind, &[&[0; 4], value.as_bytes()].concat());
+            let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+            data.extend_from_slice(value.as_bytes());
+            mp4::mp4_box(b"----", &[full_box(b"mean", mean), full_box(b"name", name), mp4::mp4_box(b"data", &data)].concat())
+        };
+        let mut moov = mp4::track(b"soun", &mp4::audio_entry(b"alac", &[]));
+        moov.extend_from_slice(&mp4::udta(&[
+            mp4::ilst_item(b"\xA9nam", 1, b"Money"),
+            freeform("com.example", "LABEL", "Harvest"),
+            freeform("org.example", "MOOD", "Dark"),
+        ]));
+        let mut bytes = mp4::mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+        bytes.extend_from_slice(&mp4::mp4_box(b"moov", &moov));
+        fs::write(&path, &bytes).unwrap();
+
+        let mut tags = SupportedFiletype::M4a.read_tags(&path).unwrap();
+        tags.custom.retain(|(key, _)| key != "LABEL");
+        tags.custom.push(("LABEL".to_string(), "EMI".to_string()));
+        SupportedFiletype::M4a.write_tags(&path, &tags).unwrap();
+
+        let written = mp4::read_tags(&path).unwrap().unwrap();
+        assert_eq!(written.text("----:com.example:LABEL"), Some("EMI"));
+        assert_eq!(written.text("----:com.apple.iTunes:LABEL"), None);
+        assert_eq!(written.text("----:org.example:MOOD"), Some("Dark"));
+        assert_eq!(written.text("©nam"), Some("Money"));
+    }
+
+    #[test]
+    fn test_write_unsupported_filetype() {
+        let result = SupportedFiletype::Mp3.write_tags(Path::new("song.mp3"), &Tags::default());
+        assert!(matches!(result, Err(Error::UnsupportedFiletype)));
+    }
+
+    #[test]
+    fn test_mp3_with_unsupported_id3v2() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.mp3");
+
+        // An empty ID3v2.2 tag, then an MPEG frame and an ID3v1 tag
+        let mut bytes = b"ID3\x02\x00\x00\x00\x00\x00\x00".to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        let mut v1 = vec![0u8; 128];
+        v1[0..3].copy_from_slice(b"TAG");
+        v1[3..8].copy_from_slice(b"Money");
+        bytes.extend_from_slice(&v1);
+        fs::write(&path, &bytes).unwrap();
+
+        let tags = SupportedFiletype::Mp3.read_tags(&path).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Money"));
+    }
+
+    #[test]
+    fn test_backend_fallback() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("song.flac");
+        let comments = encode("libFLAC", &[("TITLE", "Money")]);
+        fs::write(&path, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0)), (flac::VORBIS_COMMENT, comments)])).unwrap();
+
+        let missing = MetadataBackend::Ffprobe(Ffprobe::new(temp_dir.path().join("no-such-ffprobe")));
+        let tags = [missing.clone(), MetadataBackend::Native].read_tags(&path).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Money"));
+        assert!([missing.clone()].read_tags(&path).is_err());
+
+        // A backend that finds nothing still beats one that fails
+        let empty = temp_dir.path().join("empty.flac");
+        fs::write(&empty, flac::flac(&[(flac::STREAMINFO, flac::streaminfo(44_100, 0))])).unwrap();
+        assert_eq!([MetadataBackend::Native, missing].read_tags(&empty).unwrap(), Tags::default());
+    }
+}
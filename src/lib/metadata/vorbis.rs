@@ -0,0 +1,139 @@
+//! Vorbis comment parsing
+//!
+//! Vorbis comments are the tag format shared by Ogg Vorbis, Opus, FLAC and
+//! FLAC-in-Ogg. They are a vendor string followed by a list of `KEY=value`
+//! entries, where keys are case insensitive and may repeat.
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VorbisComments {
+    pub vendor: String,
+    /// Entries in file order, keys are kept as written
+    pub comments: Vec<(String, String)>,
+}
+
+impl VorbisComments {
+    /// Parse a comment block without any codec specific packet header
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = LeReader { bytes, offset: 0 };
+
+        let vendor_len = reader.u32()? as usize;
+        let vendor = String::from_utf8_lossy(reader.take(vendor_len)?).to_string();
+
+        let count = reader.u32()?;
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            let len = reader.u32()? as usize;
+            let entry = String::from_utf8_lossy(reader.take(len)?).to_string();
+            // Entries without a separator aren't valid, skip them rather than fail the whole block
+            if let Some((key, value)) = entry.split_once('=') {
+                comments.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        Ok(Self { vendor, comments })
+    }
+
+    /// The first value for `key`, ignoring case
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every value for `key`, ignoring case
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.comments
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// Replace every value for `key` with `values`, keeping the position of the first one
+    pub fn set(&mut self, key: &str, values: &[&str]) {
+        let position = self.comments.iter().position(|(k, _)| k.eq_ignore_ascii_case(key));
+        self.comments.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+        let at = position.unwrap_or(self.comments.len());
+        let entries = values.iter().map(|v| (key.to_string(), v.to_string()));
+        self.comments.splice(at..at, entries);
+    }
+
+    /// Encode the comment block without any codec specific packet header or framing bit
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = (self.vendor.len() as u32).to_le_bytes().to_vec();
+        out.extend_from_slice(self.vendor.as_bytes());
+        out.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for (key, value) in &self.comments {
+            let entry = format!("{}={}", key, value);
+            out.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            out.extend_from_slice(entry.as_bytes());
+        }
+        out
+    }
+}
+
+/// Minimal little endian cursor, Vorbis comments are the only LE format we read
+struct LeReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> LeReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.offset.checked_add(len).filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| Error::InvalidMetadata("truncated vorbis comment".to_string()))?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn encode(vendor: &str, comments: &[(&str, &str)]) -> Vec<u8> {
+    VorbisComments {
+        vendor: vendor.to_string(),
+        comments: comments.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+    }
+    .to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_comments() {
+        let bytes = encode("libopus 1.3", &[("TITLE", "Song"), ("artist", "A"), ("ARTIST", "B")]);
+        let comments = VorbisComments::parse(&bytes).unwrap();
+
+        assert_eq!(comments.vendor, "libopus 1.3");
+        assert_eq!(comments.get("title"), Some("Song"));
+        assert_eq!(comments.get_all("Artist"), vec!["A", "B"]);
+    }
+
+    #[test]
+    fn test_set_round_trip() {
+        let mut comments = VorbisComments::parse(&encode("vendor", &[("TITLE", "Song"), ("artist", "A"), ("ARTIST", "B"), ("DATE", "1999")])).unwrap();
+        comments.set("ARTIST", &["C"]);
+        comments.set("GENRE", &["Rock"]);
+
+        let comments = VorbisComments::parse(&comments.to_bytes()).unwrap();
+        let keys: Vec<&str> = comments.comments.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["TITLE", "ARTIST", "DATE", "GENRE"]);
+        assert_eq!(comments.get_all("artist"), vec!["C"]);
+    }
+
+    #[test]
+    fn test_truncated_comments() {
+        let bytes = encode("vendor", &[("TITLE", "Song")]);
+        assert!(VorbisComments::parse(&bytes[..bytes.len() - 2]).is_err());
+    }
+}
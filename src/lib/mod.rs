@@ -2,7 +2,14 @@ mod error;
 pub use error::Error;
 pub use error::Result;
 
+pub mod art;
+pub mod audiobook;
 pub mod music;
 pub mod files;
-
-
+pub mod metadata;
+pub mod pattern;
+pub mod sanitize;
+pub mod sidecar;
+pub mod sniff;
+pub mod template;
+pub mod video;
@@ -0,0 +1,329 @@
+//! Music specific handling
+//!
+//! Albums are often ripped to one image file with a CUE sheet, either next
+//! to it or embedded in a FLAC. Those images are split into per-track files
+//! with ffmpeg so each track can be normalized on its own.
+//!
+//! Compilations are filed under one folder rather than under each track's
+//! artist. Whether an album is one is decided from the tags of all of its
+//! tracks, so every track of it gets the same answer.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::files::{SupportedFiletype, SupportedMediaType};
+use crate::metadata::cue::{self, CueSheet};
+use crate::metadata::flac;
+use crate::metadata::tags::Tags;
+use crate::{Error, Result};
+
+/// Album artists that stand for a release by many artists
+pub const DEFAULT_VARIOUS_ARTISTS: &[&str] = &["Various Artists", "Various", "VA", "V.A."];
+
+/// Where compilations are filed unless configured otherwise
+pub const DEFAULT_COMPILATION_FOLDER: &str = "Various Artists";
+
+/// Folder names rippers give the discs of a multi-disc album, followed by the disc number
+const DISC_FOLDER_PREFIXES: &[&str] = &["disc", "disk", "cd"];
+
+/// How compilations are recognised and where they are filed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compilations {
+    /// Album artists that mark a compilation, compared ignoring case
+    pub various_artists: Vec<String>,
+    /// The folder compilations are filed under in place of an artist
+    pub folder: String,
+}
+
+impl Default for Compilations {
+    fn default() -> Self {
+        Self {
+            various_artists: DEFAULT_VARIOUS_ARTISTS.iter().map(|name| name.to_string()).collect(),
+            folder: DEFAULT_COMPILATION_FOLDER.to_string(),
+        }
+    }
+}
+
+impl Compilations {
+    /// Check whether an album is a compilation, given the tags of its tracks
+    ///
+    /// It is one when any track has the compilation flag or a various artists
+    /// album artist, or when its tracks don't agree on an album artist,
+    /// falling back to the track artist for tracks without one.
+    pub fn is_compilation(&self, album: &[Tags]) -> bool {
+        let various = |artist: &str| self.various_artists.iter().any(|va| va.to_lowercase() == artist.to_lowercase());
+        let mut artists = album.iter().filter_map(Tags::primary_artist);
+        let first = artists.next();
+
+        album.iter().any(|tags| tags.compilation || tags.primary_artist().is_some_and(various))
+            || artists.any(|artist| Some(artist) != first)
+    }
+
+    /// The artist an album is filed under: the compilation folder, or the
+    /// artist all of its tracks agree on
+    pub fn release_artist(&self, album: &[Tags]) -> Option<String> {
+        if self.is_compilation(album) {
+            return Some(self.folder.clone());
+        }
+        album.iter().find_map(Tags::primary_artist).map(str::to_string)
+    }
+}
+
+/// The music files that may be on the same album as `path`, `path` included
+///
+/// That is every music file in its directory, plus those in the other disc
+/// folders next to it when it sits in one, like `CD2` or `Disc 2`.
+pub fn album_candidates(path: &Path) -> Result<Vec<PathBuf>> {
+    let path = path.canonicalize()?;
+    let dir = path.parent().ok_or(Error::InvalidFilePath)?;
+
+    let mut dirs = vec![dir.to_path_buf()];
+    if let Some(root) = dir.parent().filter(|_| is_disc_folder(dir)) {
+        for entry in fs::read_dir(root)? {
+            let sibling = entry?.path();
+            if sibling != dir && sibling.is_dir() && is_disc_folder(&sibling) {
+                dirs.push(sibling);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    for dir in dirs {
+        for entry in fs::read_dir(dir)? {
+            let file = entry?.path();
+            let music = SupportedFiletype::from_path(&file).is_some_and(|ft| SupportedMediaType::from(ft) == SupportedMediaType::Music);
+            if music && file.is_file() {
+                files.push(file);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Check whether a directory is named like one disc of an album, e.g. `CD1`, `Disc 2` or `disk_03`
+fn is_disc_folder(dir: &Path) -> bool {
+    let Some(name) = dir.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let name = name.to_lowercase();
+    DISC_FOLDER_PREFIXES.iter().any(|prefix| {
+        let number = name.strip_prefix(prefix).unwrap_or_default().trim_start_matches([' ', '_', '-', '.']);
+        !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+/// Find the cue sheet describing an audio file
+///
+/// A `.cue` file sharing the audio's stem wins over one embedded in a FLAC.
+pub fn find_cue_sheet(path: &Path, filetype: &SupportedFiletype) -> Result<Option<CueSheet>> {
+    let sidecar = path.with_extension("cue");
+    if sidecar.is_file() {
+        return cue::read_cue_sheet(&sidecar).map(Some);
+    }
+
+    match filetype {
+        SupportedFiletype::Flac => flac::read_cue_sheet(path),
+        _ => Ok(None),
+    }
+}
+
+/// Locate the audio file a cue sheet refers to
+///
+/// Rippers write the `FILE` name at rip time, so an image converted to FLAC
+/// afterwards often still points at a `.wav`. In that case any supported file
+/// with the same stem is used instead.
+pub fn cue_audio_file(cue_path: &Path, sheet: &CueSheet) -> Result<PathBuf> {
+    let files = sheet.files();
+    let [file] = files.as_slice() else {
+        return Err(Error::InvalidMetadata("cue sheet must reference exactly one file".to_string()));
+    };
+
+    let dir = cue_path.parent().unwrap_or(Path::new(""));
+    let audio = dir.join(file);
+    if audio.is_file() {
+        return Ok(audio);
+    }
+
+    let stem = audio.file_stem().ok_or(Error::InvalidFilePath)?;
+    let parent = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    for entry in fs::read_dir(parent)? {
+        let candidate = entry?.path();
+        if candidate.file_stem() == Some(stem) && SupportedFiletype::from_path(&candidate).is_some() {
+            return Ok(candidate);
+        }
+    }
+
+    Err(Error::FileNotFound)
+}
+
+/// Build the commands that split an image into one file per track with `ffmpeg`
+///
+/// Each track runs from its `INDEX 01` to the next track's, so pregaps stay
+/// at the end of the previous track. Tracks are tagged from the sheet and
+/// named `NN_Title` with the image's extension.
+pub fn split_commands(
+    ffmpeg: &Path,
+    audio: &Path,
+    filetype: &SupportedFiletype,
+    sheet: &CueSheet,
+    output_dir: &Path,
+) -> Result<Vec<(PathBuf, tokio::process::Command)>> {
+    let input = audio.to_str().ok_or(Error::InvalidFilePath)?;
+    let extension = filetype.clone().get_extension();
+    let total = sheet.tracks.len();
+
+    let mut commands = Vec::new();
+    for (i, track) in sheet.tracks.iter().enumerate() {
+        let start = track
+            .start()
+            .ok_or_else(|| Error::InvalidMetadata(format!("cue track {} has no index", track.number)))?;
+        let end = sheet.tracks.get(i + 1).and_then(|next| next.start());
+
+        let title = track.title.clone().unwrap_or_else(|| format!("Track {:02}", track.number));
+        let output = output_dir.join(format!("{:02}_{}{}", track.number, title.replace(['/', '\\'], "-"), extension));
+
+        let mut args: Vec<String> = vec![
+            "-v".to_string(),
+            "error".to_string(),
+            "-i".to_string(),
+            input.to_string(),
+            "-ss".to_string(),
+            format!("{:.6}", start.as_secs_f64()),
+        ];
+        if let Some(end) = end {
+            args.push("-to".to_string());
+            args.push(format!("{:.6}", end.as_secs_f64()));
+        }
+        args.extend(["-map".to_string(), "0:a".to_string(), "-map_metadata".to_string(), "-1".to_string()]);
+
+        // FLAC is re-encoded so every track starts on a frame boundary, everything else is copied
+        args.push("-c:a".to_string());
+        args.push(match filetype {
+            SupportedFiletype::Flac => "flac".to_string(),
+            _ => "copy".to_string(),
+        });
+
+        let performer = track.performer.as_ref().or(sheet.performer.as_ref());
+        let tags = [
+            ("title", Some(title.clone())),
+            ("artist", performer.cloned()),
+            ("album_artist", sheet.performer.clone()),
+            ("album", sheet.title.clone()),
+            ("track", Some(format!("{}/{}", track.number, total))),
+            ("date", sheet.rem("DATE").map(str::to_string)),
+            ("genre", sheet.rem("GENRE").map(str::to_string)),
+        ];
+        for (key, value) in tags {
+            if let Some(value) = value {
+                args.push("-metadata".to_string());
+                args.push(format!("{}={}", key, value));
+            }
+        }
+
+        args.push(output.to_str().ok_or(Error::InvalidFilePath)?.to_string());
+
+        let mut cmd = tokio::process::Command::new(ffmpeg);
+        cmd.args(&args);
+        commands.push((output, cmd));
+    }
+
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const SHEET: &str = "PERFORMER \"Artist\"\nTITLE \"Album\"\nREM DATE 1999\nFILE \"image.wav\" WAVE\n\
+        TRACK 01 AUDIO\nTITLE \"Intro\"\nINDEX 01 00:00:00\n\
+        TRACK 02 AUDIO\nTITLE \"AC/DC Cover\"\nINDEX 00 03:58:00\nINDEX 01 04:00:00\n";
+
+    fn args(cmd: &tokio::process::Command) -> Vec<String> {
+        cmd.as_std().get_args().map(|a| a.to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn test_split_commands() {
+        let sheet = CueSheet::parse(SHEET).unwrap();
+        let ffmpeg = Path::new("/opt/ffmpeg/bin/ffmpeg");
+        let commands = split_commands(ffmpeg, Path::new("image.flac"), &SupportedFiletype::Flac, &sheet, Path::new("out")).unwrap();
+
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].0, Path::new("out/01_Intro.flac"));
+        assert_eq!(commands[1].0, Path::new("out/02_AC-DC Cover.flac"));
+        assert_eq!(commands[0].1.as_std().get_program(), ffmpeg);
+
+        let first = args(&commands[0].1);
+        assert!(first.windows(2).any(|w| w == ["-ss", "0.000000"]));
+        assert!(first.windows(2).any(|w| w == ["-to", "240.000000"]));
+        assert!(first.contains(&"track=1/2".to_string()));
+        assert!(first.contains(&"date=1999".to_string()));
+
+        // The last track runs to the end of the image
+        assert!(!args(&commands[1].1).contains(&"-to".to_string()));
+    }
+
+    #[test]
+    fn test_find_cue_and_converted_image() {
+        let temp_dir = TempDir::new().unwrap();
+        let audio = temp_dir.path().join("image.flac");
+        let cue_path = temp_dir.path().join("image.cue");
+        fs::write(&audio, b"fLaC\0\0\0\x22").unwrap();
+        fs::write(&cue_path, SHEET).unwrap();
+
+        let sheet = find_cue_sheet(&audio, &SupportedFiletype::Flac).unwrap().unwrap();
+        assert_eq!(sheet.tracks.len(), 2);
+        // The sheet names image.wav, but only image.flac exists
+        assert_eq!(cue_audio_file(&cue_path, &sheet).unwrap(), audio);
+    }
+
+    #[test]
+    fn test_compilations() {
+        let track = |album_artist: Option<&str>, artist: &str| Tags {
+            album_artist: album_artist.map(str::to_string),
+            artists: vec![artist.to_string()],
+            album: Some("Album".to_string()),
+            ..Default::default()
+        };
+        let compilations = Compilations::default();
+
+        let album = [track(None, "Pink Floyd"), track(Some("Pink Floyd"), "Pink Floyd"), Tags::default()];
+        assert!(!compilations.is_compilation(&album));
+        // Untagged tracks still go where the rest of the album goes
+        assert_eq!(compilations.release_artist(&album[2..]), None);
+        assert_eq!(compilations.release_artist(&album).as_deref(), Some("Pink Floyd"));
+
+        // Varying track artists without an album artist
+        assert!(compilations.is_compilation(&[track(None, "Blur"), track(None, "Oasis")]));
+        // A shared album artist keeps guest artists together
+        assert!(!compilations.is_compilation(&[track(Some("Blur"), "Blur"), track(Some("Blur"), "Blur feat. Phil Daniels")]));
+        assert!(compilations.is_compilation(&[track(Some("various artists"), "Blur")]));
+
+        let mut flagged = track(Some("Blur"), "Blur");
+        flagged.compilation = true;
+        let album = [track(Some("Blur"), "Blur"), flagged];
+        assert_eq!(compilations.release_artist(&album).as_deref(), Some("Various Artists"));
+    }
+
+    #[test]
+    fn test_album_candidates_span_disc_folders() {
+        let temp_dir = TempDir::new().unwrap();
+        let album = temp_dir.path().join("Album");
+        for dir in ["CD1", "CD 2", "Scans"] {
+            fs::create_dir_all(album.join(dir)).unwrap();
+        }
+        for file in ["CD1/01.flac", "CD1/cover.jpg", "CD 2/01.mp3", "Scans/01.flac", "CD1/book.m4b"] {
+            fs::write(album.join(file), "").unwrap();
+        }
+
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files.iter().map(|f| f.strip_prefix(album.canonicalize().unwrap()).unwrap().to_string_lossy().to_string()).collect()
+        };
+        assert_eq!(names(album_candidates(&album.join("CD1/01.flac")).unwrap()), vec!["CD 2/01.mp3", "CD1/01.flac"]);
+        assert_eq!(names(album_candidates(&album.join("Scans/01.flac")).unwrap()), vec!["Scans/01.flac"]);
+        assert!(is_disc_folder(Path::new("Disc_03")));
+        assert!(!is_disc_folder(Path::new("Discovery")));
+    }
+}
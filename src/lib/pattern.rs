@@ -0,0 +1,359 @@
+//! Tags inferred from the folders and name of a file
+//!
+//! Legacy rips often carry no tags, only a well structured path such as
+//! `Artist - Album/01 - Title.flac`. A `PathPattern` describes one such
+//! layout with `{field}` placeholders, one `/` separated segment per path
+//! component, matched against the end of the path with the last segment
+//! taking the file stem. Every match is scored so the most trustworthy of
+//! several patterns can be picked.
+
+use std::path::Path;
+
+use crate::metadata::tags::Tags;
+use crate::{Error, Result};
+
+/// The placeholder names a pattern may use, `_` matches anything and is dropped
+pub const FIELDS: &[&str] = &["artist", "album_artist", "album", "disc", "track", "year", "title", "_"];
+
+/// Most ways a single segment is allowed to split before we stop counting
+const MAX_SPLITS: usize = 16;
+
+/// Confidence kept by a segment made of a single placeholder, which any folder name matches
+const UNANCHORED: f32 = 0.8;
+
+/// Confidence kept by a value that still looks like it holds a separator
+const SUSPICIOUS_VALUE: f32 = 0.8;
+
+/// A tag field a placeholder captures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Artist,
+    AlbumArtist,
+    Album,
+    Disc,
+    Track,
+    Year,
+    Title,
+    /// `{_}`, matched but not kept
+    Ignore,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "artist" => Some(Self::Artist),
+            "album_artist" => Some(Self::AlbumArtist),
+            "album" => Some(Self::Album),
+            "disc" => Some(Self::Disc),
+            "track" => Some(Self::Track),
+            "year" => Some(Self::Year),
+            "title" => Some(Self::Title),
+            "_" => Some(Self::Ignore),
+            _ => None,
+        }
+    }
+
+    /// Check whether a value is plausible for the field
+    ///
+    /// Numbers must be all digits, so `{track} {title}` can't swallow the
+    /// start of a title, and years must look like a recording year.
+    fn accepts(self, value: &str) -> bool {
+        let digits = !value.is_empty() && value.chars().all(|c| c.is_ascii_digit());
+        match self {
+            Self::Disc | Self::Track => digits && value.len() <= 3 && value.parse::<u32>().is_ok_and(|n| n > 0),
+            Self::Year => digits && value.len() == 4 && value.parse::<u32>().is_ok_and(|y| (1900..2100).contains(&y)),
+            _ => !value.trim().is_empty(),
+        }
+    }
+
+    fn is_text(self) -> bool {
+        !matches!(self, Self::Disc | Self::Track | Self::Year | Self::Ignore)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+/// A parsed path pattern like `{artist}/{year} - {album}/{track}. {title}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPattern {
+    source: String,
+    segments: Vec<Vec<Token>>,
+}
+
+/// Tags read from a path, with how far they can be trusted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inference {
+    pub tags: Tags,
+    /// From 0 to 1, 1 being an unambiguous match of a well anchored pattern
+    pub confidence: f32,
+    /// The pattern that matched, as written
+    pub pattern: String,
+}
+
+impl PathPattern {
+    /// Parse a pattern, rejecting unknown placeholders and empty segments
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let error = |message: &str| Error::InvalidPattern(format!("{} in '{}'", message, pattern));
+
+        let mut segments = Vec::new();
+        for segment in pattern.split('/') {
+            let mut tokens = Vec::new();
+            let mut rest = segment;
+            while !rest.is_empty() {
+                match rest.find('{') {
+                    Some(0) => {
+                        let end = rest.find('}').ok_or_else(|| error("unclosed '{'"))?;
+                        let name = &rest[1..end];
+                        let field = Field::from_name(name).ok_or_else(|| {
+                            error(&format!("unknown field '{{{}}}', expected one of {}", name, FIELDS.join(", ")))
+                        })?;
+                        tokens.push(Token::Field(field));
+                        rest = &rest[end + 1..];
+                    }
+                    Some(start) => {
+                        tokens.push(Token::Literal(rest[..start].to_string()));
+                        rest = &rest[start..];
+                    }
+                    None => {
+                        tokens.push(Token::Literal(rest.to_string()));
+                        rest = "";
+                    }
+                }
+            }
+
+            if tokens.is_empty() {
+                return Err(error("empty path segment"));
+            }
+            if tokens.iter().any(|t| matches!(t, Token::Literal(l) if l.contains('}'))) {
+                return Err(error("unmatched '}'"));
+            }
+            segments.push(tokens);
+        }
+
+        if !segments.iter().flatten().any(|t| matches!(t, Token::Field(f) if *f != Field::Ignore)) {
+            return Err(error("no fields to capture"));
+        }
+        Ok(Self { source: pattern.to_string(), segments })
+    }
+
+    /// The pattern as written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Match the end of a path against the pattern
+    ///
+    /// Each placeholder takes the shortest value that lets the rest of its
+    /// segment match. Confidence drops when a segment could be split more
+    /// than one way, when a segment is a bare placeholder that any folder
+    /// name would match, when a text value still contains ` - `, and when
+    /// a field captured twice disagrees with itself.
+    pub fn infer(&self, path: &Path) -> Option<Inference> {
+        let stem = path.file_stem()?.to_str()?;
+        let mut components: Vec<&str> = path
+            .parent()
+            .map(|parent| parent.iter().filter_map(|c| c.to_str()).collect())
+            .unwrap_or_default();
+        components.push(stem);
+        let components = components.get(components.len().checked_sub(self.segments.len())?..)?;
+
+        let mut confidence = 1.0;
+        let mut captures = Vec::new();
+        for (tokens, component) in self.segments.iter().zip(components) {
+            let mut splits = Vec::new();
+            match_tokens(tokens, component, &mut Vec::new(), &mut splits);
+            let first = splits.first()?;
+
+            confidence /= splits.len() as f32;
+            if let [Token::Field(_)] = tokens.as_slice() {
+                confidence *= UNANCHORED;
+            }
+            captures.extend(first.iter().cloned());
+        }
+
+        let mut tags = Tags::default();
+        for (field, value) in &captures {
+            if field.is_text() && value.contains(" - ") {
+                confidence *= SUSPICIOUS_VALUE;
+            }
+
+            let value = value.trim().to_string();
+            let number = || value.parse::<u32>().ok();
+            let slot = match field {
+                Field::Artist => {
+                    if tags.artists.is_empty() {
+                        tags.artists.push(value.clone());
+                    } else if tags.artists[0] != value {
+                        confidence *= 0.5;
+                    }
+                    continue;
+                }
+                Field::AlbumArtist => set(&mut tags.album_artist, value.clone()),
+                Field::Album => set(&mut tags.album, value.clone()),
+                Field::Title => set(&mut tags.title, value.clone()),
+                Field::Year => set(&mut tags.date, value.clone()),
+                Field::Disc => set(&mut tags.disc_number, number()?),
+                Field::Track => set(&mut tags.track_number, number()?),
+                Field::Ignore => true,
+            };
+            if !slot {
+                confidence *= 0.5;
+            }
+        }
+
+        Some(Inference { tags, confidence, pattern: self.source.clone() })
+    }
+}
+
+/// Infer tags from the pattern that matches a path with the highest confidence
+///
+/// Ties go to the pattern filling more fields, then to the earlier pattern.
+pub fn infer_tags(path: &Path, patterns: &[PathPattern]) -> Option<Inference> {
+    let mut best: Option<(Inference, usize)> = None;
+    for inference in patterns.iter().filter_map(|pattern| pattern.infer(path)) {
+        let filled = filled_fields(&inference.tags);
+        let better = match &best {
+            Some((current, current_filled)) => {
+                inference.confidence > current.confidence
+                    || (inference.confidence == current.confidence && filled > *current_filled)
+            }
+            None => true,
+        };
+        if better {
+            best = Some((inference, filled));
+        }
+    }
+    best.map(|(inference, _)| inference)
+}
+
+/// Set a field that may already hold a value, returning whether they agree
+fn set<T: PartialEq>(slot: &mut Option<T>, value: T) -> bool {
+    match slot {
+        Some(existing) => *existing == value,
+        None => {
+            *slot = Some(value);
+            true
+        }
+    }
+}
+
+fn filled_fields(tags: &Tags) -> usize {
+    [
+        !tags.artists.is_empty(),
+        tags.album_artist.is_some(),
+        tags.album.is_some(),
+        tags.disc_number.is_some(),
+        tags.track_number.is_some(),
+        tags.date.is_some(),
+        tags.title.is_some(),
+    ]
+    .into_iter()
+    .filter(|filled| *filled)
+    .count()
+}
+
+/// Collect every way `text` splits into `tokens`, shortest values first
+fn match_tokens(tokens: &[Token], text: &str, captures: &mut Vec<(Field, String)>, splits: &mut Vec<Vec<(Field, String)>>) {
+    if splits.len() >= MAX_SPLITS {
+        return;
+    }
+
+    match tokens.split_first() {
+        None => {
+            if text.is_empty() {
+                splits.push(captures.clone());
+            }
+        }
+        Some((Token::Literal(literal), rest)) => {
+            if let Some(remaining) = text.strip_prefix(literal.as_str()) {
+                match_tokens(rest, remaining, captures, splits);
+            }
+        }
+        Some((Token::Field(field), rest)) => {
+            let ends = text.char_indices().skip(1).map(|(i, _)| i).chain([text.len()]);
+            for end in ends {
+                let value = &text[..end];
+                if !field.accepts(value) {
+                    continue;
+                }
+                captures.push((*field, value.to_string()));
+                match_tokens(rest, &text[end..], captures, splits);
+                captures.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(pattern: &str) -> PathPattern {
+        PathPattern::parse(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(PathPattern::parse("{artist}/{album}/{track} - {title}").is_ok());
+        assert!(PathPattern::parse("{artist}/{albm}").is_err());
+        assert!(PathPattern::parse("{artist}//{title}").is_err());
+        assert!(PathPattern::parse("{artist - {title}").is_err());
+        assert!(PathPattern::parse("{artist}} - {title}").is_err());
+        assert!(PathPattern::parse("{_} - {_}").is_err());
+    }
+
+    #[test]
+    fn test_infer_artist_album_track_title() {
+        let inference = pattern("{artist} - {album}/{track} - {title}")
+            .infer(Path::new("/rips/Pink Floyd - Animals/02 - Dogs.flac"))
+            .unwrap();
+        assert_eq!(inference.confidence, 1.0);
+        assert_eq!(inference.tags.artists, vec!["Pink Floyd"]);
+        assert_eq!(inference.tags.album.as_deref(), Some("Animals"));
+        assert_eq!(inference.tags.track_number, Some(2));
+        assert_eq!(inference.tags.title.as_deref(), Some("Dogs"));
+    }
+
+    #[test]
+    fn test_infer_year_and_numbers() {
+        let inference = pattern("{artist}/{year} - {album}/{track}. {title}")
+            .infer(Path::new("Pink Floyd/1977 - Animals/3. Pigs (Three Different Ones).mp3"))
+            .unwrap();
+        assert_eq!(inference.confidence, UNANCHORED);
+        assert_eq!(inference.tags.date.as_deref(), Some("1977"));
+        assert_eq!(inference.tags.track_number, Some(3));
+        assert_eq!(inference.tags.title.as_deref(), Some("Pigs (Three Different Ones)"));
+
+        // Neither a year nor a track number
+        assert_eq!(pattern("{year} - {album}").infer(Path::new("Live - Animals.mp3")), None);
+        assert_eq!(pattern("{track}. {title}").infer(Path::new("A. Title.mp3")), None);
+        // Not enough folders
+        assert_eq!(pattern("{artist}/{album}/{title}").infer(Path::new("Animals/Dogs.mp3")), None);
+    }
+
+    #[test]
+    fn test_ambiguous_split_lowers_confidence() {
+        let inference = pattern("{artist} - {album}/{track} {title}")
+            .infer(Path::new("Crosby, Stills - Nash - CSN/01 Suite.flac"))
+            .unwrap();
+        assert_eq!(inference.tags.artists, vec!["Crosby, Stills"]);
+        assert_eq!(inference.tags.album.as_deref(), Some("Nash - CSN"));
+        assert!(inference.confidence < 0.5);
+    }
+
+    #[test]
+    fn test_infer_tags_picks_most_confident() {
+        let patterns = [pattern("{artist}/{album}/{track} - {title}"), pattern("{artist} - {album}/{track} - {title}")];
+        let inference = infer_tags(Path::new("Music/Pink Floyd - Animals/02 - Dogs.flac"), &patterns).unwrap();
+        assert_eq!(inference.pattern, "{artist} - {album}/{track} - {title}");
+        assert_eq!(inference.tags.artists, vec!["Pink Floyd"]);
+
+        let inference = infer_tags(Path::new("Pink Floyd/Animals/02 - Dogs.flac"), &patterns).unwrap();
+        assert_eq!(inference.pattern, "{artist}/{album}/{track} - {title}");
+        assert_eq!(inference.confidence, UNANCHORED * UNANCHORED);
+    }
+}
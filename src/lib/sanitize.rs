@@ -0,0 +1,213 @@
+//! Filesystem safe names for generated paths
+//!
+//! Tag text ends up in directory and file names as is, so a title like
+//! `AC/DC` or `What?` has to be cleaned before it becomes a path component.
+//! A `Sanitizer` replaces separators and control characters, applies the
+//! rules of the target filesystem, normalizes Unicode and keeps each
+//! component under a byte limit.
+
+use unicode_normalization::UnicodeNormalization;
+
+/// What a character the target can't store is replaced with
+const REPLACEMENT: char = '_';
+
+/// Characters FAT, exFAT and NTFS reserve, on top of the path separators
+const WINDOWS_RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+/// Device names Windows reserves, with or without an extension
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest component most filesystems accept, in bytes
+pub const DEFAULT_MAX_COMPONENT_BYTES: usize = 255;
+
+/// The filesystems a path has to be valid on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    /// Only `/` and NUL are off limits, as on ext4 or APFS
+    Posix,
+    /// Also valid on FAT, exFAT and NTFS, so SD cards and Samba shares
+    WindowsSafe,
+    /// Windows safe and transliterated to ASCII, for players that can't show anything else
+    AsciiOnly,
+}
+
+impl Profile {
+    pub const NAMES: &'static [&'static str] = &["posix", "windows-safe", "ascii-only"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "posix" => Some(Self::Posix),
+            "windows-safe" => Some(Self::WindowsSafe),
+            "ascii-only" => Some(Self::AsciiOnly),
+            _ => None,
+        }
+    }
+}
+
+/// The Unicode normalization form names are written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnicodeForm {
+    /// Composed, what Linux and Windows tools expect
+    Nfc,
+    /// Decomposed, what older macOS filesystems store
+    Nfd,
+}
+
+impl UnicodeForm {
+    pub const NAMES: &'static [&'static str] = &["nfc", "nfd"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nfc" => Some(Self::Nfc),
+            "nfd" => Some(Self::Nfd),
+            _ => None,
+        }
+    }
+}
+
+/// Cleans text into path components for a target profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sanitizer {
+    pub profile: Profile,
+    pub unicode_form: UnicodeForm,
+    /// Longest a component may be in bytes, extension included
+    pub max_component_bytes: usize,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self {
+            profile: Profile::WindowsSafe,
+            unicode_form: UnicodeForm::Nfc,
+            max_component_bytes: DEFAULT_MAX_COMPONENT_BYTES,
+        }
+    }
+}
+
+impl Sanitizer {
+    /// Clean a directory name
+    pub fn component(&self, text: &str) -> String {
+        self.file_name(text, "")
+    }
+
+    /// Clean a file stem, keeping `extension` whole when the name has to be shortened
+    ///
+    /// The extension is expected to be safe already and include its dot.
+    pub fn file_name(&self, stem: &str, extension: &str) -> String {
+        let mut stem = self.clean(stem);
+        let limit = self.max_component_bytes.saturating_sub(extension.len()).max(1);
+        if stem.len() > limit {
+            truncate(&mut stem, limit);
+            stem = self.trim(&stem).to_string();
+        }
+        if stem.is_empty() || stem == "." || stem == ".." {
+            stem = REPLACEMENT.to_string();
+        }
+        if self.profile != Profile::Posix {
+            if let Some(base) = reserved_name_len(&stem) {
+                stem.insert(base, REPLACEMENT);
+            }
+        }
+        format!("{}{}", stem, extension)
+    }
+
+    fn clean(&self, text: &str) -> String {
+        let normalized: String = match self.profile {
+            // Transliterate from composed text, then it's plain ASCII in either form
+            Profile::AsciiOnly => deunicode::deunicode(&text.nfc().collect::<String>()),
+            _ => match self.unicode_form {
+                UnicodeForm::Nfc => text.nfc().collect(),
+                UnicodeForm::Nfd => text.nfd().collect(),
+            },
+        };
+
+        let cleaned: String = normalized
+            .chars()
+            .filter_map(|c| match c {
+                '\t' | '\n' | '\r' => Some(' '),
+                c if c.is_control() => None,
+                '/' => Some(REPLACEMENT),
+                c if self.profile != Profile::Posix && WINDOWS_RESERVED_CHARS.contains(&c) => Some(REPLACEMENT),
+                c => Some(c),
+            })
+            .collect();
+        self.trim(&cleaned).to_string()
+    }
+
+    /// Trim whitespace, and on Windows the trailing dots it silently drops
+    fn trim<'a>(&self, text: &'a str) -> &'a str {
+        match self.profile {
+            Profile::Posix => text.trim(),
+            _ => text.trim().trim_end_matches(['.', ' ']),
+        }
+    }
+}
+
+/// Shorten text to at most `max` bytes without splitting a character
+fn truncate(text: &mut String, max: usize) {
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+}
+
+/// The length of a Windows device name at the start of `name`, which stays reserved with any extension
+fn reserved_name_len(name: &str) -> Option<usize> {
+    let base = name.split('.').next().unwrap_or_default();
+    WINDOWS_RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(base.trim_end())).then_some(base.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitizer(profile: Profile) -> Sanitizer {
+        Sanitizer { profile, ..Default::default() }
+    }
+
+    #[test]
+    fn test_separators_and_control_characters() {
+        let posix = sanitizer(Profile::Posix);
+        assert_eq!(posix.component("AC/DC"), "AC_DC");
+        assert_eq!(posix.component("What?\tNow\u{7}"), "What? Now");
+        assert_eq!(posix.component(".."), "_");
+        assert_eq!(posix.component("  "), "_");
+    }
+
+    #[test]
+    fn test_windows_safe() {
+        let windows = sanitizer(Profile::WindowsSafe);
+        assert_eq!(windows.component("Star Wars: A New Hope"), "Star Wars_ A New Hope");
+        assert_eq!(windows.component("Who Are You?"), "Who Are You_");
+        assert_eq!(windows.component("Greatest Hits Vol. 2..."), "Greatest Hits Vol. 2");
+        assert_eq!(windows.file_name("con", ".flac"), "con_.flac");
+        assert_eq!(windows.file_name("Aux.live", ".flac"), "Aux_.live.flac");
+        assert_eq!(windows.file_name("Console", ".flac"), "Console.flac");
+    }
+
+    #[test]
+    fn test_unicode_forms() {
+        let composed = "Beyonc\u{e9}";
+        let decomposed = "Beyonce\u{301}";
+        assert_eq!(sanitizer(Profile::Posix).component(decomposed), composed);
+
+        let nfd = Sanitizer { unicode_form: UnicodeForm::Nfd, ..Default::default() };
+        assert_eq!(nfd.component(composed), decomposed);
+
+        assert_eq!(sanitizer(Profile::AsciiOnly).component(decomposed), "Beyonce");
+        assert_eq!(sanitizer(Profile::AsciiOnly).component("Sigur Rós: Ágætis byrjun"), "Sigur Ros_ Agaetis byrjun");
+    }
+
+    #[test]
+    fn test_byte_limit_keeps_extension() {
+        let short = Sanitizer { max_component_bytes: 12, ..Default::default() };
+        assert_eq!(short.file_name("Long Title Here", ".flac"), "Long Ti.flac");
+        // Never splits a character, and trims what the cut leaves behind
+        assert_eq!(short.file_name("ééé. éé", ".flac"), "ééé.flac");
+        assert_eq!(short.component("Long Title Here"), "Long Title H");
+    }
+}
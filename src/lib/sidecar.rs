@@ -0,0 +1,201 @@
+//! Sidecar files that travel with a media file
+//!
+//! Subtitles, lyrics, cue sheets and `.nfo` files share the stem of the
+//! media they belong to (`Movie.en.srt` next to `Movie.mkv`), while cover
+//! art is usually named by convention for the whole directory (`cover.jpg`),
+//! as are the `desc.txt` and `reader.txt` of an audiobook.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audiobook::{DESCRIPTION_FILE, NARRATOR_FILE};
+use crate::Result;
+
+/// Image names that hold the art of every media file in their directory
+const DIRECTORY_ART_NAMES: &[&str] = &["cover", "folder", "front", "album", "poster", "fanart"];
+
+/// The role of a sidecar file, decided by its extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SidecarKind {
+    Subtitle,
+    Art,
+    Lyrics,
+    Cue,
+    Nfo,
+    Text,
+}
+
+impl SidecarKind {
+    /// Look up the sidecar kind matching the extension of `path`, ignoring case
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "srt" | "ass" | "ssa" | "vtt" | "sub" | "idx" | "sup" => Some(Self::Subtitle),
+            "jpg" | "jpeg" | "png" | "webp" => Some(Self::Art),
+            "lrc" => Some(Self::Lyrics),
+            "cue" => Some(Self::Cue),
+            "nfo" => Some(Self::Nfo),
+            "txt" => Some(Self::Text),
+            _ => None,
+        }
+    }
+}
+
+/// A file associated with a media file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sidecar {
+    kind: SidecarKind,
+    path: PathBuf,
+    normalized_path: PathBuf,
+}
+
+impl Sidecar {
+    pub fn kind(&self) -> SidecarKind {
+        self.kind
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn normalized_path(&self) -> &Path {
+        &self.normalized_path
+    }
+}
+
+/// Find the sidecars of `media` and place them alongside its `normalized` path
+///
+/// Sidecars matched by stem keep whatever follows the stem, so `Movie.en.srt`
+/// for `Movie.mkv` becomes `<normalized stem>.en.srt`. Directory art and
+/// book text files keep their own name in the normalized directory.
+pub fn find_sidecars(media: &Path, normalized: &Path) -> Result<Vec<Sidecar>> {
+    let (Some(stem), Some(normalized_stem)) = (
+        media.file_stem().and_then(|s| s.to_str()),
+        normalized.file_stem().and_then(|s| s.to_str()),
+    ) else {
+        return Ok(Vec::new());
+    };
+    let parent = match media.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut sidecars = Vec::new();
+    for entry in fs::read_dir(parent)? {
+        let path = entry?.path();
+        let Some(kind) = SidecarKind::from_path(&path) else {
+            continue;
+        };
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !path.is_file() {
+            continue;
+        }
+
+        let normalized_name = if let Some(suffix) = name.strip_prefix(stem).filter(|s| s.starts_with('.')) {
+            format!("{}{}", normalized_stem, suffix)
+        } else if is_directory_file(kind, &path) {
+            name.to_string()
+        } else {
+            continue;
+        };
+
+        sidecars.push(Sidecar { kind, normalized_path: normalized.with_file_name(normalized_name), path });
+    }
+
+    sidecars.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(sidecars)
+}
+
+/// Find the image holding the art of a whole directory
+///
+/// Names are tried in `DIRECTORY_ART_NAMES` order, so `cover.jpg` wins over
+/// `folder.png`. Names are compared ignoring case.
+pub fn find_directory_art(dir: &Path) -> Result<Option<PathBuf>> {
+    let mut art = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if SidecarKind::from_path(&path) == Some(SidecarKind::Art) && is_directory_art(&path) && path.is_file() {
+            art.push(path);
+        }
+    }
+
+    let rank = |path: &PathBuf| {
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_lowercase();
+        DIRECTORY_ART_NAMES.iter().position(|name| *name == stem)
+    };
+    art.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
+    Ok(art.into_iter().next())
+}
+
+/// Check whether a sidecar is named as belonging to its whole directory rather than one file
+fn is_directory_file(kind: SidecarKind, path: &Path) -> bool {
+    match kind {
+        SidecarKind::Art => is_directory_art(path),
+        SidecarKind::Text => path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| [DESCRIPTION_FILE, NARRATOR_FILE].iter().any(|book| book.eq_ignore_ascii_case(name))),
+        _ => false,
+    }
+}
+
+/// Check whether an image is named as the art for its whole directory
+fn is_directory_art(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .is_some_and(|stem| DIRECTORY_ART_NAMES.contains(&stem.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_sidecars() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        for name in [
+            "Show.S01E02.mkv",
+            "Show.S01E02.en.srt",
+            "Show.S01E02.forced.de.ass",
+            "Show.S01E02.nfo",
+            "Show.S01E03.en.srt",
+            "Folder.JPG",
+            "screenshot.png",
+            "notes.txt",
+            "desc.txt",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let normalized = Path::new("Show/Season 01/S01E02_Title.mkv");
+        let sidecars = find_sidecars(&dir.join("Show.S01E02.mkv"), normalized).unwrap();
+        let found: Vec<(SidecarKind, &Path)> = sidecars.iter().map(|s| (s.kind(), s.normalized_path())).collect();
+
+        assert_eq!(found, vec![
+            (SidecarKind::Art, Path::new("Show/Season 01/Folder.JPG")),
+            (SidecarKind::Subtitle, Path::new("Show/Season 01/S01E02_Title.en.srt")),
+            (SidecarKind::Subtitle, Path::new("Show/Season 01/S01E02_Title.forced.de.ass")),
+            (SidecarKind::Nfo, Path::new("Show/Season 01/S01E02_Title.nfo")),
+            (SidecarKind::Text, Path::new("Show/Season 01/desc.txt")),
+        ]);
+    }
+
+    #[test]
+    fn test_find_directory_art() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        assert_eq!(find_directory_art(dir).unwrap(), None);
+
+        for name in ["01.flac", "back.jpg", "Folder.PNG", "front.jpg"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        assert_eq!(find_directory_art(dir).unwrap(), Some(dir.join("Folder.PNG")));
+
+        fs::write(dir.join("cover.webp"), b"").unwrap();
+        assert_eq!(find_directory_art(dir).unwrap(), Some(dir.join("cover.webp")));
+    }
+}
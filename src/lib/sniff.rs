@@ -0,0 +1,267 @@
+//! Content based filetype detection
+//!
+//! Reads the first bytes of a file and compares them against the magic
+//! numbers of each `SupportedFiletype`. This lets us classify files with a
+//! missing or misleading extension without spawning ffprobe.
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::files::SupportedFiletype;
+use crate::metadata::epub::is_epub;
+use crate::metadata::matroska::doc_type;
+use crate::metadata::ogg::{identify_stream, OggCodec};
+use crate::metadata::riff::ChunkForm;
+use crate::Result;
+
+/// Number of bytes read from the start of the file (after any ID3v2 tag)
+const HEADER_LEN: usize = 4096;
+
+/// MP4 brands that identify an audio only container
+const MP4_AUDIO_BRANDS: &[&[u8; 4]] = &[b"M4A ", b"M4P ", b"F4A "];
+
+/// MP4 brands that identify an audiobook
+const MP4_AUDIOBOOK_BRANDS: &[&[u8; 4]] = &[b"M4B ", b"F4B "];
+
+/// MP4 brands that identify a video container
+const MP4_VIDEO_BRANDS: &[&[u8; 4]] = &[b"M4V ", b"M4VH", b"M4VP", b"avc1", b"f4v "];
+
+/// Generic MP4 brands that may carry audio
+const MP4_GENERIC_BRANDS: &[&[u8; 4]] = &[
+    b"isom", b"iso2", b"iso3", b"iso4", b"iso5", b"iso6", b"mp41", b"mp42", b"dash",
+];
+
+/// The leading bytes of a file used for signature matching
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// Bytes following any leading ID3v2 tag
+    pub bytes: Vec<u8>,
+    /// Size in bytes of a leading ID3v2 tag, if one was present
+    pub id3v2_len: Option<u64>,
+}
+
+impl Header {
+    /// Read the header of the file at `path`, skipping over a leading ID3v2 tag
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut file = fs::File::open(path)?;
+
+        let mut id3 = [0u8; 10];
+        let id3v2_len = match read_up_to(&mut file, &mut id3)? {
+            10 => id3v2_tag_len(&id3),
+            _ => None,
+        };
+        file.seek(SeekFrom::Start(id3v2_len.unwrap_or(0)))?;
+
+        let mut bytes = vec![0u8; HEADER_LEN];
+        let read = read_up_to(&mut file, &mut bytes)?;
+        bytes.truncate(read);
+
+        Ok(Self { bytes, id3v2_len })
+    }
+
+    /// Build a header from an in-memory buffer
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match id3v2_tag_len(bytes) {
+            Some(len) => Self {
+                bytes: bytes.get(len as usize..).unwrap_or_default().to_vec(),
+                id3v2_len: Some(len),
+            },
+            None => Self { bytes: bytes.to_vec(), id3v2_len: None },
+        }
+    }
+}
+
+/// Check whether `header` carries the signature of `filetype`
+pub fn matches(filetype: &SupportedFiletype, header: &Header) -> bool {
+    let bytes = header.bytes.as_slice();
+    match filetype {
+        SupportedFiletype::Flac => bytes.starts_with(b"fLaC"),
+        // An audiobook brand is authoritative, generic brands defer to the extension
+        SupportedFiletype::M4a => mp4_brands(bytes)
+            .map(|brands| {
+                !brands.iter().any(|b| MP4_AUDIOBOOK_BRANDS.contains(b) || MP4_VIDEO_BRANDS.contains(b))
+                    && brands.iter().any(|b| MP4_AUDIO_BRANDS.contains(b) || MP4_GENERIC_BRANDS.contains(b))
+            })
+            .unwrap_or(false),
+        SupportedFiletype::M4b => mp4_brands(bytes)
+            .map(|brands| {
+                brands.iter().any(|b| MP4_AUDIOBOOK_BRANDS.contains(b))
+                    || (!brands.iter().any(|b| MP4_AUDIO_BRANDS.contains(b) || MP4_VIDEO_BRANDS.contains(b)) && brands.iter().any(|b| MP4_GENERIC_BRANDS.contains(b)))
+            })
+            .unwrap_or(false),
+        SupportedFiletype::Mp3 => {
+            // Encoders often pad past the declared end of an ID3v2 tag
+            let start = match header.id3v2_len {
+                Some(_) => bytes.iter().position(|b| *b != 0).unwrap_or(0),
+                None => 0,
+            };
+            is_mpeg_audio_frame(&bytes[start..])
+        }
+        SupportedFiletype::Ogg => identify_stream(bytes) == Some(OggCodec::Vorbis),
+        SupportedFiletype::Opus => identify_stream(bytes) == Some(OggCodec::Opus),
+        SupportedFiletype::OggFlac => identify_stream(bytes) == Some(OggCodec::Flac),
+        SupportedFiletype::Wav => ChunkForm::identify(bytes) == Some(ChunkForm::Wave),
+        SupportedFiletype::Aiff => matches!(ChunkForm::identify(bytes), Some(ChunkForm::Aiff | ChunkForm::Aifc)),
+        SupportedFiletype::Epub => is_epub(bytes),
+        SupportedFiletype::Mkv => doc_type(bytes).as_deref() == Some("matroska"),
+        SupportedFiletype::Webm => doc_type(bytes).as_deref() == Some("webm"),
+        // Like M4b, generic brands defer to the extension
+        SupportedFiletype::Mp4 => mp4_brands(bytes)
+            .map(|brands| {
+                brands.iter().any(|b| MP4_VIDEO_BRANDS.contains(b))
+                    || (!brands.iter().any(|b| MP4_AUDIO_BRANDS.contains(b) || MP4_AUDIOBOOK_BRANDS.contains(b))
+                        && brands.iter().any(|b| MP4_GENERIC_BRANDS.contains(b)))
+            })
+            .unwrap_or(false),
+    }
+}
+
+/// Check for an MPEG-1/2/2.5 Layer III frame header
+pub fn is_mpeg_audio_frame(bytes: &[u8]) -> bool {
+    if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] & 0xE0 != 0xE0 {
+        return false;
+    }
+
+    let version = (bytes[1] >> 3) & 0x03;
+    let layer = (bytes[1] >> 1) & 0x03;
+    let bitrate = bytes[2] >> 4;
+    let sample_rate = (bytes[2] >> 2) & 0x03;
+
+    version != 0b01 && layer == 0b01 && bitrate != 0x0F && sample_rate != 0x03
+}
+
+/// Returns the major brand followed by the compatible brands of an MP4 `ftyp` box
+pub fn mp4_brands(bytes: &[u8]) -> Option<Vec<&[u8; 4]>> {
+    if bytes.len() < 12 || &bytes[4..8] != b"ftyp" {
+        return None;
+    }
+
+    let box_len = u32::from_be_bytes(bytes[0..4].try_into().ok()?) as usize;
+    let end = box_len.min(bytes.len());
+
+    let mut brands = vec![bytes[8..12].try_into().ok()?];
+    // Skip the 4 byte minor version that follows the major brand
+    let mut offset = 16;
+    while offset + 4 <= end {
+        brands.push(bytes[offset..offset + 4].try_into().ok()?);
+        offset += 4;
+    }
+
+    Some(brands)
+}
+
+/// Size of an ID3v2 tag (header, body and optional footer) at the start of `bytes`
+pub fn id3v2_tag_len(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() < 10 || &bytes[0..3] != b"ID3" {
+        return None;
+    }
+
+    let size = synchsafe_u32(&bytes[6..10])? as u64;
+    let footer = if bytes[5] & 0x10 != 0 { 10 } else { 0 };
+    Some(10 + size + footer)
+}
+
+/// Decode a 28 bit synchsafe integer as used by ID3v2
+pub fn synchsafe_u32(bytes: &[u8]) -> Option<u32> {
+    if bytes.len() < 4 || bytes[..4].iter().any(|b| b & 0x80 != 0) {
+        return None;
+    }
+    Some(bytes[..4].iter().fold(0u32, |acc, b| (acc << 7) | *b as u32))
+}
+
+/// Fill as much of `buf` as the reader allows, returning the number of bytes read
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flac_signature() {
+        let header = Header::from_bytes(b"fLaC\0\0\0\x22");
+        assert!(matches(&SupportedFiletype::Flac, &header));
+        assert!(!matches(&SupportedFiletype::M4a, &header));
+        assert!(!matches(&SupportedFiletype::Mp3, &header));
+    }
+
+    #[test]
+    fn test_mp3_signature() {
+        assert!(matches(&SupportedFiletype::Mp3, &Header::from_bytes(&[0xFF, 0xFB, 0x90, 0x64])));
+
+        let mut bytes = b"ID3\x03\x00\x00\x00\x00\x00\x02\x00\x00".to_vec();
+        bytes.extend_from_slice(&[0, 0, 0xFF, 0xF3, 0x84, 0x64]);
+        assert!(matches(&SupportedFiletype::Mp3, &Header::from_bytes(&bytes)));
+
+        // ADTS AAC shares the sync word but uses layer 0
+        assert!(!matches(&SupportedFiletype::Mp3, &Header::from_bytes(&[0xFF, 0xF1, 0x50, 0x80])));
+    }
+
+    #[test]
+    fn test_flac_signature_after_id3v2() {
+        let mut bytes = b"ID3\x03\x00\x00\x00\x00\x00\x04".to_vec();
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(b"fLaC");
+
+        let header = Header::from_bytes(&bytes);
+        assert_eq!(header.id3v2_len, Some(14));
+        assert!(matches(&SupportedFiletype::Flac, &header));
+    }
+
+    #[test]
+    fn test_m4a_brands() {
+        let mut bytes = vec![0, 0, 0, 0x1c];
+        bytes.extend_from_slice(b"ftypM4A \0\0\0\0M4A mp42isom");
+
+        let brands = mp4_brands(&bytes).unwrap();
+        assert_eq!(brands, vec![b"M4A ", b"M4A ", b"mp42", b"isom"]);
+        assert!(matches(&SupportedFiletype::M4a, &Header::from_bytes(&bytes)));
+        assert!(!matches(&SupportedFiletype::M4b, &Header::from_bytes(&bytes)));
+    }
+
+    #[test]
+    fn test_m4b_brands() {
+        let mut bytes = vec![0, 0, 0, 0x18];
+        bytes.extend_from_slice(b"ftypM4B \0\0\0\0M4B isom");
+
+        assert!(matches(&SupportedFiletype::M4b, &Header::from_bytes(&bytes)));
+        assert!(!matches(&SupportedFiletype::M4a, &Header::from_bytes(&bytes)));
+    }
+
+    #[test]
+    fn test_video_signatures() {
+        let mut bytes = vec![0, 0, 0, 0x1c];
+        bytes.extend_from_slice(b"ftypM4V \0\0\0\0M4V M4A isom");
+        let header = Header::from_bytes(&bytes);
+        assert!(matches(&SupportedFiletype::Mp4, &header));
+        assert!(!matches(&SupportedFiletype::M4a, &header));
+        assert!(!matches(&SupportedFiletype::M4b, &header));
+
+        let mkv = Header::from_bytes(&crate::metadata::matroska::matroska("matroska", "", &[]));
+        assert!(matches(&SupportedFiletype::Mkv, &mkv));
+        assert!(!matches(&SupportedFiletype::Webm, &mkv));
+    }
+
+    #[test]
+    fn test_unknown_content() {
+        let header = Header::from_bytes(b"test flac content");
+        assert!(!matches(&SupportedFiletype::Flac, &header));
+        assert!(!matches(&SupportedFiletype::M4a, &header));
+        assert!(!matches(&SupportedFiletype::Mp3, &header));
+    }
+
+    #[test]
+    fn test_synchsafe() {
+        assert_eq!(synchsafe_u32(&[0x00, 0x00, 0x02, 0x01]), Some(257));
+        assert_eq!(synchsafe_u32(&[0x80, 0x00, 0x00, 0x00]), None);
+    }
+}
@@ -0,0 +1,387 @@
+//! Path templates that lay out normalized files
+//!
+//! A template like `{albumartist|artist}/[{year} - ]{album}/[{track:02}_]{title}`
+//! is rendered from the fields of a file into the directories and stem of
+//! its normalized path.
+//!
+//! - `{field}` inserts a field, empty when the file has no value for it
+//! - `{field|other|'text'}` tries each alternative in turn, quoted ones are literal text
+//! - `{field:02}` zero pads a number to two digits
+//! - `[...]` is a conditional section, dropped unless every field in it has a value
+//! - `/` starts a new directory, directories that render empty are dropped
+//! - `{{`, `}}`, `[[` and `]]` are literal braces and brackets
+//!
+//! `{disc_prefix}` and `{disc_folder}` hold the disc number of releases with
+//! more than one disc, whichever the `DiscLayout` picks, and are missing for
+//! single disc releases so those stay unprefixed.
+//!
+//! `{episode_code}` is how an episode is usually named: `S01E02`,
+//! `S01E01-E02` for a multi-episode file, the air date of a daily show or
+//! the absolute number of an anime episode. Daily shows use their year as
+//! `{season}`, absolutely numbered ones have none.
+//!
+//! `{movie}`, `{edition}` and `{part}` are parsed from a movie's file name,
+//! or its title tag when the name has no year, which also fills in `{year}`
+//! for movies without a date tag.
+//!
+//! `{book}` is the title of an audiobook, shared by all of its parts, and
+//! `{series}`, `{series_index}` and `{narrator}` may come from its folder
+//! name or sidecar files as well as its tags, see `audiobook::fill_book_tags`.
+//!
+//! `{release_artist}` is the artist an album is filed under: the folder for
+//! compilations, or the artist every track of the album agrees on, see
+//! `music::Compilations`.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::files::SupportedMediaType;
+use crate::{Error, Result};
+
+pub const DEFAULT_MUSIC_TEMPLATE: &str =
+    "{release_artist}/{album}/[Disc {disc_folder}/][{disc_prefix}-][{track:02}_]{title|filename}";
+pub const DEFAULT_AUDIOBOOK_TEMPLATE: &str =
+    "{author}/[{series}/][{series_index}_]{book}/[Disc {disc_folder}/][{disc_prefix}-][{track:02}_]{title|filename}";
+pub const DEFAULT_EBOOK_TEMPLATE: &str = "{author}/{series}/[{series_index}_]{title|filename}";
+pub const DEFAULT_TVSHOW_TEMPLATE: &str = "{show}/[Season {season:02}]/[{episode_code}_]{episode_title|title|show|filename}";
+pub const DEFAULT_MOVIE_TEMPLATE: &str = "{movie}[ ({year})]/{movie}[ ({year})][ {{edition-{edition}}}][ - part{part}]";
+
+/// The fields a template may use
+pub const TEMPLATE_FIELDS: &[&str] = &[
+    "artist",
+    "albumartist",
+    "release_artist",
+    "author",
+    "album",
+    "title",
+    "subtitle",
+    "book",
+    "narrator",
+    "track",
+    "tracktotal",
+    "disc",
+    "disctotal",
+    "disc_prefix",
+    "disc_folder",
+    "year",
+    "date",
+    "genre",
+    "series",
+    "series_index",
+    "show",
+    "season",
+    "episode",
+    "episode_end",
+    "episode_code",
+    "air_date",
+    "episode_title",
+    "movie",
+    "edition",
+    "part",
+    "filename",
+];
+
+/// A parsed path template
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(String),
+    Separator,
+    Placeholder(Placeholder),
+    Conditional(Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Placeholder {
+    alternatives: Vec<Alternative>,
+    /// Zero padded width for numbers
+    width: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Alternative {
+    Field(String),
+    Literal(String),
+}
+
+impl Template {
+    /// Parse a template, rejecting unknown fields and unbalanced braces or brackets
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut chars = template.chars().peekable();
+        let nodes = parse_nodes(&mut chars, false).map_err(|e| Error::InvalidPattern(format!("{} in '{}'", e, template)))?;
+        Ok(Self { source: template.to_string(), nodes })
+    }
+
+    /// The template as written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Render the template into path components, looking fields up with `field`
+    ///
+    /// Components are trimmed and empty ones dropped, so the last component
+    /// is the file stem.
+    pub fn render(&self, field: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut components = vec![String::new()];
+        render_nodes(&self.nodes, &field, &mut components);
+        components.into_iter().map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect()
+    }
+}
+
+/// How the tracks of a release with several discs are kept apart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiscLayout {
+    /// `{disc_prefix}` is set, for names like `2-05_Title`
+    Prefix,
+    /// `{disc_folder}` is set, for a `Disc 2` folder per disc
+    Folder,
+}
+
+impl DiscLayout {
+    pub const NAMES: &'static [&'static str] = &["prefix", "folder"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "prefix" => Some(Self::Prefix),
+            "folder" => Some(Self::Folder),
+            _ => None,
+        }
+    }
+}
+
+/// The template used for each media type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Templates {
+    pub music: Template,
+    pub audiobook: Template,
+    pub ebook: Template,
+    pub tvshow: Template,
+    pub movie: Template,
+}
+
+impl Templates {
+    pub fn get(&self, mediatype: &SupportedMediaType) -> &Template {
+        match mediatype {
+            SupportedMediaType::Music => &self.music,
+            SupportedMediaType::Audiobook => &self.audiobook,
+            SupportedMediaType::Ebook => &self.ebook,
+            SupportedMediaType::TVShow => &self.tvshow,
+            SupportedMediaType::Movie => &self.movie,
+        }
+    }
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        let parse = |template| Template::parse(template).expect("default templates are valid");
+        Self {
+            music: parse(DEFAULT_MUSIC_TEMPLATE),
+            audiobook: parse(DEFAULT_AUDIOBOOK_TEMPLATE),
+            ebook: parse(DEFAULT_EBOOK_TEMPLATE),
+            tvshow: parse(DEFAULT_TVSHOW_TEMPLATE),
+            movie: parse(DEFAULT_MOVIE_TEMPLATE),
+        }
+    }
+}
+
+/// Render nodes onto the end of `components`, returning false if a field in them had no value
+fn render_nodes(nodes: &[Node], field: &impl Fn(&str) -> Option<String>, components: &mut Vec<String>) -> bool {
+    let mut complete = true;
+    for node in nodes {
+        match node {
+            Node::Literal(text) => push(components, text),
+            Node::Separator => components.push(String::new()),
+            Node::Placeholder(placeholder) => match placeholder.resolve(field) {
+                Some(value) => push(components, &value),
+                None => complete = false,
+            },
+            Node::Conditional(children) => {
+                let mut section = vec![String::new()];
+                if render_nodes(children, field, &mut section) {
+                    let mut section = section.into_iter();
+                    push(components, &section.next().unwrap_or_default());
+                    components.extend(section);
+                }
+            }
+        }
+    }
+    complete
+}
+
+fn push(components: &mut [String], text: &str) {
+    if let Some(last) = components.last_mut() {
+        last.push_str(text);
+    }
+}
+
+impl Placeholder {
+    fn resolve(&self, field: &impl Fn(&str) -> Option<String>) -> Option<String> {
+        let value = self.alternatives.iter().find_map(|alternative| match alternative {
+            Alternative::Field(name) => field(name).filter(|v| !v.trim().is_empty()),
+            Alternative::Literal(text) => Some(text.clone()),
+        })?;
+
+        match self.width {
+            Some(width) if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) => {
+                Some(format!("{:0>width$}", value, width = width))
+            }
+            _ => Some(value),
+        }
+    }
+}
+
+fn parse_nodes(chars: &mut Peekable<Chars>, conditional: bool) -> std::result::Result<Vec<Node>, String> {
+    let mut nodes = Vec::new();
+    let mut literal = String::new();
+    let flush = |literal: &mut String, nodes: &mut Vec<Node>| {
+        if !literal.is_empty() {
+            nodes.push(Node::Literal(std::mem::take(literal)));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' | '[' | ']' if chars.peek() == Some(&c) => {
+                chars.next();
+                literal.push(c);
+            }
+            '{' => {
+                flush(&mut literal, &mut nodes);
+                nodes.push(Node::Placeholder(parse_placeholder(chars)?));
+            }
+            '}' => return Err("unmatched '}'".to_string()),
+            '[' => {
+                flush(&mut literal, &mut nodes);
+                let children = parse_nodes(chars, true)?;
+                if !children.iter().any(has_placeholder) {
+                    return Err("conditional section without a field".to_string());
+                }
+                nodes.push(Node::Conditional(children));
+            }
+            ']' if conditional => {
+                flush(&mut literal, &mut nodes);
+                return Ok(nodes);
+            }
+            ']' => return Err("unmatched ']'".to_string()),
+            '/' => {
+                flush(&mut literal, &mut nodes);
+                nodes.push(Node::Separator);
+            }
+            c => literal.push(c),
+        }
+    }
+
+    if conditional {
+        return Err("unclosed '['".to_string());
+    }
+    flush(&mut literal, &mut nodes);
+    Ok(nodes)
+}
+
+fn has_placeholder(node: &Node) -> bool {
+    match node {
+        Node::Placeholder(_) => true,
+        Node::Conditional(children) => children.iter().any(has_placeholder),
+        _ => false,
+    }
+}
+
+/// Parse the inside of a `{...}`, up to and including the closing brace
+fn parse_placeholder(chars: &mut Peekable<Chars>) -> std::result::Result<Placeholder, String> {
+    // Split on `|` and the format `:`, except inside quoted literals
+    let mut parts = vec![String::new()];
+    let mut spec = None;
+    let mut quoted = false;
+    loop {
+        let c = chars.next().ok_or("unclosed '{'")?;
+        match c {
+            '\'' => {
+                quoted = !quoted;
+                parts.last_mut().unwrap().push(c);
+            }
+            '}' if !quoted => break,
+            '|' if !quoted && spec.is_none() => parts.push(String::new()),
+            ':' if !quoted && spec.is_none() => spec = Some(String::new()),
+            c => match &mut spec {
+                Some(spec) => spec.push(c),
+                None => parts.last_mut().unwrap().push(c),
+            },
+        }
+    }
+
+    let mut alternatives = Vec::new();
+    for part in parts {
+        let part = part.trim();
+        if let Some(text) = part.strip_prefix('\'').and_then(|p| p.strip_suffix('\'')) {
+            alternatives.push(Alternative::Literal(text.to_string()));
+        } else if TEMPLATE_FIELDS.contains(&part) {
+            alternatives.push(Alternative::Field(part.to_string()));
+        } else if part.is_empty() {
+            return Err("empty field".to_string());
+        } else {
+            return Err(format!("unknown field '{}', expected one of {}", part, TEMPLATE_FIELDS.join(", ")));
+        }
+    }
+
+    let width = match spec.as_deref().map(str::trim) {
+        None => None,
+        Some(spec) => Some(spec.parse::<usize>().ok().filter(|w| *w > 0).ok_or(format!("invalid padding ':{}'", spec))?),
+    };
+    Ok(Placeholder { alternatives, width })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, fields: &[(&str, &str)]) -> Vec<String> {
+        let lookup = |name: &str| fields.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string());
+        Template::parse(template).unwrap().render(lookup)
+    }
+
+    #[test]
+    fn test_render_fields_and_padding() {
+        let fields = [("albumartist", "Pink Floyd"), ("year", "1977"), ("album", "Animals"), ("disc", "1"), ("track", "2"), ("title", "Dogs")];
+        assert_eq!(
+            render("{albumartist}/{year} - {album}/{disc}-{track:02} {title}", &fields),
+            vec!["Pink Floyd", "1977 - Animals", "1-02 Dogs"]
+        );
+    }
+
+    #[test]
+    fn test_conditional_sections() {
+        let template = "{artist}/[{year} - ]{album}/[{disc}-][{track:02}_]{title}";
+        assert_eq!(render(template, &[("artist", "A"), ("album", "B"), ("title", "T")]), vec!["A", "B", "T"]);
+        assert_eq!(render(template, &[("artist", "A"), ("album", "B"), ("track", "7"), ("title", "T")]), vec!["A", "B", "07_T"]);
+
+        // A section can hold whole directories
+        assert_eq!(render("{author}/[{series}/]{title}", &[("author", "A"), ("title", "T")]), vec!["A", "T"]);
+        assert_eq!(render("{author}/[{series}/]{title}", &[("author", "A"), ("series", "S"), ("title", "T")]), vec!["A", "S", "T"]);
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        let template = "{albumartist|artist|'Unknown Artist'}/{title|filename}";
+        assert_eq!(render(template, &[("artist", "A"), ("filename", "01 track")]), vec!["A", "01 track"]);
+        assert_eq!(render(template, &[("albumartist", " "), ("title", "T")]), vec!["Unknown Artist", "T"]);
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(render("{title} {{edition}} [[x]]", &[("title", "T")]), vec!["T {edition} [x]"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for template in ["{titel}", "{title", "title}", "[{title}", "{title}]", "[text]", "{track:x}", "{}", "{a|}"] {
+            assert!(Template::parse(template).is_err(), "{}", template);
+        }
+        let error = Template::parse("{albumartst}").unwrap_err().to_string();
+        assert!(error.contains("unknown field 'albumartst'"));
+    }
+}
@@ -0,0 +1,513 @@
+//! TV show and movie detection
+//!
+//! Video containers don't say whether they hold an episode or a film, so a
+//! file is treated as a TV episode when its name carries an episode marker,
+//! like `S01E02`, `1x02`, an air date or fansub style absolute numbering,
+//! and as a movie otherwise.
+//!
+//! Movie release names are split into a title, year, edition and part
+//! number, with the resolution, source and codec tags dropped, so they can
+//! be laid out the way media servers expect, e.g.
+//! `Blade Runner (1982)/Blade Runner (1982) {edition-Final Cut}.mkv`.
+
+use std::path::Path;
+
+use crate::files::SupportedFiletype;
+use crate::metadata::{matroska, mp4, VideoInfo};
+use crate::Result;
+
+/// How an episode is numbered
+#[derive(Debug, Clone, PartialEq)]
+pub enum EpisodeNumber {
+    /// `S01E02`, `1x02` or a range like `S01E01-E02`, season 0 holds the specials
+    Season { season: u32, first: u32, last: u32 },
+    /// The air date of a daily show
+    Date { year: u32, month: u32, day: u32 },
+    /// Anime style numbering that runs on across seasons
+    Absolute(u32),
+}
+
+/// An episode parsed from a release name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Episode {
+    /// Series name, empty if the name starts with the episode marker
+    pub series: String,
+    pub number: EpisodeNumber,
+    /// Episode title following the marker, if any
+    pub title: Option<String>,
+}
+
+impl Episode {
+    /// The season, or the year of a daily show, as media servers file those by year
+    pub fn season(&self) -> Option<u32> {
+        match self.number {
+            EpisodeNumber::Season { season, .. } => Some(season),
+            EpisodeNumber::Date { year, .. } => Some(year),
+            EpisodeNumber::Absolute(_) => None,
+        }
+    }
+
+    /// The episode number, the first one of a multi-episode file
+    pub fn episode(&self) -> Option<u32> {
+        match self.number {
+            EpisodeNumber::Season { first, .. } => Some(first),
+            EpisodeNumber::Date { .. } => None,
+            EpisodeNumber::Absolute(episode) => Some(episode),
+        }
+    }
+
+    /// The last episode of a multi-episode file
+    pub fn last_episode(&self) -> Option<u32> {
+        match self.number {
+            EpisodeNumber::Season { first, last, .. } if last > first => Some(last),
+            _ => None,
+        }
+    }
+
+    /// The air date of a daily show as `YYYY-MM-DD`
+    pub fn air_date(&self) -> Option<String> {
+        match self.number {
+            EpisodeNumber::Date { year, month, day } => Some(format!("{:04}-{:02}-{:02}", year, month, day)),
+            _ => None,
+        }
+    }
+
+    /// The marker the episode is named with: `S01E02`, `S01E01-E02`, `2024-03-15` or `143`
+    pub fn code(&self) -> String {
+        match self.number {
+            EpisodeNumber::Season { season, first, last } if last > first => format!("S{:02}E{:02}-E{:02}", season, first, last),
+            EpisodeNumber::Season { season, first, .. } => format!("S{:02}E{:02}", season, first),
+            EpisodeNumber::Date { .. } => self.air_date().unwrap_or_default(),
+            EpisodeNumber::Absolute(episode) => format!("{:02}", episode),
+        }
+    }
+}
+
+/// A movie parsed from a release name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub title: String,
+    pub year: Option<u32>,
+    /// A cut or release of the film, e.g. `Director's Cut` or `Remastered`
+    pub edition: Option<String>,
+    /// Which file of a film split over several, from `cd1` or `part2`
+    pub part: Option<u32>,
+}
+
+/// Editions as they're spelled in release names, lower case and without
+/// apostrophes, and how they're written out. Longer spellings come first.
+const EDITIONS: &[(&[&str], &str)] = &[
+    (&["directors", "cut"], "Director's Cut"),
+    (&["final", "cut"], "Final Cut"),
+    (&["extended", "cut"], "Extended Cut"),
+    (&["extended", "edition"], "Extended Edition"),
+    (&["extended"], "Extended"),
+    (&["theatrical", "cut"], "Theatrical Cut"),
+    (&["theatrical"], "Theatrical"),
+    (&["special", "edition"], "Special Edition"),
+    (&["ultimate", "edition"], "Ultimate Edition"),
+    (&["anniversary", "edition"], "Anniversary Edition"),
+    (&["remastered"], "Remastered"),
+    (&["unrated"], "Unrated"),
+    (&["uncut"], "Uncut"),
+    (&["imax"], "IMAX"),
+    (&["criterion"], "Criterion"),
+];
+
+/// Words that number the files of a film split over several
+const PART_PREFIXES: &[&str] = &["cd", "part", "pt", "disc", "disk"];
+
+/// Read the title, duration and streams of a video file
+pub fn read_video_info(path: &Path, filetype: &SupportedFiletype) -> Result<Option<VideoInfo>> {
+    match filetype {
+        SupportedFiletype::Mkv | SupportedFiletype::Webm => matroska::read_video_info(path),
+        SupportedFiletype::Mp4 => mp4::read_video_info(path),
+        _ => Ok(None),
+    }
+}
+
+/// Parse the episode marker from the file name of `path`
+pub fn episode(path: &Path) -> Option<Episode> {
+    parse_episode(path.file_stem()?.to_str()?)
+}
+
+/// Parse a release name like `Show.Name.S01E02.Episode.Title.1080p`
+///
+/// Understands `S01E02`, `1x02`, ranges like `S01E01-E02` or `S01E01E02`,
+/// air dates like `2024.03.15` and fansub style absolute numbering. The
+/// first marker in the name wins.
+pub fn parse_episode(name: &str) -> Option<Episode> {
+    let bytes = name.as_bytes();
+    for start in 0..bytes.len() {
+        // The marker has to start a word, so "Cases01e02" isn't an episode
+        if start > 0 && bytes[start - 1].is_ascii_alphanumeric() {
+            continue;
+        }
+        let marker = season_marker(bytes, start).or_else(|| cross_marker(bytes, start)).or_else(|| air_date(bytes, start));
+        if let Some((number, end)) = marker {
+            return Some(Episode { series: clean(&name[..start]), number, title: episode_title(&name[end..]) });
+        }
+    }
+    absolute_episode(name)
+}
+
+/// Parse `S01E02` at `start`, with an optional range, returning where it ends
+fn season_marker(bytes: &[u8], start: usize) -> Option<(EpisodeNumber, usize)> {
+    if !bytes[start].eq_ignore_ascii_case(&b's') {
+        return None;
+    }
+    let (season, len) = digits(&bytes[start + 1..], 2)?;
+    let e = start + 1 + len;
+    if !bytes.get(e).is_some_and(|b| b.eq_ignore_ascii_case(&b'e')) {
+        return None;
+    }
+    let (first, len) = digits(&bytes[e + 1..], 3)?;
+    let (last, end) = episode_range(bytes, e + 1 + len, first, b'e');
+    if bytes.get(end).is_some_and(u8::is_ascii_alphanumeric) {
+        return None;
+    }
+    Some((EpisodeNumber::Season { season, first, last }, end))
+}
+
+/// Parse `1x02` at `start`, with an optional range, returning where it ends
+fn cross_marker(bytes: &[u8], start: usize) -> Option<(EpisodeNumber, usize)> {
+    let (season, len) = digits(&bytes[start..], 2)?;
+    let x = start + len;
+    if !bytes.get(x).is_some_and(|b| b.eq_ignore_ascii_case(&b'x')) {
+        return None;
+    }
+    let (first, len) = digits(&bytes[x + 1..], 3)?;
+    let (last, end) = episode_range(bytes, x + 1 + len, first, b'x');
+    if bytes.get(end).is_some_and(u8::is_ascii_alphanumeric) {
+        return None;
+    }
+    Some((EpisodeNumber::Season { season, first, last }, end))
+}
+
+/// Extend an episode number over a range like `-E03`, `-03` or `E03`
+///
+/// Returns the last episode and where the range ends. A number that
+/// doesn't count up, like the `720` of `S01E01-720p`, isn't part of it.
+fn episode_range(bytes: &[u8], mut end: usize, first: u32, prefix: u8) -> (u32, usize) {
+    let mut last = first;
+    loop {
+        let mut next = end;
+        if bytes.get(next) == Some(&b'-') {
+            next += 1;
+        }
+        if bytes.get(next).is_some_and(|b| b.eq_ignore_ascii_case(&prefix)) {
+            next += 1;
+        } else if next == end {
+            break;
+        }
+        let Some((episode, len)) = digits(&bytes[next..], 3) else {
+            break;
+        };
+        next += len;
+        if episode <= last || bytes.get(next).is_some_and(u8::is_ascii_alphanumeric) {
+            break;
+        }
+        (last, end) = (episode, next);
+    }
+    (last, end)
+}
+
+/// Parse an air date like `2024.03.15` or `2024-03-15` at `start`, returning where it ends
+fn air_date(bytes: &[u8], start: usize) -> Option<(EpisodeNumber, usize)> {
+    let (year, 4) = digits(&bytes[start..], 4)? else {
+        return None;
+    };
+    let separator = *bytes.get(start + 4).filter(|b| b".-_ ".contains(b))?;
+    let (month, 2) = digits(&bytes[start + 5..], 2)? else {
+        return None;
+    };
+    if bytes.get(start + 7) != Some(&separator) {
+        return None;
+    }
+    let (day, 2) = digits(&bytes[start + 8..], 2)? else {
+        return None;
+    };
+    let end = start + 10;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || bytes.get(end).is_some_and(u8::is_ascii_alphanumeric) {
+        return None;
+    }
+    Some((EpisodeNumber::Date { year, month, day }, end))
+}
+
+/// Parse fansub style absolute numbering like `[Group] Show Name - 143 [1080p]`
+///
+/// A plain `Name - 12` is as likely to be a movie, so only names that start
+/// with a bracketed group tag count.
+fn absolute_episode(name: &str) -> Option<Episode> {
+    let rest = name.strip_prefix('[')?;
+    let rest = &rest[rest.find(']')? + 1..];
+    for (dash, _) in rest.match_indices(" - ") {
+        let bytes = &rest.as_bytes()[dash + 3..];
+        let Some((episode, mut end)) = digits(bytes, 4) else {
+            continue;
+        };
+        // Re-releases carry a version, e.g. `07v2`
+        if bytes.get(end) == Some(&b'v') && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) {
+            end += 2;
+        }
+        if bytes.get(end).is_some_and(u8::is_ascii_alphanumeric) {
+            continue;
+        }
+        return Some(Episode {
+            series: clean(&strip_tags(&rest[..dash])),
+            number: EpisodeNumber::Absolute(episode),
+            title: episode_title(&rest[dash + 3 + end..]),
+        });
+    }
+    None
+}
+
+/// Clean the fragment after an episode marker into a title, if anything but release noise is left
+fn episode_title(fragment: &str) -> Option<String> {
+    let title = strip_noise(&clean(&strip_tags(fragment)));
+    (!title.is_empty()).then_some(title)
+}
+
+/// Parse the movie a file holds, from its file name or else its title tag
+///
+/// Scene releases name the file after the release, while downloads renamed
+/// to something generic often still have the film's title in the tag. The
+/// file name wins when it carries a year, then the title tag when there is
+/// one.
+pub fn movie(path: &Path, title: Option<&str>) -> Option<Movie> {
+    let from_name = parse_movie(path.file_stem()?.to_str()?);
+    if from_name.as_ref().is_some_and(|movie| movie.year.is_some()) {
+        return from_name;
+    }
+    match (title.and_then(parse_movie), from_name) {
+        (Some(mut from_tag), Some(from_name)) => {
+            from_tag.edition = from_tag.edition.or(from_name.edition);
+            from_tag.part = from_tag.part.or(from_name.part);
+            Some(from_tag)
+        }
+        (from_tag, from_name) => from_tag.or(from_name),
+    }
+}
+
+/// Parse a release name like `Blade.Runner.1982.Final.Cut.1080p.BluRay.x264-GROUP`
+///
+/// The title runs up to the year, or without one up to the first edition,
+/// part or release tag, and a name that's nothing but release tags has
+/// none. The year is the last one in the name that isn't its first word,
+/// so `2001.A.Space.Odyssey.1968` keeps its title. Names this lays out,
+/// with a `{edition-...}` marker, parse back the same.
+pub fn parse_movie(name: &str) -> Option<Movie> {
+    // A leading group tag, e.g. `[YTS]`
+    let name = match name.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((_, rest)) => rest,
+        None => name,
+    };
+    let (name, marked_edition) = match name.split_once("{edition-").and_then(|(before, rest)| Some((before, rest.split_once('}')?))) {
+        Some((before, (edition, after))) => (format!("{} {}", before, after), Some(edition.trim().to_string())),
+        None => (name.to_string(), None),
+    };
+
+    let words: Vec<&str> = name
+        .split(['.', '_', ' ', '(', ')', '[', ']'])
+        .filter(|word| !word.is_empty() && *word != "-")
+        .collect();
+    let year = words.iter().enumerate().skip(1).rev().find_map(|(i, word)| parse_year(word).map(|year| (i, year)));
+    let title_end = match year {
+        Some((i, _)) => i,
+        None => (0..words.len())
+            .find(|&i| is_release_noise(words[i]) || (i > 0 && (edition_at(&words[i..]).is_some() || part_at(&words[i..]).is_some())))
+            .unwrap_or(words.len()),
+    };
+
+    let title = words[..title_end].join(" ").trim_matches(|c: char| c == '-' || c.is_whitespace()).to_string();
+    if title.is_empty() {
+        return None;
+    }
+    let rest = &words[title_end..];
+    Some(Movie {
+        title,
+        year: year.map(|(_, year)| year),
+        edition: marked_edition.or_else(|| (0..rest.len()).find_map(|i| edition_at(&rest[i..])).map(str::to_string)),
+        part: (0..rest.len()).find_map(|i| part_at(&rest[i..])),
+    })
+}
+
+/// A plausible release year
+fn parse_year(word: &str) -> Option<u32> {
+    let year = word.parse().ok().filter(|_| word.len() == 4)?;
+    (1880..=2099).contains(&year).then_some(year)
+}
+
+/// The edition spelled by the words at the start of `words`
+fn edition_at(words: &[&str]) -> Option<&'static str> {
+    let normalized: Vec<String> = words.iter().take(2).map(|word| word.to_lowercase().replace(['\'', '’'], "")).collect();
+    EDITIONS
+        .iter()
+        .find(|(spelling, _)| normalized.len() >= spelling.len() && spelling.iter().zip(&normalized).all(|(a, b)| a == b))
+        .map(|(_, edition)| *edition)
+}
+
+/// The part number given by the words at the start of `words`, `cd1` or `part 2`
+fn part_at(words: &[&str]) -> Option<u32> {
+    let word = words[0].to_lowercase();
+    let prefix = PART_PREFIXES.iter().find(|prefix| word.starts_with(*prefix))?;
+    let number = match &word[prefix.len()..] {
+        "" => words.get(1)?,
+        number => number,
+    };
+    number.parse().ok().filter(|n| (1..100).contains(n))
+}
+
+/// Read up to `max` leading ASCII digits
+fn digits(bytes: &[u8], max: usize) -> Option<(u32, usize)> {
+    let len = bytes.iter().take(max).take_while(|b| b.is_ascii_digit()).count();
+    if len == 0 {
+        return None;
+    }
+    let value = std::str::from_utf8(&bytes[..len]).ok()?.parse().ok()?;
+    Some((value, len))
+}
+
+/// Turn dotted or underscored release name fragments into plain words
+pub fn clean(fragment: &str) -> String {
+    fragment
+        .split(['.', '_', ' '])
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| c == '-' || c.is_whitespace())
+        .to_string()
+}
+
+/// Drop bracketed tags like `[1080p]`, `(WEB)` or a `[C0FFEE42]` checksum
+fn strip_tags(fragment: &str) -> String {
+    let mut out = String::new();
+    let mut depth = 0usize;
+    for c in fragment.chars() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = depth.saturating_sub(1),
+            c if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Cut cleaned text at the first word describing the release rather than its content
+pub fn strip_noise(text: &str) -> String {
+    let words: Vec<&str> = text.split(' ').collect();
+    let end = words.iter().position(|word| is_release_noise(word)).unwrap_or(words.len());
+    words[..end].join(" ").trim_matches(|c: char| c == '-' || c.is_whitespace()).to_string()
+}
+
+/// Check whether a word is a resolution, source, codec or release flag, e.g. `1080p`, `WEB-DL` or `x264-GROUP`
+fn is_release_noise(word: &str) -> bool {
+    // Words that are just as likely in a title only count in the upper case scene releases use
+    const FLAGS: &[&str] = &["REPACK", "PROPER", "INTERNAL", "LIMITED", "UNCUT", "EXTENDED"];
+    const NOISE: &[&str] = &[
+        "4k", "uhd", "hdr", "hdr10", "dv", "web", "webrip", "webdl", "bluray", "blu", "bdrip", "brrip", "dvdrip", "hdtv",
+        "hdrip", "remux", "amzn", "nf", "dsnp", "hmax", "atvp", "x264", "x265", "h264", "h265", "hevc", "avc", "xvid",
+        "aac", "ac3", "eac3", "ddp", "ddp5", "dts", "flac", "opus", "10bit", "8bit",
+    ];
+
+    if FLAGS.contains(&word) {
+        return true;
+    }
+    let word = word.split('-').next().unwrap_or_default().to_lowercase();
+    let resolution = word.strip_suffix(['p', 'i']).is_some_and(|n| matches!(n.len(), 3 | 4) && n.chars().all(|c| c.is_ascii_digit()));
+    resolution || NOISE.contains(&word.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_episode() {
+        let episode = parse_episode("The.Expanse.S02E05.Home.1080p.WEB-DL.x264-GROUP").unwrap();
+        assert_eq!(episode.series, "The Expanse");
+        assert_eq!(episode.number, EpisodeNumber::Season { season: 2, first: 5, last: 5 });
+        assert_eq!(episode.title.as_deref(), Some("Home"));
+        assert_eq!(episode.code(), "S02E05");
+
+        let episode = parse_episode("s1e10").unwrap();
+        assert_eq!(episode.series, "");
+        assert_eq!((episode.season(), episode.episode()), (Some(1), Some(10)));
+        assert_eq!(episode.title, None);
+
+        assert_eq!(parse_episode("Show - S01E02 - Pilot").unwrap().series, "Show");
+        assert_eq!(parse_episode("Cases01e02"), None);
+        assert_eq!(parse_episode("Sintel.2010.1080p"), None);
+        assert_eq!(parse_episode("Big.Buck.Bunny.1920x1080"), None);
+    }
+
+    #[test]
+    fn test_parse_episode_variants() {
+        let code = |name: &str| parse_episode(name).map(|e| e.code());
+        assert_eq!(code("Futurama.4x02.Leela's.Homeworld").as_deref(), Some("S04E02"));
+        assert_eq!(code("Doctor.Who.S00E01.A.Christmas.Carol").as_deref(), Some("S00E01"));
+        assert_eq!(code("Friends.S01E16-E17.720p").as_deref(), Some("S01E16-E17"));
+        assert_eq!(code("Friends.S01E16E17").as_deref(), Some("S01E16-E17"));
+        assert_eq!(code("Friends.1x16-17").as_deref(), Some("S01E16-E17"));
+        // Counting down or into a resolution isn't a range
+        assert_eq!(code("Show.S01E05-720p").as_deref(), Some("S01E05"));
+
+        let daily = parse_episode("The.Daily.Show.2024.03.15.Guest.Name.720p.WEB").unwrap();
+        assert_eq!(daily.series, "The Daily Show");
+        assert_eq!((daily.season(), daily.episode()), (Some(2024), None));
+        assert_eq!(daily.code(), "2024-03-15");
+        assert_eq!(daily.title.as_deref(), Some("Guest Name"));
+        assert_eq!(parse_episode("Show 2024-13-15"), None);
+
+        let anime = parse_episode("[SubsPlease] Jujutsu Kaisen - 47v2 (1080p) [C0FFEE42]").unwrap();
+        assert_eq!(anime.series, "Jujutsu Kaisen");
+        assert_eq!(anime.number, EpisodeNumber::Absolute(47));
+        assert_eq!((anime.season(), anime.title), (None, None));
+        assert_eq!(parse_episode("Blade Runner - 2049"), None);
+    }
+
+    #[test]
+    fn test_parse_movie() {
+        let movie = parse_movie("Blade.Runner.1982.The.Final.Cut.1080p.BluRay.x264-GROUP").unwrap();
+        assert_eq!(movie.title, "Blade Runner");
+        assert_eq!(movie.year, Some(1982));
+        assert_eq!(movie.edition.as_deref(), Some("Final Cut"));
+        assert_eq!(movie.part, None);
+
+        let movie = parse_movie("2001.A.Space.Odyssey.1968.REMASTERED.2160p").unwrap();
+        assert_eq!((movie.title.as_str(), movie.year), ("2001 A Space Odyssey", Some(1968)));
+        assert_eq!(movie.edition.as_deref(), Some("Remastered"));
+
+        let movie = parse_movie("[YTS] Alien (1979) Director's Cut [720p]").unwrap();
+        assert_eq!((movie.title.as_str(), movie.year), ("Alien", Some(1979)));
+        assert_eq!(movie.edition.as_deref(), Some("Director's Cut"));
+
+        let movie = parse_movie("The Godfather Part II 1974 cd2").unwrap();
+        assert_eq!((movie.title.as_str(), movie.part), ("The Godfather Part II", Some(2)));
+
+        let movie = parse_movie("Kill.Bill.EXTENDED.part1.DVDRip").unwrap();
+        assert_eq!((movie.title.as_str(), movie.year), ("Kill Bill", None));
+        assert_eq!((movie.edition.as_deref(), movie.part), (Some("Extended"), Some(1)));
+
+        assert_eq!(parse_movie("1917").unwrap().year, None);
+        assert_eq!(parse_movie("1080p.x264"), None);
+
+        // A laid out name parses back to the same movie
+        let movie = parse_movie("Blade Runner (1982) {edition-Final Cut} - part1").unwrap();
+        assert_eq!(movie, Movie { title: "Blade Runner".to_string(), year: Some(1982), edition: Some("Final Cut".to_string()), part: Some(1) });
+    }
+
+    #[test]
+    fn test_movie_falls_back_to_title_tag() {
+        assert_eq!(movie(Path::new("download.cd2.mkv"), Some("Sintel")).unwrap(), Movie {
+            title: "Sintel".to_string(),
+            year: None,
+            edition: None,
+            part: Some(2),
+        });
+        let from_name = movie(Path::new("Sintel.2010.1080p.mkv"), Some("Sintel.2010.1080p.WEB")).unwrap();
+        assert_eq!((from_name.title.as_str(), from_name.year), ("Sintel", Some(2010)));
+        assert_eq!(movie(Path::new("Sintel.mkv"), None).unwrap().title, "Sintel");
+    }
+}